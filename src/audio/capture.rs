@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{unbounded, Sender};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+
+/// Seconds of audio between updates of the WAV header, so a killed session
+/// still leaves a readable file
+const FLUSH_INTERVAL_SECONDS: u32 = 5;
+
+/// Writes the input audio to a WAV file on a background thread
+///
+/// `write` only queues the samples, so disk access never holds up the
+/// processing of the stream. The file has the input's sample rate and
+/// channels as 32-bit float. The processor captures each take to its own
+/// file, starting with the take's first sample, which is time 0 of its MIDI.
+pub struct AudioCapture {
+    path: PathBuf,
+    tx: Option<Sender<Vec<f32>>>,
+    writer: Option<JoinHandle<Result<u32>>>,
+}
+
+impl AudioCapture {
+    /// Create the WAV file and start its writer thread
+    pub fn create(path: &Path, sample_rate: u32, channels: u16) -> Result<Self> {
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut wav = WavWriter::create(path, spec)
+            .with_context(|| format!("Failed to create audio capture {:?}", path))?;
+
+        let (tx, rx) = unbounded::<Vec<f32>>();
+        let flush_samples = sample_rate as u64 * channels as u64 * FLUSH_INTERVAL_SECONDS as u64;
+        let writer = std::thread::Builder::new()
+            .name("audio-capture".to_string())
+            .spawn(move || {
+                let mut unflushed = 0u64;
+                for samples in rx {
+                    for &sample in &samples {
+                        wav.write_sample(sample)?;
+                    }
+                    unflushed += samples.len() as u64;
+                    if unflushed >= flush_samples {
+                        wav.flush()?;
+                        unflushed = 0;
+                    }
+                }
+                let frames = wav.duration();
+                wav.finalize()?;
+                Ok(frames)
+            })
+            .context("Failed to start the audio capture thread")?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue interleaved samples for writing
+    pub fn write(&self, samples: &[f32]) {
+        if let Some(tx) = &self.tx {
+            if tx.send(samples.to_vec()).is_err() {
+                debug!("Audio capture writer has stopped");
+            }
+        }
+    }
+
+    /// Write the queued samples and close the file, returning its length in frames
+    pub fn finish(mut self) -> Result<u32> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<u32> {
+        // Closing the channel ends the writer once the queue is written
+        self.tx = None;
+        let Some(writer) = self.writer.take() else {
+            return Ok(0);
+        };
+        match writer.join() {
+            Ok(result) => {
+                result.with_context(|| format!("Failed to write audio capture {:?}", self.path))
+            }
+            Err(_) => anyhow::bail!("Audio capture thread panicked"),
+        }
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            warn!("{:#}", err);
+        }
+    }
+}
+
+/// Path of the audio captured along with a recording, e.g. take.wav
+pub fn audio_capture_path(path: &Path) -> PathBuf {
+    path.with_extension("wav")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_capture() {
+        let path = std::env::temp_dir().join("test_audio_capture.wav");
+        let capture = AudioCapture::create(&path, 48_000, 2).unwrap();
+        capture.write(&[0.5, -0.5, 0.25, -0.25]);
+        capture.write(&[1.0, -1.0]);
+        assert_eq!(capture.finish().unwrap(), 3);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48_000);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.5, -0.5, 0.25, -0.25, 1.0, -1.0]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_audio_capture_path() {
+        assert_eq!(
+            audio_capture_path(Path::new("takes/solo.mid")),
+            PathBuf::from("takes/solo.wav")
+        );
+    }
+}
@@ -0,0 +1,75 @@
+/// Position in the input stream, counted in received samples
+///
+/// Detections are stamped with the time of the audio they were made from
+/// rather than the time they were processed, which lags behind by the
+/// channel and buffering delay and jitters with it.
+#[derive(Debug, Clone, Copy)]
+pub struct SampleClock {
+    sample_rate: u32,
+    channels: u16,
+    /// Interleaved samples received so far
+    samples: u64,
+}
+
+impl SampleClock {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            channels: channels.max(1),
+            samples: 0,
+        }
+    }
+
+    /// Count interleaved samples received from the stream, including any that are dropped
+    pub fn advance(&mut self, samples: usize) {
+        self.samples += samples as u64;
+    }
+
+    /// Interleaved samples received so far
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Microseconds from the start of the stream to an interleaved sample position
+    pub fn time_at(&self, sample: u64) -> u64 {
+        sample / self.channels as u64 * 1_000_000 / self.sample_rate as u64
+    }
+
+    /// Time of the sound an analysis window describes: its middle
+    ///
+    /// `end` is the position after the window's last sample and `len` its
+    /// length in interleaved samples. A detection reflects the whole window,
+    /// so stamping it with the window's end would make every note late by
+    /// half the window.
+    pub fn window_time(&self, end: u64, len: usize) -> u64 {
+        self.time_at(end.saturating_sub(len as u64 / 2))
+    }
+
+    /// Start counting from zero again
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_clock() {
+        let mut clock = SampleClock::new(48_000, 2);
+        clock.advance(960);
+        clock.advance(960);
+        // 1920 interleaved stereo samples are 960 frames, 20ms at 48kHz
+        assert_eq!(clock.samples(), 1920);
+        assert_eq!(clock.time_at(clock.samples()), 20_000);
+
+        // A 2048-frame window ending a second in is stamped at its middle
+        let mono = SampleClock::new(44_100, 1);
+        assert_eq!(mono.window_time(44_100, 2048), 976_780);
+        assert_eq!(mono.window_time(1000, 2048), 0);
+
+        clock.reset();
+        assert_eq!(clock.samples(), 0);
+    }
+}
@@ -1,12 +1,43 @@
+mod capture;
+mod clock;
+mod pluck;
+mod synth;
+
+pub use capture::{audio_capture_path, AudioCapture};
+pub use clock::SampleClock;
+pub use pluck::Pluck;
+pub use synth::{mix_stereo, read_wav_mono, write_wav, PreviewSynth, SynthVoice};
+
 use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Device, Stream, StreamConfig};
 use crossbeam_channel::Sender;
+use hound::{SampleFormat, WavReader};
 use log::{debug, info};
+use std::path::{Path, PathBuf};
+
+/// Frames per block sent from an audio file, about what a device delivers
+const FILE_BLOCK_FRAMES: usize = 1024;
+
+enum Source {
+    Device {
+        device: Device,
+        config: StreamConfig,
+    },
+    File {
+        path: PathBuf,
+        sample_rate: u32,
+        channels: u16,
+    },
+}
 
 pub struct AudioInput {
-    device: Device,
-    config: StreamConfig,
+    source: Source,
+}
+
+/// A running input stream, which stops when dropped
+pub struct InputStream {
+    _stream: Option<Stream>,
 }
 
 impl AudioInput {
@@ -24,15 +55,51 @@ impl AudioInput {
             .context("Failed to get default input config")?
             .into();
 
-        Ok(Self { device, config })
+        Ok(Self {
+            source: Source::Device { device, config },
+        })
+    }
+
+    /// Read the audio from a WAV file instead of a device, for offline conversion
+    ///
+    /// The file is streamed as fast as it is consumed, and the stream's
+    /// channel is closed at the end of the file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let reader = WavReader::open(path)
+            .with_context(|| format!("Failed to open audio file {:?}", path))?;
+        let spec = reader.spec();
+        info!(
+            "Using audio file: {} ({} Hz, {} channels)",
+            path.display(),
+            spec.sample_rate,
+            spec.channels
+        );
+        Ok(Self {
+            source: Source::File {
+                path: path.to_path_buf(),
+                sample_rate: spec.sample_rate,
+                channels: spec.channels,
+            },
+        })
+    }
+
+    /// Whether the audio comes from a device, as it is played
+    pub fn is_live(&self) -> bool {
+        matches!(self.source, Source::Device { .. })
     }
 
     /// Start streaming audio samples to the provided channel
-    pub fn start_stream(&self, tx: Sender<Vec<f32>>) -> Result<Stream> {
-        let config = self.config.clone();
+    pub fn start_stream(&self, tx: Sender<Vec<f32>>) -> Result<InputStream> {
+        let (device, config) = match &self.source {
+            Source::Device { device, config } => (device, config.clone()),
+            Source::File { path, channels, .. } => {
+                stream_file(path, *channels, tx)?;
+                return Ok(InputStream { _stream: None });
+            }
+        };
         debug!("Audio config: {:?}", config);
 
-        let stream = self.device.build_input_stream(
+        let stream = device.build_input_stream(
             &config,
             move |data: &[f32], _: &cpal::InputCallbackInfo| {
                 // Send audio samples through the channel
@@ -49,21 +116,84 @@ impl AudioInput {
 
         stream.play()?;
         info!("Audio stream started");
-        Ok(stream)
+        Ok(InputStream {
+            _stream: Some(stream),
+        })
     }
 
     /// Get the sample rate of the audio input
     pub fn sample_rate(&self) -> u32 {
-        self.config.sample_rate.0
+        match &self.source {
+            Source::Device { config, .. } => config.sample_rate.0,
+            Source::File { sample_rate, .. } => *sample_rate,
+        }
     }
 
     /// Get the number of channels
-    #[allow(dead_code)]
     pub fn channels(&self) -> u16 {
-        self.config.channels
+        match &self.source {
+            Source::Device { config, .. } => config.channels,
+            Source::File { channels, .. } => *channels,
+        }
     }
 }
 
+/// Send a WAV file's samples as float blocks from a background thread
+///
+/// Sending blocks until the receiver catches up, so no audio is dropped;
+/// the thread stops early if the receiver goes away.
+fn stream_file(path: &Path, channels: u16, tx: Sender<Vec<f32>>) -> Result<()> {
+    let samples = wav_samples(path)?;
+    let block = FILE_BLOCK_FRAMES * channels.max(1) as usize;
+    let path = path.to_path_buf();
+
+    std::thread::Builder::new()
+        .name("audio-file".to_string())
+        .spawn(move || {
+            let mut buffer = Vec::with_capacity(block);
+            for sample in samples {
+                match sample {
+                    Ok(sample) => buffer.push(sample),
+                    Err(err) => {
+                        log::error!("Failed to read audio file {:?}: {}", path, err);
+                        break;
+                    }
+                }
+                if buffer.len() == block
+                    && tx
+                        .send(std::mem::replace(&mut buffer, Vec::with_capacity(block)))
+                        .is_err()
+                {
+                    return;
+                }
+            }
+            if !buffer.is_empty() {
+                let _ = tx.send(buffer);
+            }
+            info!("End of audio file");
+        })
+        .context("Failed to start the audio file thread")?;
+    Ok(())
+}
+
+/// Interleaved samples of a WAV file as floats, whatever its sample format
+fn wav_samples(path: &Path) -> Result<Box<dyn Iterator<Item = hound::Result<f32>> + Send>> {
+    let reader =
+        WavReader::open(path).with_context(|| format!("Failed to open audio file {:?}", path))?;
+    let spec = reader.spec();
+    Ok(match spec.sample_format {
+        SampleFormat::Float => Box::new(reader.into_samples::<f32>()),
+        SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+            Box::new(
+                reader
+                    .into_samples::<i32>()
+                    .map(move |sample| sample.map(|s| s as f32 * scale)),
+            )
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,4 +208,38 @@ mod tests {
             assert!(audio.channels() > 0);
         }
     }
+
+    #[test]
+    fn test_audio_file_input() {
+        let path = std::env::temp_dir().join("test_audio_file_input.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 22_050,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..1500 {
+            writer.write_sample(i16::MAX / 2).unwrap();
+            writer.write_sample(i16::MIN).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let input = AudioInput::open(&path).unwrap();
+        assert!(!input.is_live());
+        assert_eq!(input.sample_rate(), 22_050);
+        assert_eq!(input.channels(), 2);
+
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _stream = input.start_stream(tx).unwrap();
+        // Blocks of 1024 frames, then the rest; the channel closes at the end
+        let blocks: Vec<Vec<f32>> = rx.iter().collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].len(), 2048);
+        assert_eq!(blocks[1].len(), 952);
+        assert!((blocks[0][0] - 0.5).abs() < 1e-3);
+        assert_eq!(blocks[0][1], -1.0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
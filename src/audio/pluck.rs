@@ -0,0 +1,74 @@
+/// Feedback of the string; lower values damp it faster
+const PLUCK_DECAY: f32 = 0.996;
+
+/// Plucked string after Karplus-Strong
+///
+/// The delay line is read at a variable rate, so a pitch bend glides the
+/// string instead of retuning it. Used by the preview synthesizer and to
+/// generate guitar notes for the tests.
+pub struct Pluck {
+    line: Vec<f32>,
+    position: f64,
+    /// Delay line samples per output sample at the unbent pitch
+    rate: f64,
+}
+
+impl Pluck {
+    /// Pluck a string at a frequency, exciting it with noise from `seed`
+    pub fn new(frequency: f64, sample_rate: f64, seed: u32) -> Self {
+        let period = (sample_rate / frequency).max(2.0);
+        let mut state = seed.max(1);
+        let line = (0..period.ceil() as usize)
+            .map(|_| {
+                // Xorshift noise, so renders are repeatable
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 * 2.0 - 1.0
+            })
+            .collect::<Vec<_>>();
+        let rate = line.len() as f64 / period;
+        Self {
+            line,
+            position: 0.0,
+            rate,
+        }
+    }
+
+    /// Next sample, with the pitch scaled by `ratio` (1.0 = unbent)
+    pub fn next(&mut self, ratio: f64) -> f32 {
+        let len = self.line.len();
+        let index = self.position as usize;
+        let frac = (self.position - index as f64) as f32;
+        let out = self.line[index] * (1.0 - frac) + self.line[(index + 1) % len] * frac;
+
+        // Average every sample the read passes, which damps the string
+        let next = self.position + self.rate * ratio;
+        for passed in index..next as usize {
+            let (a, b) = (passed % len, (passed + 1) % len);
+            self.line[a] = (self.line[a] + self.line[b]) * 0.5 * PLUCK_DECAY;
+        }
+        self.position = next % len as f64;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::PitchDetector;
+
+    #[test]
+    fn test_pluck_pitch() {
+        let mut pluck = Pluck::new(110.0, 44_100.0, 7);
+        let samples: Vec<f32> = (0..8192).map(|_| pluck.next(1.0)).collect();
+        let pitch = PitchDetector::new(44_100, 4096, 0.15)
+            .detect_pitch(&samples[2048..6144])
+            .unwrap();
+        assert!((pitch - 110.0).abs() < 1.0, "{}", pitch);
+
+        // The string decays
+        let peak = |s: &[f32]| s.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!(peak(&samples[7168..]) < peak(&samples[..1024]));
+    }
+}
@@ -0,0 +1,455 @@
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::f64::consts::TAU;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::Pluck;
+use crate::midi::MidiPlayer;
+
+const ATTACK_SECONDS: f32 = 0.003;
+const RELEASE_SECONDS: f32 = 0.08;
+/// Rendered past the end of the file, so the last notes ring out
+const TAIL_SECONDS: f64 = 0.5;
+const HARMONICS: usize = 8;
+/// Level of the voices in the mix before soft clipping
+const MIX_GAIN: f32 = 0.3;
+
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+const CC_ALL_SOUND_OFF: u8 = 120;
+const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// Sound of the preview synthesizer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SynthVoice {
+    /// Karplus-Strong plucked string
+    #[default]
+    Pluck,
+    /// Decaying harmonics, with a steadier pitch for judging bends
+    Additive,
+}
+
+impl FromStr for SynthVoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "pluck" => Ok(SynthVoice::Pluck),
+            "additive" => Ok(SynthVoice::Additive),
+            _ => anyhow::bail!("Unknown synth voice '{}' (use pluck or additive)", s),
+        }
+    }
+}
+
+/// Harmonics with amplitudes falling as 1/n, the higher ones dying away faster
+struct Additive {
+    phases: [f64; HARMONICS],
+}
+
+impl Additive {
+    fn next(&mut self, frequency: f64, sample_rate: f64, time: f32) -> f32 {
+        let mut out = 0.0;
+        for (index, phase) in self.phases.iter_mut().enumerate() {
+            let harmonic = (index + 1) as f64;
+            if frequency * harmonic >= sample_rate / 2.0 {
+                break;
+            }
+            let level = (-time * (0.5 + 0.7 * harmonic as f32)).exp() / harmonic as f32;
+            out += phase.sin() as f32 * level;
+            *phase = (*phase + TAU * frequency * harmonic / sample_rate) % TAU;
+        }
+        out * 0.5
+    }
+}
+
+enum Oscillator {
+    Pluck(Pluck),
+    Additive(Additive),
+}
+
+struct Voice {
+    channel: u8,
+    key: u8,
+    amplitude: f32,
+    oscillator: Oscillator,
+    /// Samples since the note started
+    age: u64,
+    /// Age the note was released at
+    released: Option<u64>,
+}
+
+impl Voice {
+    fn next(&mut self, bend: f32, sample_rate: f64) -> f32 {
+        let time = self.age as f32 / sample_rate as f32;
+        let ratio = 2f64.powf(bend as f64 / 12.0);
+        let raw = match &mut self.oscillator {
+            Oscillator::Pluck(pluck) => pluck.next(ratio),
+            Oscillator::Additive(additive) => {
+                additive.next(key_frequency(self.key) * ratio, sample_rate, time)
+            }
+        };
+        let attack = (time / ATTACK_SECONDS).min(1.0);
+        let release = self.released.map_or(1.0, |at| {
+            let since = (self.age - at) as f32 / sample_rate as f32;
+            (1.0 - since / RELEASE_SECONDS).max(0.0)
+        });
+        self.age += 1;
+        raw * self.amplitude * attack * release
+    }
+
+    fn release(&mut self) {
+        self.released.get_or_insert(self.age);
+    }
+
+    fn is_done(&self, sample_rate: f64) -> bool {
+        self.released
+            .is_some_and(|at| (self.age - at) as f64 >= RELEASE_SECONDS as f64 * sample_rate)
+    }
+}
+
+/// Pitch bend state of a MIDI channel
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    /// Bend from -1.0 to 1.0 of the range
+    bend: f32,
+    /// Bend range in semitones, set by RPN 0
+    bend_range: f32,
+    /// Selected registered parameter (MSB, LSB)
+    rpn: (u8, u8),
+}
+
+impl Channel {
+    fn semitones(&self) -> f32 {
+        self.bend * self.bend_range
+    }
+}
+
+fn key_frequency(key: u8) -> f64 {
+    440.0 * 2f64.powf((key as f64 - 69.0) / 12.0)
+}
+
+/// Renders MIDI files to audio for listening back to transcriptions
+///
+/// Velocities set the level of each note and pitch bends follow the bend
+/// range of their channel: the configured one, or what the file sets with
+/// RPN 0. Per-string and MPE recordings set it at the start of every take.
+pub struct PreviewSynth {
+    sample_rate: u32,
+    voice: SynthVoice,
+    bend_range: f32,
+}
+
+impl PreviewSynth {
+    pub fn new(sample_rate: u32, voice: SynthVoice) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            voice,
+            bend_range: 2.0,
+        }
+    }
+
+    /// Bend range in semitones of channels the file does not set one for
+    pub fn set_bend_range(&mut self, semitones: f32) {
+        self.bend_range = semitones;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Render the file to mono samples, from its start to shortly after its end
+    pub fn render(&self, player: &MidiPlayer) -> Vec<f32> {
+        let sample_rate = self.sample_rate as f64;
+        let length = ((player.length().as_secs_f64() + TAIL_SECONDS) * sample_rate) as usize;
+        let mut channels = [Channel {
+            bend: 0.0,
+            bend_range: self.bend_range,
+            rpn: (127, 127),
+        }; 16];
+        let mut voices: Vec<Voice> = Vec::new();
+        let mut messages = player
+            .messages()
+            .map(|(time, bytes)| ((time.as_secs_f64() * sample_rate) as usize, bytes))
+            .peekable();
+        let mut notes = 0u32;
+
+        let mut samples = Vec::with_capacity(length);
+        for sample in 0..length {
+            while let Some((_, message)) = messages.next_if(|&(time, _)| time <= sample) {
+                if let Some(voice) = self.handle(message, &mut channels, &mut voices, notes) {
+                    voices.push(voice);
+                    notes += 1;
+                }
+            }
+            let mix: f32 = voices
+                .iter_mut()
+                .map(|voice| voice.next(channels[voice.channel as usize].semitones(), sample_rate))
+                .sum();
+            voices.retain(|voice| !voice.is_done(sample_rate));
+            samples.push((mix * MIX_GAIN).tanh());
+        }
+        samples
+    }
+
+    /// Apply a channel message, returning the voice of a note that starts
+    fn handle(
+        &self,
+        message: &[u8],
+        channels: &mut [Channel; 16],
+        voices: &mut [Voice],
+        seed: u32,
+    ) -> Option<Voice> {
+        let &[status, data1, ..] = message else {
+            return None;
+        };
+        let data2 = message.get(2).copied().unwrap_or(0);
+        let index = status & 0x0F;
+        let channel = &mut channels[index as usize];
+        let mut release = |key: Option<u8>| {
+            voices
+                .iter_mut()
+                .filter(|voice| voice.channel == index && key.is_none_or(|key| voice.key == key))
+                .for_each(Voice::release);
+        };
+
+        match status & 0xF0 {
+            0x90 if data2 > 0 => {
+                // A retrigger ends the sounding note
+                release(Some(data1));
+                let oscillator = match self.voice {
+                    SynthVoice::Pluck => Oscillator::Pluck(Pluck::new(
+                        key_frequency(data1),
+                        self.sample_rate as f64,
+                        seed.wrapping_mul(2_654_435_761) ^ data1 as u32,
+                    )),
+                    SynthVoice::Additive => Oscillator::Additive(Additive {
+                        phases: [0.0; HARMONICS],
+                    }),
+                };
+                return Some(Voice {
+                    channel: index,
+                    key: data1,
+                    amplitude: data2 as f32 / 127.0,
+                    oscillator,
+                    age: 0,
+                    released: None,
+                });
+            }
+            0x80 | 0x90 => release(Some(data1)),
+            0xE0 => {
+                let value = ((data2 as u16) << 7) | data1 as u16;
+                channel.bend = (value as f32 - 8192.0) / 8192.0;
+            }
+            0xB0 => match data1 {
+                CC_RPN_MSB => channel.rpn.0 = data2,
+                CC_RPN_LSB => channel.rpn.1 = data2,
+                CC_DATA_ENTRY_MSB if channel.rpn == (0, 0) => {
+                    channel.bend_range = data2 as f32;
+                }
+                CC_DATA_ENTRY_LSB if channel.rpn == (0, 0) => {
+                    channel.bend_range = channel.bend_range.trunc() + data2 as f32 / 100.0;
+                }
+                CC_ALL_SOUND_OFF | CC_ALL_NOTES_OFF => release(None),
+                _ => {}
+            },
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Read a WAV file as mono samples, averaging its channels, with its sample rate
+pub fn read_wav_mono(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let spec = WavReader::open(path)
+        .with_context(|| format!("Failed to open audio file {:?}", path))?
+        .spec();
+    let channels = spec.channels.max(1) as usize;
+    let interleaved = super::wav_samples(path)?
+        .collect::<hound::Result<Vec<f32>>>()
+        .with_context(|| format!("Failed to read audio file {:?}", path))?;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok((samples, spec.sample_rate))
+}
+
+/// Interleave two mono signals into stereo, padding the shorter one with silence
+pub fn mix_stereo(left: &[f32], right: &[f32]) -> Vec<f32> {
+    let frames = left.len().max(right.len());
+    (0..frames)
+        .flat_map(|frame| {
+            [
+                left.get(frame).copied().unwrap_or(0.0),
+                right.get(frame).copied().unwrap_or(0.0),
+            ]
+        })
+        .collect()
+}
+
+/// Write interleaved samples to a 32-bit float WAV file
+pub fn write_wav(path: &Path, sample_rate: u32, channels: u16, samples: &[f32]) -> Result<()> {
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create audio file {:?}", path))?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer
+        .finalize()
+        .with_context(|| format!("Failed to write audio file {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::PitchDetector;
+    use midly::{
+        live::LiveEvent,
+        num::{u15, u28},
+        Format, Header, Smf, Timing, TrackEvent, TrackEventKind,
+    };
+
+    /// A file at 120 BPM and 480 PPQ from (tick, message) pairs
+    fn player(messages: &[(u32, &[u8])]) -> MidiPlayer {
+        let mut last = 0;
+        let track = messages
+            .iter()
+            .map(|&(tick, bytes)| {
+                let LiveEvent::Midi { channel, message } = LiveEvent::parse(bytes).unwrap() else {
+                    panic!("not a channel message");
+                };
+                let delta = tick - last;
+                last = tick;
+                TrackEvent {
+                    delta: u28::new(delta),
+                    kind: TrackEventKind::Midi { channel, message },
+                }
+            })
+            .collect();
+        let smf = Smf {
+            header: Header {
+                format: Format::SingleTrack,
+                timing: Timing::Metrical(u15::new(480)),
+            },
+            tracks: vec![track],
+        };
+        MidiPlayer::from_smf(&smf).unwrap()
+    }
+
+    /// Pitch detected in a window starting at `seconds`
+    fn pitch_at(samples: &[f32], seconds: f32) -> f32 {
+        let start = (seconds * 44_100.0) as usize;
+        PitchDetector::new(44_100, 2048, 0.15)
+            .detect_pitch(&samples[start..start + 2048])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_pitch() {
+        // A4 for a beat, half a second at 120 BPM
+        let player = player(&[(0, &[0x90, 69, 100]), (480, &[0x80, 69, 0])]);
+        for voice in [SynthVoice::Pluck, SynthVoice::Additive] {
+            let samples = PreviewSynth::new(44_100, voice).render(&player);
+            assert_eq!(samples.len(), 44_100);
+            let pitch = pitch_at(&samples, 0.1);
+            assert!((pitch - 440.0).abs() < 5.0, "{:?}: {}", voice, pitch);
+            // Released and silent well before the end of the tail
+            assert!(samples[40_000..].iter().all(|s| s.abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_render_pitch_bend() {
+        // Full bend up with the default range of 2 semitones
+        let bent = player(&[
+            (0, &[0x90, 69, 100]),
+            (240, &[0xE0, 0x7F, 0x7F]),
+            (960, &[0x80, 69, 0]),
+        ]);
+        let samples = PreviewSynth::new(44_100, SynthVoice::Additive).render(&bent);
+        assert!((pitch_at(&samples, 0.05) - 440.0).abs() < 5.0);
+        assert!((pitch_at(&samples, 0.5) - 493.9).abs() < 5.0);
+
+        // Half a bend with the range set to 12 semitones through RPN 0
+        let rpn = player(&[
+            (0, &[0xB0, 101, 0]),
+            (0, &[0xB0, 100, 0]),
+            (0, &[0xB0, 6, 12]),
+            (0, &[0xE0, 0x00, 0x60]),
+            (0, &[0x90, 57, 100]),
+            (480, &[0x80, 57, 0]),
+        ]);
+        let samples = PreviewSynth::new(44_100, SynthVoice::Pluck).render(&rpn);
+        assert!((pitch_at(&samples, 0.1) - 311.1).abs() < 5.0);
+    }
+
+    #[test]
+    fn test_render_recorded_mpe() {
+        use crate::midi::{MidiOutputHandler, MidiRecorder, NoteExpression};
+
+        let recorder = MidiRecorder::shared();
+        let mut output = MidiOutputHandler::new().unwrap();
+        output.enable_mpe(15, 48);
+        output.record_only().unwrap();
+        output.set_recorder(recorder.clone());
+        recorder.lock().unwrap().start();
+
+        // A3 bent up a quarter of ±48 semitones sounds as A4
+        let expression = NoteExpression {
+            bend: 0.25,
+            ..Default::default()
+        };
+        recorder.lock().unwrap().set_time(Some(0));
+        output.mpe_note_on(57, 100, expression).unwrap();
+        recorder.lock().unwrap().set_time(Some(500_000));
+        output.note_off(57).unwrap();
+        recorder.lock().unwrap().stop();
+
+        let path = std::env::temp_dir().join("test_render_recorded_mpe.mid");
+        recorder.lock().unwrap().save(&path).unwrap();
+        let player = MidiPlayer::load(&path).unwrap();
+        let samples = PreviewSynth::new(44_100, SynthVoice::Additive).render(&player);
+        let pitch = pitch_at(&samples, 0.1);
+        assert!((pitch - 440.0).abs() < 5.0, "{}", pitch);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_render_velocity() {
+        let peak = |velocity: u8| {
+            let player = player(&[(0, &[0x90, 60, velocity]), (480, &[0x80, 60, 0])]);
+            PreviewSynth::new(44_100, SynthVoice::Additive)
+                .render(&player)
+                .iter()
+                .fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        assert!(peak(40) < peak(120) * 0.5);
+    }
+
+    #[test]
+    fn test_mix_stereo() {
+        assert_eq!(
+            mix_stereo(&[0.1, 0.2, 0.3], &[-0.1]),
+            vec![0.1, -0.1, 0.2, 0.0, 0.3, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_wav_round_trip() {
+        let path = std::env::temp_dir().join("test_synth_wav.wav");
+        write_wav(&path, 22_050, 2, &[0.5, -0.5, 0.25, 0.75]).unwrap();
+        let (samples, sample_rate) = read_wav_mono(&path).unwrap();
+        assert_eq!(sample_rate, 22_050);
+        assert_eq!(samples, vec![0.0, 0.5]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
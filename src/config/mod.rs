@@ -1,4 +1,80 @@
+use crate::guitar::Tuning;
+use crate::midi::{OverlapFix, PunchRange, TakeStorage, TrackLayout};
+use crate::notation::TimeSignature;
+use crate::notelog::NoteLogFormat;
+use crate::theory::{Scale, SnapDirection};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How notes are assigned to strings in per-string channel mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StringAssignmentMode {
+    /// Infer the string from the tuning and fret range
+    #[default]
+    Tuning,
+    /// Each audio input channel is one string (hexaphonic pickup)
+    InputChannel,
+}
+
+/// A keyboard-split zone: notes in range go to its own channel and/or port
+///
+/// Unknown fields are rejected, so a misspelled or unsupported range is not
+/// silently ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneConfig {
+    /// Zone name shown in logs
+    #[serde(default)]
+    pub name: String,
+
+    /// Lowest note of the zone (after global note mapping)
+    #[serde(default)]
+    pub note_low: u8,
+
+    /// Highest note of the zone (after global note mapping)
+    #[serde(default = "default_note_range_high")]
+    pub note_high: u8,
+
+    /// Output channel, 0-based (None = midi_channel)
+    #[serde(default)]
+    pub channel: Option<u8>,
+
+    /// Output port name (None = midi_port)
+    #[serde(default)]
+    pub port: Option<String>,
+
+    /// Transposition in semitones applied to notes in this zone
+    #[serde(default)]
+    pub transpose: i8,
+}
+
+impl Default for ZoneConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            note_low: 0,
+            note_high: 127,
+            channel: None,
+            port: None,
+            transpose: 0,
+        }
+    }
+}
+
+/// A tempo change in recordings, at the start of a bar
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TempoChangeConfig {
+    /// Bar number, 1-based (bar 1 uses record_bpm)
+    pub bar: u32,
+
+    /// Tempo from this bar on, in quarter notes per minute
+    pub bpm: f32,
+}
+
+/// Tempo range accepted for recordings
+const MIN_RECORD_BPM: f32 = 20.0;
+const MAX_RECORD_BPM: f32 = 400.0;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -43,6 +119,123 @@ pub struct Config {
     /// Output file path for MIDI recording (None = auto-generate based on timestamp)
     #[serde(default)]
     pub record_output: Option<String>,
+
+    /// Tempo of recordings in quarter notes per minute
+    #[serde(default = "default_record_bpm")]
+    pub record_bpm: f32,
+
+    /// Time signature of recordings, e.g. "4/4" or "6/8"
+    #[serde(default = "default_record_time_signature")]
+    pub record_time_signature: String,
+
+    /// Resolution of recordings in ticks per quarter note (PPQ)
+    #[serde(default = "default_record_ppq")]
+    pub record_ppq: u16,
+
+    /// Tempo changes during recordings, by bar
+    #[serde(default)]
+    pub record_tempo_changes: Vec<TempoChangeConfig>,
+
+    /// How recordings are split into tracks: single, channel, zone or detection
+    #[serde(default)]
+    pub record_tracks: TrackLayout,
+
+    /// Thin dense pitch bend, pressure and controller data in recordings
+    #[serde(default)]
+    pub record_thinning_enabled: bool,
+
+    /// Minimum time in seconds between recorded values of one controller
+    #[serde(default = "default_record_thinning_interval")]
+    pub record_thinning_interval: f32,
+
+    /// Minimum change of a recorded controller value, in 7-bit steps
+    #[serde(default = "default_record_thinning_delta")]
+    pub record_thinning_delta: u8,
+
+    /// Journal recordings to disk as they are made, for the recover command
+    #[serde(default = "default_record_journal_enabled")]
+    pub record_journal_enabled: bool,
+
+    /// Seconds between autosaves of the recording (0 = no autosave)
+    #[serde(default = "default_record_autosave_interval")]
+    pub record_autosave_interval: f32,
+
+    /// Capture the input audio of each take to a WAV file next to the take
+    #[serde(default = "default_record_audio_enabled")]
+    pub record_audio_enabled: bool,
+
+    /// Write a note log (jsonl or csv) next to the recording, for tuning detection
+    #[serde(default)]
+    pub record_note_log: Option<NoteLogFormat>,
+
+    /// Start recording armed; when off, the first take starts once armed at runtime
+    #[serde(default = "default_record_armed")]
+    pub record_armed: bool,
+
+    /// Where takes are kept: files (one file per take) or tracks (one file, tracks per take)
+    #[serde(default)]
+    pub record_takes: TakeStorage,
+
+    /// First bar of each take that is recorded (0 = from the start)
+    #[serde(default)]
+    pub record_punch_in: u32,
+
+    /// Bar of each take where recording stops (0 = to the end)
+    #[serde(default)]
+    pub record_punch_out: u32,
+
+    /// Existing MIDI file to layer the recording onto as new tracks
+    #[serde(default)]
+    pub record_overdub: Option<String>,
+
+    /// Clean up recordings when saving, keeping the unprocessed take next to them
+    #[serde(default)]
+    pub record_cleanup_enabled: bool,
+
+    /// Notes shorter than this many seconds are dropped by cleanup
+    #[serde(default = "default_cleanup_min_note_length")]
+    pub cleanup_min_note_length: f32,
+
+    /// Same-pitch notes separated by at most this many seconds are merged by cleanup
+    #[serde(default = "default_cleanup_merge_gap")]
+    pub cleanup_merge_gap: f32,
+
+    /// How cleanup fixes overlapping notes: keep, trim or legato
+    #[serde(default)]
+    pub cleanup_overlap: OverlapFix,
+
+    /// Quantization grid of cleanup as a note value: 4, 8, 16 or 32 (0 = no quantization)
+    #[serde(default)]
+    pub cleanup_grid: u32,
+
+    /// How far cleanup moves notes towards the grid (0.0-1.0)
+    #[serde(default = "default_cleanup_quantize_strength")]
+    pub cleanup_quantize_strength: f32,
+
+    /// Swing of the quantization grid (0.5 = straight, 0.67 = triplet feel)
+    #[serde(default = "default_cleanup_swing")]
+    pub cleanup_swing: f32,
+
+    /// Velocity of the loudest note after cleanup (None = velocities unchanged)
+    #[serde(default)]
+    pub cleanup_velocity_peak: Option<u8>,
+
+    /// Write the tracked tempo into recordings instead of record_bpm
+    #[serde(default)]
+    pub record_tempo_tracking: bool,
+
+    /// Estimate tempo and beats from note attacks
+    #[serde(default = "default_beat_tracking_enabled")]
+    pub beat_tracking_enabled: bool,
+
+    /// Slowest tempo considered by beat tracking
+    #[serde(default = "default_beat_tracking_min_bpm")]
+    pub beat_tracking_min_bpm: f32,
+
+    /// Fastest tempo considered by beat tracking
+    #[serde(default = "default_beat_tracking_max_bpm")]
+    pub beat_tracking_max_bpm: f32,
+
     /// Enable pitch bend for vibrato, trills, and whammy effects
     #[serde(default = "default_pitch_bend_enabled")]
     pub pitch_bend_enabled: bool,
@@ -58,6 +251,102 @@ pub struct Config {
     /// Minimum peak magnitude for polyphonic detection (higher = less sensitive)
     #[serde(default = "default_polyphonic_threshold")]
     pub polyphonic_threshold: f32,
+
+    /// Enable MPE output (one member channel per note with per-note bend, pressure and timbre)
+    #[serde(default)]
+    pub mpe_enabled: bool,
+
+    /// Number of MPE member channels (1-15)
+    #[serde(default = "default_mpe_member_channels")]
+    pub mpe_member_channels: u8,
+
+    /// Pitch bend range of the MPE member channels in semitones
+    #[serde(default = "default_mpe_pitch_bend_range")]
+    pub mpe_pitch_bend_range: u8,
+
+    /// Send each guitar string on its own MIDI channel (guitar synth mode)
+    #[serde(default)]
+    pub string_channels_enabled: bool,
+
+    /// How notes are assigned to strings in per-string channel mode
+    #[serde(default)]
+    pub string_assignment: StringAssignmentMode,
+
+    /// MIDI channel of string 1 (0-based); the other strings use the following channels
+    #[serde(default)]
+    pub string_channel_base: u8,
+
+    /// Guitar tuning: preset name (e.g. "standard", "drop-d") or note names low to high
+    #[serde(default = "default_tuning")]
+    pub tuning: String,
+
+    /// Capo fret (0 = no capo)
+    #[serde(default)]
+    pub capo: u8,
+
+    /// Highest playable fret
+    #[serde(default = "default_max_fret")]
+    pub max_fret: u8,
+
+    /// MIDI output channel (0-based, 0 = channel 1)
+    #[serde(default)]
+    pub midi_channel: u8,
+
+    /// Transposition in semitones applied to every note
+    #[serde(default)]
+    pub transpose_semitones: i8,
+
+    /// Transposition in octaves applied to every note
+    #[serde(default)]
+    pub transpose_octaves: i8,
+
+    /// Lowest note sent (after transposition); lower notes are dropped
+    #[serde(default)]
+    pub note_range_low: u8,
+
+    /// Highest note sent (after transposition); higher notes are dropped
+    #[serde(default = "default_note_range_high")]
+    pub note_range_high: u8,
+
+    /// Remap table from (transposed) note to output note, e.g. to trigger drum sounds
+    #[serde(default)]
+    pub note_remap: BTreeMap<u8, u8>,
+
+    /// Keyboard-split zones (empty = all notes on midi_channel and midi_port)
+    #[serde(default)]
+    pub zones: Vec<ZoneConfig>,
+
+    /// Estimate the key of the music from played notes
+    #[serde(default = "default_key_detection_enabled")]
+    pub key_detection_enabled: bool,
+
+    /// Seconds for a played note's weight in key detection to halve
+    #[serde(default = "default_key_half_life")]
+    pub key_half_life: f32,
+
+    /// Minimum confidence for a key change to be reported
+    #[serde(default = "default_key_confidence_threshold")]
+    pub key_confidence_threshold: f32,
+
+    /// Snap detected notes onto a scale (monophonic mode)
+    #[serde(default)]
+    pub scale_quantize_enabled: bool,
+
+    /// Scale to snap to, e.g. "A minor" or "D 0,2,3,7,9" (None = detected key)
+    #[serde(default)]
+    pub scale: Option<String>,
+
+    /// Direction notes outside the scale are moved
+    #[serde(default)]
+    pub scale_snap: SnapDirection,
+
+    /// Infer string/fret positions of played notes for live tablature
+    #[serde(default = "default_tab_enabled")]
+    pub tab_enabled: bool,
+
+    /// Use timbre (harmonic brightness) to tell apart the same pitch on different strings
+    #[serde(default)]
+    pub fingering_timbre_cues: bool,
 }
 
 fn default_fuzzy_enabled() -> bool {
@@ -76,6 +365,70 @@ fn default_max_recent_notes() -> usize {
     20
 }
 
+fn default_record_bpm() -> f32 {
+    120.0
+}
+
+fn default_record_time_signature() -> String {
+    "4/4".to_string()
+}
+
+fn default_record_ppq() -> u16 {
+    480
+}
+
+fn default_record_thinning_interval() -> f32 {
+    0.01 // 10ms
+}
+
+fn default_record_thinning_delta() -> u8 {
+    1
+}
+
+fn default_record_journal_enabled() -> bool {
+    true
+}
+
+fn default_record_audio_enabled() -> bool {
+    true
+}
+
+fn default_record_armed() -> bool {
+    true
+}
+
+fn default_record_autosave_interval() -> f32 {
+    30.0
+}
+
+fn default_cleanup_min_note_length() -> f32 {
+    0.03
+}
+
+fn default_cleanup_merge_gap() -> f32 {
+    0.03
+}
+
+fn default_cleanup_quantize_strength() -> f32 {
+    1.0
+}
+
+fn default_cleanup_swing() -> f32 {
+    0.5
+}
+
+fn default_beat_tracking_enabled() -> bool {
+    true
+}
+
+fn default_beat_tracking_min_bpm() -> f32 {
+    60.0
+}
+
+fn default_beat_tracking_max_bpm() -> f32 {
+    200.0
+}
+
 fn default_pitch_bend_enabled() -> bool {
     true
 }
@@ -88,6 +441,42 @@ fn default_polyphonic_threshold() -> f32 {
     0.2
 }
 
+fn default_mpe_member_channels() -> u8 {
+    15
+}
+
+fn default_mpe_pitch_bend_range() -> u8 {
+    48
+}
+
+fn default_tuning() -> String {
+    "standard".to_string()
+}
+
+fn default_max_fret() -> u8 {
+    22
+}
+
+fn default_note_range_high() -> u8 {
+    127
+}
+
+fn default_tab_enabled() -> bool {
+    true
+}
+
+fn default_key_detection_enabled() -> bool {
+    true
+}
+
+fn default_key_half_life() -> f32 {
+    20.0
+}
+
+fn default_key_confidence_threshold() -> f32 {
+    0.6
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -103,10 +492,63 @@ impl Default for Config {
             max_recent_notes: default_max_recent_notes(),
             record_enabled: false,
             record_output: None,
+            record_bpm: default_record_bpm(),
+            record_time_signature: default_record_time_signature(),
+            record_ppq: default_record_ppq(),
+            record_tempo_changes: Vec::new(),
+            record_tracks: TrackLayout::default(),
+            record_thinning_enabled: false,
+            record_thinning_interval: default_record_thinning_interval(),
+            record_thinning_delta: default_record_thinning_delta(),
+            record_journal_enabled: default_record_journal_enabled(),
+            record_autosave_interval: default_record_autosave_interval(),
+            record_audio_enabled: default_record_audio_enabled(),
+            record_note_log: None,
+            record_armed: default_record_armed(),
+            record_takes: TakeStorage::default(),
+            record_punch_in: 0,
+            record_punch_out: 0,
+            record_overdub: None,
+            record_cleanup_enabled: false,
+            cleanup_min_note_length: default_cleanup_min_note_length(),
+            cleanup_merge_gap: default_cleanup_merge_gap(),
+            cleanup_overlap: OverlapFix::default(),
+            cleanup_grid: 0,
+            cleanup_quantize_strength: default_cleanup_quantize_strength(),
+            cleanup_swing: default_cleanup_swing(),
+            cleanup_velocity_peak: None,
+            record_tempo_tracking: false,
+            beat_tracking_enabled: default_beat_tracking_enabled(),
+            beat_tracking_min_bpm: default_beat_tracking_min_bpm(),
+            beat_tracking_max_bpm: default_beat_tracking_max_bpm(),
             pitch_bend_enabled: default_pitch_bend_enabled(),
             pitch_bend_range: default_pitch_bend_range(),
             polyphonic_enabled: false,
             polyphonic_threshold: default_polyphonic_threshold(),
+            mpe_enabled: false,
+            mpe_member_channels: default_mpe_member_channels(),
+            mpe_pitch_bend_range: default_mpe_pitch_bend_range(),
+            string_channels_enabled: false,
+            string_assignment: StringAssignmentMode::default(),
+            string_channel_base: 0,
+            tuning: default_tuning(),
+            capo: 0,
+            max_fret: default_max_fret(),
+            midi_channel: 0,
+            transpose_semitones: 0,
+            transpose_octaves: 0,
+            note_range_low: 0,
+            note_range_high: default_note_range_high(),
+            note_remap: BTreeMap::new(),
+            zones: Vec::new(),
+            key_detection_enabled: default_key_detection_enabled(),
+            key_half_life: default_key_half_life(),
+            key_confidence_threshold: default_key_confidence_threshold(),
+            scale_quantize_enabled: false,
+            scale: None,
+            scale_snap: SnapDirection::default(),
+            tab_enabled: default_tab_enabled(),
+            fingering_timbre_cues: false,
         }
     }
 }
@@ -129,7 +571,7 @@ impl Config {
     }
 
     /// Validate configuration parameters
-    fn validate(&self) -> anyhow::Result<()> {
+    pub fn validate(&self) -> anyhow::Result<()> {
         if self.fuzzy_enabled && self.clear_threshold < self.fuzzy_threshold {
             anyhow::bail!(
                 "clear_threshold ({}) must be greater than or equal to fuzzy_threshold ({}). \
@@ -138,8 +580,209 @@ impl Config {
                 self.fuzzy_threshold
             );
         }
+        if self.mpe_enabled && !(1..=15).contains(&self.mpe_member_channels) {
+            anyhow::bail!(
+                "mpe_member_channels ({}) must be between 1 and 15",
+                self.mpe_member_channels
+            );
+        }
+        if self.mpe_enabled && !(1..=96).contains(&self.mpe_pitch_bend_range) {
+            anyhow::bail!(
+                "mpe_pitch_bend_range ({}) must be between 1 and 96 semitones",
+                self.mpe_pitch_bend_range
+            );
+        }
+        if self.midi_channel > 15 {
+            anyhow::bail!(
+                "midi_channel ({}) must be between 0 and 15",
+                self.midi_channel
+            );
+        }
+        if self.note_range_low > self.note_range_high || self.note_range_high > 127 {
+            anyhow::bail!(
+                "note range {}-{} is invalid (must be within 0-127 and low <= high)",
+                self.note_range_low,
+                self.note_range_high
+            );
+        }
+        if let Some((from, to)) = self.note_remap.iter().find(|(&f, &t)| f > 127 || t > 127) {
+            anyhow::bail!("note_remap entry {} -> {} is outside 0-127", from, to);
+        }
+        for (index, zone) in self.zones.iter().enumerate() {
+            if zone.note_low > zone.note_high || zone.note_high > 127 {
+                anyhow::bail!(
+                    "zone {} note range {}-{} is invalid",
+                    index + 1,
+                    zone.note_low,
+                    zone.note_high
+                );
+            }
+            if zone.channel.is_some_and(|channel| channel > 15) {
+                anyhow::bail!("zone {} channel must be between 0 and 15", index + 1);
+            }
+        }
+        let tempos = std::iter::once(self.record_bpm)
+            .chain(self.record_tempo_changes.iter().map(|change| change.bpm));
+        for bpm in tempos {
+            if !(MIN_RECORD_BPM..=MAX_RECORD_BPM).contains(&bpm) {
+                anyhow::bail!(
+                    "recording tempo ({}) must be between {} and {} BPM",
+                    bpm,
+                    MIN_RECORD_BPM,
+                    MAX_RECORD_BPM
+                );
+            }
+        }
+        if let Some(change) = self.record_tempo_changes.iter().find(|c| c.bar < 1) {
+            anyhow::bail!("tempo change bar ({}) must be 1 or later", change.bar);
+        }
+        self.record_time_signature()?;
+        if !(24..=0x7FFF).contains(&self.record_ppq) {
+            anyhow::bail!(
+                "record_ppq ({}) must be between 24 and 32767",
+                self.record_ppq
+            );
+        }
+        if self.record_thinning_enabled
+            && !(self.record_thinning_interval >= 0.0 && self.record_thinning_interval < 10.0)
+        {
+            anyhow::bail!(
+                "record_thinning_interval ({}) must be between 0 and 10 seconds",
+                self.record_thinning_interval
+            );
+        }
+        if self.record_autosave_interval.is_nan() || self.record_autosave_interval < 0.0 {
+            anyhow::bail!(
+                "record_autosave_interval ({}) must be 0 or more seconds",
+                self.record_autosave_interval
+            );
+        }
+        if self.record_punch_out != 0 && self.record_punch_out <= self.record_punch_in.max(1) {
+            anyhow::bail!(
+                "record_punch_out ({}) must be after record_punch_in ({})",
+                self.record_punch_out,
+                self.record_punch_in.max(1)
+            );
+        }
+        for (name, seconds) in [
+            ("cleanup_min_note_length", self.cleanup_min_note_length),
+            ("cleanup_merge_gap", self.cleanup_merge_gap),
+        ] {
+            if !(0.0..=1.0).contains(&seconds) {
+                anyhow::bail!("{} ({}) must be between 0 and 1 second", name, seconds);
+            }
+        }
+        if ![0, 4, 8, 16, 32].contains(&self.cleanup_grid) {
+            anyhow::bail!(
+                "cleanup_grid ({}) must be 4, 8, 16, 32 or 0 for no quantization",
+                self.cleanup_grid
+            );
+        }
+        if !(0.0..=1.0).contains(&self.cleanup_quantize_strength) {
+            anyhow::bail!(
+                "cleanup_quantize_strength ({}) must be between 0.0 and 1.0",
+                self.cleanup_quantize_strength
+            );
+        }
+        if !(0.5..=0.75).contains(&self.cleanup_swing) {
+            anyhow::bail!(
+                "cleanup_swing ({}) must be between 0.5 and 0.75",
+                self.cleanup_swing
+            );
+        }
+        if let Some(peak) = self.cleanup_velocity_peak {
+            if !(1..=127).contains(&peak) {
+                anyhow::bail!("cleanup_velocity_peak ({}) must be between 1 and 127", peak);
+            }
+        }
+        if self.beat_tracking_enabled || self.record_tempo_tracking {
+            let (min, max) = (self.beat_tracking_min_bpm, self.beat_tracking_max_bpm);
+            if !(MIN_RECORD_BPM <= min && min < max && max <= MAX_RECORD_BPM) {
+                anyhow::bail!(
+                    "beat tracking tempo range ({} to {} BPM) must be within {} and {} BPM",
+                    min,
+                    max,
+                    MIN_RECORD_BPM,
+                    MAX_RECORD_BPM
+                );
+            }
+        }
+        if self.record_tempo_tracking && !self.beat_tracking_enabled {
+            anyhow::bail!("record_tempo_tracking needs beat_tracking_enabled");
+        }
+        if !self.zones.is_empty() && (self.mpe_enabled || self.string_channels_enabled) {
+            anyhow::bail!("zones cannot be combined with MPE or per-string channels");
+        }
+        if self.key_detection_enabled {
+            if self.key_half_life <= 0.0 {
+                anyhow::bail!(
+                    "key_half_life ({}) must be greater than 0",
+                    self.key_half_life
+                );
+            }
+            if !(0.0..=1.0).contains(&self.key_confidence_threshold) {
+                anyhow::bail!(
+                    "key_confidence_threshold ({}) must be between 0.0 and 1.0",
+                    self.key_confidence_threshold
+                );
+            }
+        }
+        if let Some(scale) = &self.scale {
+            Scale::parse(scale)?;
+        }
+        if self.scale_quantize_enabled && self.scale.is_none() && !self.key_detection_enabled {
+            anyhow::bail!("scale quantization needs a scale or key_detection_enabled");
+        }
+        // Quantization is applied to monophonic detection only
+        let hex = self.string_channels_enabled
+            && self.string_assignment == StringAssignmentMode::InputChannel;
+        if self.scale_quantize_enabled && (self.polyphonic_enabled || hex) {
+            anyhow::bail!(
+                "scale quantization is only available in monophonic mode, not with polyphonic or hexaphonic input"
+            );
+        }
+
+        let tuning = Tuning::parse(&self.tuning)?;
+        if self.capo > self.max_fret {
+            anyhow::bail!(
+                "capo ({}) must not be above max_fret ({})",
+                self.capo,
+                self.max_fret
+            );
+        }
+        if self.string_channels_enabled {
+            if self.mpe_enabled {
+                anyhow::bail!("string_channels_enabled and mpe_enabled cannot be used together");
+            }
+            if self.string_channel_base as usize + tuning.string_count() > 16 {
+                anyhow::bail!(
+                    "string_channel_base ({}) leaves no room for {} string channels",
+                    self.string_channel_base,
+                    tuning.string_count()
+                );
+            }
+        }
         Ok(())
     }
+
+    /// Parsed guitar tuning
+    pub fn guitar_tuning(&self) -> anyhow::Result<Tuning> {
+        Tuning::parse(&self.tuning)
+    }
+
+    /// Parsed time signature of recordings
+    pub fn record_time_signature(&self) -> anyhow::Result<TimeSignature> {
+        self.record_time_signature.parse()
+    }
+
+    /// Punch range of takes, if punching in or out
+    pub fn record_punch(&self) -> Option<PunchRange> {
+        if self.record_punch_in == 0 && self.record_punch_out == 0 {
+            return None;
+        }
+        let end = (self.record_punch_out != 0).then_some(self.record_punch_out);
+        Some(PunchRange::new(self.record_punch_in.max(1), end))
+    }
 }
 
 #[cfg(test)]
@@ -208,4 +851,391 @@ mod tests {
         assert!(!deserialized.pitch_bend_enabled);
         assert_eq!(deserialized.pitch_bend_range, 12.0);
     }
+
+    #[test]
+    fn test_config_mpe_defaults() {
+        let config = Config::default();
+        assert!(!config.mpe_enabled);
+        assert_eq!(config.mpe_member_channels, 15);
+        assert_eq!(config.mpe_pitch_bend_range, 48);
+    }
+
+    #[test]
+    fn test_config_validation_invalid_mpe_channels() {
+        let config = Config {
+            mpe_enabled: true,
+            mpe_member_channels: 16,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_string_channel_defaults() {
+        let config = Config::default();
+        assert!(!config.string_channels_enabled);
+        assert_eq!(config.string_assignment, StringAssignmentMode::Tuning);
+        assert_eq!(config.guitar_tuning().unwrap(), Tuning::standard());
+    }
+
+    #[test]
+    fn test_config_validation_string_channels() {
+        let config = Config {
+            string_channels_enabled: true,
+            string_channel_base: 12,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            tuning: "not a tuning".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let json = r#"{"buffer_size": 2048, "min_note_duration": 0.05, "pitch_threshold": 0.15,
+            "midi_port": null, "velocity": 80, "verbose": false,
+            "string_assignment": "input_channel"}"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.string_assignment, StringAssignmentMode::InputChannel);
+    }
+
+    #[test]
+    fn test_config_note_mapping_serialization() {
+        let config = Config {
+            midi_channel: 9,
+            transpose_octaves: 1,
+            note_range_low: 40,
+            note_remap: BTreeMap::from([(40, 36)]),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let deserialized: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.midi_channel, 9);
+        assert_eq!(deserialized.transpose_octaves, 1);
+        assert_eq!(deserialized.note_remap.get(&40), Some(&36));
+        assert!(deserialized.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validation_note_range() {
+        let config = Config {
+            note_range_low: 80,
+            note_range_high: 40,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_zones_serialization() {
+        let json = r#"{
+            "buffer_size": 2048,
+            "min_note_duration": 0.05,
+            "pitch_threshold": 0.15,
+            "midi_port": null,
+            "velocity": 80,
+            "verbose": false,
+            "zones": [
+                {"name": "bass", "note_high": 51, "channel": 1, "transpose": -12},
+                {"note_low": 52, "port": "Lead Synth"}
+            ]
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.zones.len(), 2);
+        assert_eq!(config.zones[0].note_low, 0);
+        assert_eq!(config.zones[0].note_high, 51);
+        assert_eq!(config.zones[1].port.as_deref(), Some("Lead Synth"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validation_zones() {
+        let mut config = Config {
+            zones: vec![ZoneConfig {
+                note_low: 100,
+                note_high: 50,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        config.zones[0] = ZoneConfig::default();
+        config.mpe_enabled = true;
+        assert!(config.validate().is_err());
+
+        // Zones route by note only, so velocity ranges are rejected
+        assert!(serde_json::from_str::<ZoneConfig>(r#"{"velocity_low": 64}"#).is_err());
+    }
+
+    #[test]
+    fn test_config_key_detection_defaults() {
+        let config = Config::default();
+        assert!(config.key_detection_enabled);
+        assert_eq!(config.key_half_life, 20.0);
+
+        let config = Config {
+            key_confidence_threshold: 1.5,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_tab_defaults() {
+        let config = Config::default();
+        assert!(config.tab_enabled);
+        assert!(!config.fingering_timbre_cues);
+
+        let json = r#"{
+            "buffer_size": 2048,
+            "min_note_duration": 0.05,
+            "pitch_threshold": 0.15,
+            "midi_port": null,
+            "velocity": 80,
+            "verbose": false,
+            "fingering_timbre_cues": true
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert!(config.fingering_timbre_cues);
+        assert!(config.tab_enabled);
+    }
+
+    #[test]
+    fn test_config_scale_quantize() {
+        let json = r#"{
+            "buffer_size": 2048,
+            "min_note_duration": 0.05,
+            "pitch_threshold": 0.15,
+            "midi_port": null,
+            "velocity": 80,
+            "verbose": false,
+            "scale_quantize_enabled": true,
+            "scale": "E minor-pentatonic",
+            "scale_snap": "down"
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.scale_snap, SnapDirection::Down);
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            scale: Some("E nonsense".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let quantized = Config {
+            scale_quantize_enabled: true,
+            scale: Some("A minor".to_string()),
+            ..Default::default()
+        };
+        let config = Config {
+            polyphonic_enabled: true,
+            ..quantized.clone()
+        };
+        assert!(config.validate().is_err());
+        let config = Config {
+            string_channels_enabled: true,
+            string_assignment: StringAssignmentMode::InputChannel,
+            ..quantized.clone()
+        };
+        assert!(config.validate().is_err());
+        // Per-string channels assigned from the tuning are fed by monophonic detection
+        let config = Config {
+            string_channels_enabled: true,
+            ..quantized
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_record_thinning() {
+        let config = Config::default();
+        assert!(!config.record_thinning_enabled);
+        assert_eq!(config.record_thinning_delta, 1);
+
+        let config = Config {
+            record_thinning_enabled: true,
+            record_thinning_interval: -0.5,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_record_tracks() {
+        assert_eq!(Config::default().record_tracks, TrackLayout::Single);
+
+        let json = r#"{
+            "buffer_size": 2048,
+            "min_note_duration": 0.05,
+            "pitch_threshold": 0.15,
+            "midi_port": null,
+            "velocity": 80,
+            "verbose": false,
+            "record_tracks": "detection"
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.record_tracks, TrackLayout::Detection);
+    }
+
+    #[test]
+    fn test_config_record_tempo() {
+        let config = Config::default();
+        assert_eq!(config.record_bpm, 120.0);
+        assert_eq!(config.record_ppq, 480);
+        assert_eq!(
+            config.record_time_signature().unwrap(),
+            TimeSignature::default()
+        );
+
+        let json = r#"{
+            "buffer_size": 2048,
+            "min_note_duration": 0.05,
+            "pitch_threshold": 0.15,
+            "midi_port": null,
+            "velocity": 80,
+            "verbose": false,
+            "record_bpm": 96.0,
+            "record_time_signature": "6/8",
+            "record_tempo_changes": [{"bar": 17, "bpm": 104.0}]
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.record_time_signature().unwrap().numerator, 6);
+        assert_eq!(config.record_tempo_changes[0].bar, 17);
+
+        for invalid in [
+            Config {
+                record_bpm: 0.0,
+                ..Default::default()
+            },
+            Config {
+                record_time_signature: "4/5".to_string(),
+                ..Default::default()
+            },
+            Config {
+                record_ppq: 0,
+                ..Default::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_beat_tracking() {
+        let config = Config::default();
+        assert!(config.beat_tracking_enabled);
+        assert!(!config.record_tempo_tracking);
+        assert!(config.validate().is_ok());
+
+        for invalid in [
+            Config {
+                beat_tracking_min_bpm: 180.0,
+                beat_tracking_max_bpm: 90.0,
+                ..Default::default()
+            },
+            Config {
+                beat_tracking_enabled: false,
+                record_tempo_tracking: true,
+                ..Default::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_record_journal() {
+        let config = Config::default();
+        assert!(config.record_journal_enabled);
+        assert_eq!(config.record_autosave_interval, 30.0);
+        assert!(config.record_audio_enabled);
+        assert_eq!(config.record_note_log, None);
+
+        let config = Config {
+            record_note_log: Some(NoteLogFormat::Csv),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""record_note_log":"csv""#));
+
+        let config = Config {
+            record_autosave_interval: -1.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_record_takes() {
+        let config = Config::default();
+        assert!(config.record_armed);
+        assert_eq!(config.record_takes, TakeStorage::Files);
+        assert_eq!(config.record_punch(), None);
+
+        let config = Config {
+            record_punch_in: 5,
+            record_punch_out: 9,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.record_punch(), Some(PunchRange::new(5, Some(9))));
+
+        let config = Config {
+            record_punch_out: 3,
+            ..Default::default()
+        };
+        assert_eq!(config.record_punch(), Some(PunchRange::new(1, Some(3))));
+
+        let config = Config {
+            record_punch_in: 9,
+            record_punch_out: 5,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_cleanup() {
+        let config = Config::default();
+        assert!(!config.record_cleanup_enabled);
+        assert_eq!(config.cleanup_overlap, OverlapFix::Keep);
+        assert_eq!(config.cleanup_grid, 0);
+
+        let json = r#"{
+            "buffer_size": 2048,
+            "min_note_duration": 0.05,
+            "pitch_threshold": 0.15,
+            "midi_port": null,
+            "velocity": 80,
+            "verbose": false,
+            "record_cleanup_enabled": true,
+            "cleanup_overlap": "legato",
+            "cleanup_grid": 16,
+            "cleanup_swing": 0.6
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.cleanup_overlap, OverlapFix::Legato);
+
+        for invalid in [
+            Config {
+                cleanup_grid: 12,
+                ..Default::default()
+            },
+            Config {
+                cleanup_swing: 0.3,
+                ..Default::default()
+            },
+            Config {
+                cleanup_velocity_peak: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
 }
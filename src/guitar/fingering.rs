@@ -0,0 +1,279 @@
+use super::{StringPosition, Tuning};
+use serde::{Deserialize, Serialize};
+
+/// Widest comfortable stretch between fretted notes of one chord, in frets
+const MAX_SPAN: u8 = 4;
+
+/// Cost of leaving a note of a chord unplayed
+const UNPLAYED_COST: f32 = 100.0;
+
+/// Cost per fret of moving the hand between chords or notes
+const HAND_MOVE_COST: f32 = 1.0;
+
+/// Cost of each fretted note; open strings need no finger
+const FRETTED_NOTE_COST: f32 = 0.5;
+
+/// Cost per fret of the first position, before the hand has a position
+const POSITION_COST: f32 = 0.3;
+
+/// Cost per fret of stretch beyond a comfortable span
+const STRETCH_COST: f32 = 4.0;
+
+/// Weight of the timbre mismatch cost
+const TIMBRE_COST: f32 = 10.0;
+
+/// A note to finger, with an optional timbre cue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteCue {
+    pub note: u8,
+    /// Harmonic brightness (0.0 to 1.0); the same pitch sounds darker on a thicker string
+    pub brightness: Option<f32>,
+}
+
+impl NoteCue {
+    pub fn new(note: u8) -> Self {
+        Self {
+            note,
+            brightness: None,
+        }
+    }
+}
+
+/// A note with the fretboard position it was played at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TabNote {
+    pub note: u8,
+    /// String index, 0 = highest-pitched string
+    pub string: usize,
+    pub fret: u8,
+}
+
+/// Infers string/fret positions for a stream of notes and chords
+///
+/// Each onset (a single note or the notes of a chord struck together) is
+/// fingered as a whole by searching every assignment of notes to distinct
+/// strings and picking the cheapest: little hand movement from the previous
+/// position, chord shapes within a comfortable span, few fretted notes and,
+/// when available, positions whose expected timbre matches the note's.
+pub struct FingeringInference {
+    tuning: Tuning,
+    capo: u8,
+    max_fret: u8,
+    /// Fret around which the hand is positioned, `None` before the first note
+    hand_position: Option<f32>,
+}
+
+impl FingeringInference {
+    pub fn new(tuning: Tuning, capo: u8, max_fret: u8) -> Self {
+        Self {
+            tuning,
+            capo,
+            max_fret,
+            hand_position: None,
+        }
+    }
+
+    /// The tuning fingerings are inferred for
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Capo fret
+    pub fn capo(&self) -> u8 {
+        self.capo
+    }
+
+    /// Finger one onset; returns a position per note, `None` for unplayable notes
+    pub fn fingering(&mut self, notes: &[NoteCue]) -> Vec<Option<StringPosition>> {
+        let options: Vec<Vec<StringPosition>> = notes
+            .iter()
+            .map(|cue| self.tuning.positions(cue.note, self.capo, self.max_fret))
+            .collect();
+
+        let mut current = Vec::with_capacity(notes.len());
+        let mut best = (f32::INFINITY, vec![None; notes.len()]);
+        self.search(notes, &options, &mut current, &mut best);
+
+        let fingering = best.1;
+        if let Some(hand) = self.hand_for(&fingering) {
+            self.hand_position = Some(hand);
+        }
+        fingering
+    }
+
+    /// Finger one onset and pair each playable note with its position
+    pub fn tab(&mut self, notes: &[NoteCue]) -> Vec<TabNote> {
+        self.fingering(notes)
+            .into_iter()
+            .zip(notes)
+            .filter_map(|(position, cue)| {
+                position.map(|p| TabNote {
+                    note: cue.note,
+                    string: p.string,
+                    fret: p.fret,
+                })
+            })
+            .collect()
+    }
+
+    /// Forget the hand position
+    pub fn reset(&mut self) {
+        self.hand_position = None;
+    }
+
+    /// Depth-first search over assignments of notes to distinct strings
+    fn search(
+        &self,
+        notes: &[NoteCue],
+        options: &[Vec<StringPosition>],
+        current: &mut Vec<Option<StringPosition>>,
+        best: &mut (f32, Vec<Option<StringPosition>>),
+    ) {
+        let index = current.len();
+        if index == notes.len() {
+            let cost = self.cost(notes, current);
+            if cost < best.0 {
+                *best = (cost, current.clone());
+            }
+            return;
+        }
+
+        for &position in &options[index] {
+            if current
+                .iter()
+                .flatten()
+                .any(|p| p.string == position.string)
+            {
+                continue;
+            }
+            current.push(Some(position));
+            self.search(notes, options, current, best);
+            current.pop();
+        }
+        current.push(None);
+        self.search(notes, options, current, best);
+        current.pop();
+    }
+
+    /// Playability cost of a complete assignment
+    fn cost(&self, notes: &[NoteCue], fingering: &[Option<StringPosition>]) -> f32 {
+        let mut cost = 0.0;
+
+        let fretted: Vec<u8> = fingering
+            .iter()
+            .flatten()
+            .filter(|p| p.fret > self.capo)
+            .map(|p| p.fret)
+            .collect();
+        if let (Some(&low), Some(&high)) = (fretted.iter().min(), fretted.iter().max()) {
+            cost += (high - low).saturating_sub(MAX_SPAN) as f32 * STRETCH_COST;
+        }
+        if let (Some(hand), Some(previous)) = (self.hand_for(fingering), self.hand_position) {
+            cost += (hand - previous).abs() * HAND_MOVE_COST;
+        } else if let Some(hand) = self.hand_for(fingering) {
+            // Without context, lower positions are easier
+            cost += (hand - self.capo as f32) * POSITION_COST;
+        }
+
+        for (cue, position) in notes.iter().zip(fingering) {
+            let Some(position) = position else {
+                cost += UNPLAYED_COST;
+                continue;
+            };
+            if position.fret > self.capo {
+                cost += FRETTED_NOTE_COST;
+            }
+            if let Some(brightness) = cue.brightness {
+                cost += (brightness - self.expected_brightness(*position)).abs() * TIMBRE_COST;
+            }
+        }
+        cost
+    }
+
+    /// Hand position implied by an assignment: the mean fretted fret
+    fn hand_for(&self, fingering: &[Option<StringPosition>]) -> Option<f32> {
+        let frets: Vec<f32> = fingering
+            .iter()
+            .flatten()
+            .filter(|p| p.fret > self.capo)
+            .map(|p| p.fret as f32)
+            .collect();
+        (!frets.is_empty()).then(|| frets.iter().sum::<f32>() / frets.len() as f32)
+    }
+
+    /// Rough brightness of a position: thicker strings and higher frets sound darker
+    fn expected_brightness(&self, position: StringPosition) -> f32 {
+        let strings = self.tuning.string_count().max(2) as f32;
+        let thickness = position.string as f32 / (strings - 1.0);
+        let fret = (position.fret - self.capo.min(position.fret)) as f32 / 24.0;
+        (0.9 - 0.5 * thickness - 0.6 * fret).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cues(notes: &[u8]) -> Vec<NoteCue> {
+        notes.iter().map(|&n| NoteCue::new(n)).collect()
+    }
+
+    #[test]
+    fn test_open_chord_shape() {
+        let mut inference = FingeringInference::new(Tuning::standard(), 0, 22);
+        // Open C major: C3 E3 G3 C4 E4
+        let tab = inference.tab(&cues(&[48, 52, 55, 60, 64]));
+        let frets: Vec<(usize, u8)> = tab.iter().map(|t| (t.string, t.fret)).collect();
+        assert_eq!(frets, vec![(4, 3), (3, 2), (2, 0), (1, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn test_barre_chord_stays_in_span() {
+        let mut inference = FingeringInference::new(Tuning::standard(), 0, 22);
+        // A major barre at the 5th fret: A2 E3 A3 C#4 E4 A4
+        let tab = inference.tab(&cues(&[45, 52, 57, 61, 64, 69]));
+        assert_eq!(tab.len(), 6);
+        let fretted: Vec<u8> = tab.iter().map(|t| t.fret).filter(|&f| f > 0).collect();
+        let span = fretted.iter().max().unwrap() - fretted.iter().min().unwrap();
+        assert!(span <= MAX_SPAN);
+    }
+
+    #[test]
+    fn test_melody_keeps_hand_position() {
+        let mut inference = FingeringInference::new(Tuning::standard(), 0, 22);
+        // Start high on the neck, then D4 should stay in position (G string, fret 7)
+        inference.tab(&cues(&[69]));
+        inference.tab(&cues(&[71]));
+        let tab = inference.tab(&cues(&[62]));
+        assert_eq!((tab[0].string, tab[0].fret), (2, 7));
+    }
+
+    #[test]
+    fn test_timbre_cue_selects_string() {
+        let mut inference = FingeringInference::new(Tuning::standard(), 0, 22);
+        // E4 played open sounds bright; played on the B string it sounds darker
+        let bright = inference.fingering(&[NoteCue {
+            note: 64,
+            brightness: Some(0.85),
+        }]);
+        assert_eq!(bright[0].unwrap().string, 0);
+
+        inference.reset();
+        let dark = inference.fingering(&[NoteCue {
+            note: 64,
+            brightness: Some(0.2),
+        }]);
+        assert!(dark[0].unwrap().string >= 2);
+    }
+
+    #[test]
+    fn test_unplayable_notes() {
+        let mut inference = FingeringInference::new(Tuning::standard(), 0, 22);
+        let fingering = inference.fingering(&cues(&[30, 64]));
+        assert_eq!(fingering[0], None);
+        assert!(fingering[1].is_some());
+        // Seven notes cannot fit on six strings
+        let tab = inference.tab(&cues(&[40, 45, 50, 55, 59, 64, 69]));
+        assert_eq!(tab.len(), 6);
+    }
+}
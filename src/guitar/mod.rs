@@ -0,0 +1,226 @@
+pub mod fingering;
+pub mod strings;
+
+pub use fingering::{FingeringInference, NoteCue, TabNote};
+pub use strings::StringAssigner;
+
+use crate::pitch::PitchDetector;
+use serde::{Deserialize, Serialize};
+
+/// Named tunings accepted by `Tuning::parse` (open strings listed low to high)
+const PRESET_TUNINGS: &[(&str, [u8; 6])] = &[
+    ("standard", [40, 45, 50, 55, 59, 64]),
+    ("drop-d", [38, 45, 50, 55, 59, 64]),
+    ("half-step-down", [39, 44, 49, 54, 58, 63]),
+    ("full-step-down", [38, 43, 48, 53, 57, 62]),
+    ("drop-c", [36, 43, 48, 53, 57, 62]),
+    ("dadgad", [38, 45, 50, 55, 57, 62]),
+    ("open-d", [38, 45, 50, 54, 57, 62]),
+    ("open-g", [38, 43, 50, 55, 59, 62]),
+    ("open-e", [40, 47, 52, 56, 59, 64]),
+];
+
+/// A position on the fretboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StringPosition {
+    /// String index, 0 = highest-pitched string (guitar string 1)
+    pub string: usize,
+    /// Fret number, 0 = open string
+    pub fret: u8,
+}
+
+/// Open-string pitches of a stringed instrument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tuning {
+    name: String,
+    /// Open-string MIDI notes, highest-pitched string first
+    strings: Vec<u8>,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl Tuning {
+    /// Standard guitar tuning (E2 A2 D3 G3 B3 E4)
+    pub fn standard() -> Self {
+        Self::parse("standard").expect("standard tuning is a preset")
+    }
+
+    /// Create a tuning from open-string notes listed low to high
+    pub fn from_notes(name: &str, low_to_high: &[u8]) -> Self {
+        Self {
+            name: name.to_string(),
+            strings: low_to_high.iter().rev().copied().collect(),
+        }
+    }
+
+    /// Parse a preset name (e.g. "drop-d") or note names low to high (e.g. "D2 A2 D3 G3 B3 E4")
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let key = spec.trim().to_lowercase();
+        if let Some((name, notes)) = PRESET_TUNINGS.iter().find(|(name, _)| *name == key) {
+            return Ok(Self::from_notes(name, notes));
+        }
+
+        let notes = spec
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|name| {
+                parse_note_name(name).ok_or_else(|| anyhow::anyhow!("Invalid note name '{}'", name))
+            })
+            .collect::<anyhow::Result<Vec<u8>>>()?;
+
+        if notes.is_empty() {
+            anyhow::bail!(
+                "Unknown tuning '{}'. Use a preset ({}) or note names such as \"E2 A2 D3 G3 B3 E4\"",
+                spec,
+                Self::preset_names().join(", ")
+            );
+        }
+        Ok(Self::from_notes(spec.trim(), &notes))
+    }
+
+    /// Names of the built-in tuning presets
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESET_TUNINGS.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Tuning name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of strings
+    pub fn string_count(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Open-string note of a string (0 = highest-pitched string)
+    pub fn open_note(&self, string: usize) -> Option<u8> {
+        self.strings.get(string).copied()
+    }
+
+    /// Open-string notes, highest-pitched string first
+    pub fn open_notes(&self) -> &[u8] {
+        &self.strings
+    }
+
+    /// Short label of a string, lower-case for the top string (e.g. "e", "B", "E")
+    pub fn string_label(&self, string: usize) -> String {
+        let Some(note) = self.open_note(string) else {
+            return String::new();
+        };
+        let name = PitchDetector::midi_to_note_name(note);
+        let letter: String = name.chars().take_while(|c| !c.is_ascii_digit()).collect();
+        if string == 0 && self.strings.len() > 1 {
+            letter.to_lowercase()
+        } else {
+            letter
+        }
+    }
+
+    /// All positions where a note can be played, given a capo and highest fret
+    ///
+    /// Frets are counted from the nut, so with a capo on fret 2 the lowest
+    /// playable fret is 2.
+    pub fn positions(&self, note: u8, capo: u8, max_fret: u8) -> Vec<StringPosition> {
+        self.strings
+            .iter()
+            .enumerate()
+            .filter_map(|(string, &open)| {
+                let fret = note.checked_sub(open)?;
+                (capo..=max_fret)
+                    .contains(&fret)
+                    .then_some(StringPosition { string, fret })
+            })
+            .collect()
+    }
+
+    /// MIDI note sounded at a fretboard position
+    pub fn note_at(&self, position: StringPosition) -> Option<u8> {
+        self.open_note(position.string)
+            .map(|open| open.saturating_add(position.fret).min(127))
+    }
+}
+
+/// Parse a note name like "E2", "F#3" or "Bb1" into a MIDI note number
+pub fn parse_note_name(name: &str) -> Option<u8> {
+    let mut chars = name.trim().chars().peekable();
+    let base = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+
+    let mut offset = 0i32;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '#' => offset += 1,
+            'b' => offset -= 1,
+            _ => break,
+        }
+        chars.next();
+    }
+
+    let octave: i32 = chars.collect::<String>().parse().ok()?;
+    let note = (octave + 1) * 12 + base + offset;
+    (0..=127).contains(&note).then_some(note as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_tuning() {
+        let tuning = Tuning::standard();
+        assert_eq!(tuning.string_count(), 6);
+        assert_eq!(tuning.open_note(0), Some(64));
+        assert_eq!(tuning.open_note(5), Some(40));
+        assert_eq!(tuning.string_label(0), "e");
+        assert_eq!(tuning.string_label(5), "E");
+    }
+
+    #[test]
+    fn test_parse_note_names() {
+        assert_eq!(parse_note_name("E2"), Some(40));
+        assert_eq!(parse_note_name("F#3"), Some(54));
+        assert_eq!(parse_note_name("Bb1"), Some(34));
+        assert_eq!(parse_note_name("C-1"), Some(0));
+        assert_eq!(parse_note_name("H2"), None);
+    }
+
+    #[test]
+    fn test_parse_custom_tuning() {
+        let tuning = Tuning::parse("D2 A2 D3 G3 B3 E4").unwrap();
+        assert_eq!(tuning.open_notes(), &[64, 59, 55, 50, 45, 38]);
+        assert_eq!(
+            Tuning::parse("Drop-D").unwrap().open_notes(),
+            tuning.open_notes()
+        );
+        assert!(Tuning::parse("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_positions_with_capo() {
+        let tuning = Tuning::standard();
+        // E4 can be played open on string 1, fret 5 on B, fret 9 on G...
+        let positions = tuning.positions(64, 0, 22);
+        assert!(positions.contains(&StringPosition { string: 0, fret: 0 }));
+        assert!(positions.contains(&StringPosition { string: 1, fret: 5 }));
+        // With a capo on 2 the open string is no longer available
+        let positions = tuning.positions(64, 2, 22);
+        assert!(!positions.contains(&StringPosition { string: 0, fret: 0 }));
+        assert_eq!(
+            tuning.note_at(StringPosition { string: 1, fret: 5 }),
+            Some(64)
+        );
+    }
+}
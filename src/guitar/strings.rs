@@ -0,0 +1,152 @@
+use super::{StringPosition, Tuning};
+
+/// Assigns sounding notes to guitar strings so each string can use its own MIDI channel
+///
+/// Each string holds at most one note. A new note goes to the free string whose
+/// fret is closest to the current hand position, which keeps melodic lines on
+/// neighbouring strings and spreads chords across the neck the way they are played.
+pub struct StringAssigner {
+    tuning: Tuning,
+    capo: u8,
+    max_fret: u8,
+    /// Note currently sounding on each string
+    sounding: Vec<Option<u8>>,
+    /// Fret around which the hand is currently positioned
+    hand_position: f32,
+}
+
+impl StringAssigner {
+    pub fn new(tuning: Tuning, capo: u8, max_fret: u8) -> Self {
+        let sounding = vec![None; tuning.string_count()];
+        Self {
+            tuning,
+            capo,
+            max_fret,
+            sounding,
+            hand_position: capo as f32,
+        }
+    }
+
+    /// The tuning used for assignment
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Assign a note to a free string
+    ///
+    /// Returns `None` if the note is outside the fretboard range or every string
+    /// that could play it is already sounding.
+    pub fn assign(&mut self, note: u8) -> Option<StringPosition> {
+        if let Some(position) = self.position_of(note) {
+            return Some(position);
+        }
+
+        let position = self
+            .tuning
+            .positions(note, self.capo, self.max_fret)
+            .into_iter()
+            .filter(|p| self.sounding[p.string].is_none())
+            .min_by(|a, b| self.cost(*a).total_cmp(&self.cost(*b)))?;
+
+        self.sounding[position.string] = Some(note);
+        if position.fret > self.capo {
+            // Open strings don't move the hand
+            self.hand_position = self.hand_position * 0.5 + position.fret as f32 * 0.5;
+        }
+        Some(position)
+    }
+
+    /// Release a note and return the position it was played at
+    pub fn release(&mut self, note: u8) -> Option<StringPosition> {
+        let position = self.position_of(note)?;
+        self.sounding[position.string] = None;
+        Some(position)
+    }
+
+    /// Get the position of a sounding note
+    pub fn position_of(&self, note: u8) -> Option<StringPosition> {
+        let string = self.sounding.iter().position(|&n| n == Some(note))?;
+        Some(StringPosition {
+            string,
+            fret: note - self.tuning.open_note(string)?,
+        })
+    }
+
+    /// Release all strings
+    pub fn reset(&mut self) {
+        self.sounding.iter_mut().for_each(|s| *s = None);
+    }
+
+    /// Playability cost of a position: distance from the hand, open strings are cheap
+    fn cost(&self, position: StringPosition) -> f32 {
+        if position.fret == self.capo {
+            0.5
+        } else {
+            (position.fret as f32 - self.hand_position).abs()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_open_strings() {
+        let mut assigner = StringAssigner::new(Tuning::standard(), 0, 22);
+        let position = assigner.assign(40).unwrap();
+        assert_eq!(position, StringPosition { string: 5, fret: 0 });
+        assert_eq!(assigner.position_of(40), Some(position));
+    }
+
+    #[test]
+    fn test_chord_uses_distinct_strings() {
+        let mut assigner = StringAssigner::new(Tuning::standard(), 0, 22);
+        // Open E major: E2 B2 E3 G#3 B3 E4
+        let chord = [40, 47, 52, 56, 59, 64];
+        let mut strings: Vec<usize> = chord
+            .iter()
+            .map(|&n| assigner.assign(n).unwrap().string)
+            .collect();
+        strings.sort();
+        strings.dedup();
+        assert_eq!(strings.len(), 6);
+    }
+
+    #[test]
+    fn test_release_frees_string() {
+        let mut assigner = StringAssigner::new(Tuning::standard(), 0, 22);
+        let first = assigner.assign(45).unwrap();
+        assert_eq!(assigner.release(45), Some(first));
+        assert_eq!(assigner.position_of(45), None);
+        assert_eq!(assigner.assign(45), Some(first));
+    }
+
+    #[test]
+    fn test_out_of_range_note() {
+        let mut assigner = StringAssigner::new(Tuning::standard(), 0, 22);
+        assert_eq!(assigner.assign(30), None);
+    }
+
+    #[test]
+    fn test_hand_position_continuity() {
+        let mut assigner = StringAssigner::new(Tuning::standard(), 0, 22);
+        // Without context D4 goes to the lowest fret (B string, fret 3)
+        assert_eq!(
+            assigner.assign(62),
+            Some(StringPosition { string: 1, fret: 3 })
+        );
+        assigner.release(62);
+
+        // After playing up the neck, D4 stays in position (G string, fret 7)
+        let mut assigner = StringAssigner::new(Tuning::standard(), 0, 22);
+        for note in [76, 71] {
+            assigner.assign(note);
+            assigner.release(note);
+        }
+        assert_eq!(
+            assigner.assign(62),
+            Some(StringPosition { string: 2, fret: 7 })
+        );
+    }
+}
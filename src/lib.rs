@@ -2,7 +2,14 @@
 pub mod audio;
 pub mod config;
 pub mod fuzzy;
+pub mod guitar;
+pub mod lifecycle;
 pub mod midi;
+pub mod notation;
+pub mod notelog;
 pub mod pitch;
 pub mod processor;
+pub mod rhythm;
+pub mod theory;
+pub mod tuner;
 pub mod web;
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Controls a running `StreamProcessor`, `TunerProcessor` or `MidiPlayer` from other threads
+///
+/// The flags are checked between audio chunks or MIDI events, so requests
+/// take effect within about 100 ms.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessorHandle {
+    shutdown: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    armed: Arc<AtomicBool>,
+}
+
+impl ProcessorHandle {
+    /// Make `StreamProcessor::start`, `TunerProcessor::start` or `MidiPlayer::play` return
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Mute the processor: sounding notes are released and incoming audio is
+    /// dropped, while the audio stream and the recording keep running
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Pause a running processor, or resume a paused one; returns whether it is now paused
+    pub fn toggle_pause(&self) -> bool {
+        !self.paused.fetch_xor(true, Ordering::SeqCst)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Arm recording, starting a new take
+    pub fn arm(&self) {
+        self.armed.store(true, Ordering::SeqCst);
+    }
+
+    /// Disarm recording, ending the current take
+    pub fn disarm(&self) {
+        self.armed.store(false, Ordering::SeqCst);
+    }
+
+    /// Arm or disarm recording; returns whether it is now armed
+    pub fn toggle_arm(&self) -> bool {
+        !self.armed.fetch_xor(true, Ordering::SeqCst)
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_processor_handle() {
+        let handle = ProcessorHandle::default();
+        let remote = handle.clone();
+        assert!(!handle.is_shutdown());
+        assert!(!handle.is_paused());
+
+        remote.pause();
+        assert!(handle.is_paused());
+        remote.resume();
+        assert!(!handle.is_paused());
+        assert!(remote.toggle_pause());
+        assert!(handle.is_paused());
+        assert!(!remote.toggle_pause());
+        assert!(!handle.is_paused());
+
+        assert!(!handle.is_armed());
+        remote.arm();
+        assert!(handle.is_armed());
+        assert!(!remote.toggle_arm());
+        assert!(!handle.is_armed());
+
+        remote.shutdown();
+        assert!(handle.is_shutdown());
+    }
+}
@@ -1,7 +1,23 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use instrument_to_midi::{config::Config, midi, processor::StreamProcessor, web::WebServer};
+use instrument_to_midi::{
+    audio::{mix_stereo, read_wav_mono, write_wav, PreviewSynth, SynthVoice},
+    config::{Config, StringAssignmentMode},
+    guitar::parse_note_name,
+    lifecycle::ProcessorHandle,
+    midi::{
+        self, Cleanup, MidiPlayer, MidiRecorder, OverlapFix, PlayOptions, TakeStorage, TrackLayout,
+    },
+    notation::{self, ExportFormat, KeySignature, Take, TimeSignature},
+    notelog::NoteLogFormat,
+    pitch::PitchDetector,
+    processor::StreamProcessor,
+    theory::SnapDirection,
+    tuner::{Tuner, TunerProcessor, TunerReading},
+    web::WebServer,
+};
 use log::info;
+use std::io::Write;
 
 #[derive(Parser)]
 #[command(name = "instrument_to_midi")]
@@ -45,6 +61,58 @@ enum Commands {
         #[arg(short, long)]
         output: Option<String>,
 
+        /// Tempo of the recording in BPM (default: 120)
+        #[arg(long)]
+        bpm: Option<f32>,
+
+        /// Time signature of the recording, e.g. 3/4 or 6/8 (default: 4/4)
+        #[arg(long)]
+        time_signature: Option<TimeSignature>,
+
+        /// Resolution of the recording in ticks per quarter note (default: 480)
+        #[arg(long)]
+        ppq: Option<u16>,
+
+        /// Recording tracks: single, channel (per string/channel), zone, or detection (raw vs fuzzy-resolved)
+        #[arg(long)]
+        tracks: Option<TrackLayout>,
+
+        /// Write the tracked tempo into the recording so bars follow the performance
+        #[arg(long)]
+        track_tempo: bool,
+
+        /// Clean up the recording when saving (see the clean command), keeping the raw take
+        #[arg(long)]
+        clean: bool,
+
+        /// Do not capture the input audio to a WAV file alongside the recording
+        #[arg(long)]
+        no_audio: bool,
+
+        /// Write a note log next to the recording: jsonl or csv
+        #[arg(long)]
+        note_log: Option<NoteLogFormat>,
+
+        /// Keep takes as files (take.mid, take-take2.mid, ...) or as tracks of one file
+        #[arg(long)]
+        takes: Option<TakeStorage>,
+
+        /// Start disarmed: the first take starts once armed (r and Enter)
+        #[arg(long)]
+        disarmed: bool,
+
+        /// Only record from this bar of each take on
+        #[arg(long, value_name = "BAR")]
+        punch_in: Option<u32>,
+
+        /// Stop recording at this bar of each take
+        #[arg(long, value_name = "BAR")]
+        punch_out: Option<u32>,
+
+        /// Layer the recording onto an existing MIDI file as new tracks (implies --record)
+        #[arg(long, value_name = "FILE")]
+        overdub: Option<String>,
+
         /// Enable web UI for monitoring (default port: 8080)
         #[arg(short, long)]
         web: bool,
@@ -56,6 +124,265 @@ enum Commands {
         /// Enable polyphonic pitch detection (detect multiple simultaneous notes)
         #[arg(long)]
         polyphonic: bool,
+
+        /// Enable MPE output (per-note pitch bend, pressure and timbre on member channels)
+        #[arg(long)]
+        mpe: bool,
+
+        /// Send each guitar string on its own MIDI channel (channels 1-6)
+        #[arg(long)]
+        string_channels: bool,
+
+        /// Read one string per audio input channel (hexaphonic pickup); implies --string-channels
+        #[arg(long)]
+        hex: bool,
+
+        /// Guitar tuning: preset (standard, drop-d, dadgad, ...) or notes low to high ("D2 A2 D3 G3 B3 E4")
+        #[arg(long)]
+        tuning: Option<String>,
+
+        /// Capo fret
+        #[arg(long)]
+        capo: Option<u8>,
+
+        /// MIDI output channel (1-16)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=16))]
+        channel: Option<u8>,
+
+        /// Transpose output by semitones (virtual capo)
+        #[arg(long, allow_hyphen_values = true)]
+        transpose: Option<i8>,
+
+        /// Transpose output by octaves
+        #[arg(long, allow_hyphen_values = true)]
+        octave: Option<i8>,
+
+        /// Lowest note to send, as a MIDI number or name (e.g. 40 or E2)
+        #[arg(long)]
+        low_note: Option<String>,
+
+        /// Highest note to send, as a MIDI number or name (e.g. 88 or E6)
+        #[arg(long)]
+        high_note: Option<String>,
+
+        /// Snap notes to a scale ("A minor", "E blues", "D 0,2,3,7,9") or "auto" for the detected key
+        #[arg(long)]
+        scale: Option<String>,
+
+        /// Direction notes are snapped to the scale: nearest, up or down
+        #[arg(long)]
+        snap: Option<SnapDirection>,
+    },
+
+    /// Tune the instrument (chromatic, or guided string by string)
+    Tune {
+        /// Guide through the strings of a tuning: preset or notes low to high
+        #[arg(long)]
+        tuning: Option<String>,
+
+        /// Guide through the strings of the configured tuning
+        #[arg(long)]
+        guided: bool,
+
+        /// String to tune in guided mode (1 = highest-pitched string); omit to follow the played pitch
+        #[arg(long)]
+        string: Option<usize>,
+
+        /// Audio buffer size
+        #[arg(short, long, default_value = "2048")]
+        buffer_size: usize,
+
+        /// Configuration file path
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Enable the web tuner page at /tuner
+        #[arg(short, long)]
+        web: bool,
+
+        /// Web UI port (default: 8080)
+        #[arg(long, default_value = "8080")]
+        web_port: u16,
+
+        /// Enable verbose logging
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Export a MIDI recording as tablature or notation
+    Export {
+        /// MIDI file to export
+        input: String,
+
+        /// Output format: ascii-tab, guitar-pro (MusicXML with tablature), musicxml, lilypond or abc
+        #[arg(short, long, default_value = "ascii-tab")]
+        format: ExportFormat,
+
+        /// Output file path (defaults to the input file with the format's extension)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Guitar tuning: preset or notes low to high
+        #[arg(long)]
+        tuning: Option<String>,
+
+        /// Capo fret
+        #[arg(long)]
+        capo: Option<u8>,
+
+        /// Quantization grid as a note value: 4, 8, 16 or 32
+        #[arg(long, default_value = "16", value_parser = ["4", "8", "16", "32"])]
+        grid: String,
+
+        /// Time signature, overriding the file's (e.g. 3/4)
+        #[arg(long)]
+        time_signature: Option<TimeSignature>,
+
+        /// Key, overriding the file's or the detected one (e.g. "Eb major" or "F# minor")
+        #[arg(long)]
+        key: Option<KeySignature>,
+
+        /// Configuration file path
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+
+    /// Clean up a MIDI recording: drop blips, merge fragments, fix overlaps, quantize
+    Clean {
+        /// MIDI file to clean up (left unchanged)
+        input: String,
+
+        /// Output file path (defaults to the input file with a .clean.mid extension)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Drop notes shorter than this many milliseconds
+        #[arg(long)]
+        min_length: Option<f32>,
+
+        /// Merge same-pitch notes separated by gaps up to this many milliseconds
+        #[arg(long)]
+        merge_gap: Option<f32>,
+
+        /// Overlapping notes: keep, trim (end at the next note) or legato
+        #[arg(long)]
+        overlap: Option<OverlapFix>,
+
+        /// Quantization grid as a note value: 4, 8, 16 or 32
+        #[arg(long, value_parser = ["4", "8", "16", "32"])]
+        grid: Option<String>,
+
+        /// How far notes move towards the grid, 0.0-1.0
+        #[arg(long)]
+        strength: Option<f32>,
+
+        /// Swing of the grid, 0.5 (straight) to 0.75
+        #[arg(long)]
+        swing: Option<f32>,
+
+        /// Scale velocities so the loudest note has this velocity
+        #[arg(long)]
+        velocity: Option<u8>,
+
+        /// Configuration file path
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+
+    /// Rebuild a MIDI file from the journal of an interrupted recording
+    Recover {
+        /// Journal file left next to the recording (e.g. recording_20240101_120000.journal)
+        input: String,
+
+        /// Output file path (defaults to the journal's name with a .recovered.mid extension)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Convert a WAV file to a MIDI file offline, as streaming it would
+    Convert {
+        /// WAV file to convert, e.g. the audio captured with a recording
+        input: String,
+
+        /// Output file path (defaults to the input file with a .converted.mid extension)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Also write a note log next to the output: jsonl or csv
+        #[arg(long)]
+        note_log: Option<NoteLogFormat>,
+
+        /// Tempo of the recording in BPM (default: 120)
+        #[arg(long)]
+        bpm: Option<f32>,
+
+        /// Enable polyphonic pitch detection
+        #[arg(long)]
+        polyphonic: bool,
+
+        /// Read one string per audio channel (hexaphonic recording)
+        #[arg(long)]
+        hex: bool,
+
+        /// Configuration file path
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Enable verbose logging
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Play a MIDI file to a MIDI output port
+    Play {
+        /// MIDI file to play
+        input: String,
+
+        /// MIDI output port name (creates virtual port if not specified)
+        #[arg(short, long)]
+        port: Option<String>,
+
+        /// Start this many seconds into the file
+        #[arg(long, value_name = "SECONDS")]
+        start: Option<f32>,
+
+        /// Loop back to the start at the end of the file until stopped
+        #[arg(long = "loop")]
+        looped: bool,
+
+        /// Enable verbose logging
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Render a MIDI file to a WAV file with the preview synthesizer
+    Render {
+        /// MIDI file to render
+        input: String,
+
+        /// Output file path (defaults to the input file with a .preview.wav or .compare.wav extension)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Synth voice: pluck (Karplus-Strong string) or additive
+        #[arg(long, default_value = "pluck")]
+        voice: SynthVoice,
+
+        /// Sample rate of the render (default: 44100, or that of the compared audio)
+        #[arg(long)]
+        sample_rate: Option<u32>,
+
+        /// Pitch bend range in semitones of channels the file sets none for (default: pitch_bend_range)
+        #[arg(long)]
+        bend_range: Option<f32>,
+
+        /// Audio to compare with: writes it on the left and the render on the right of a stereo file
+        #[arg(long, value_name = "WAV")]
+        compare: Option<String>,
+
+        /// Configuration file path
+        #[arg(short, long)]
+        config: Option<String>,
     },
 
     /// List available MIDI output ports
@@ -82,20 +409,36 @@ async fn main() -> Result<()> {
             config: config_file,
             record,
             output,
+            bpm,
+            time_signature,
+            ppq,
+            tracks,
+            track_tempo,
+            clean,
+            no_audio,
+            note_log,
+            takes,
+            disarmed,
+            punch_in,
+            punch_out,
+            overdub,
             web,
             web_port,
             polyphonic,
+            mpe,
+            string_channels,
+            hex,
+            tuning,
+            capo,
+            channel,
+            transpose,
+            octave,
+            low_note,
+            high_note,
+            scale,
+            snap,
         } => {
-            // Initialize logger
-            if verbose {
-                env_logger::Builder::from_default_env()
-                    .filter_level(log::LevelFilter::Debug)
-                    .init();
-            } else {
-                env_logger::Builder::from_default_env()
-                    .filter_level(log::LevelFilter::Info)
-                    .init();
-            }
+            init_logger(verbose);
 
             // Load or create config
             let mut config = if let Some(path) = config_file {
@@ -111,7 +454,83 @@ async fn main() -> Result<()> {
             config.verbose = verbose;
             config.record_enabled = record;
             config.record_output = output;
+            if let Some(bpm) = bpm {
+                config.record_bpm = bpm;
+            }
+            if let Some(time_signature) = time_signature {
+                config.record_time_signature = time_signature.to_string();
+            }
+            if let Some(ppq) = ppq {
+                config.record_ppq = ppq;
+            }
+            if let Some(tracks) = tracks {
+                config.record_tracks = tracks;
+            }
+            if clean {
+                config.record_cleanup_enabled = true;
+            }
+            if track_tempo {
+                config.record_tempo_tracking = true;
+                config.beat_tracking_enabled = true;
+            }
+            if no_audio {
+                config.record_audio_enabled = false;
+            }
+            if note_log.is_some() {
+                config.record_note_log = note_log;
+            }
+            if let Some(takes) = takes {
+                config.record_takes = takes;
+            }
+            if disarmed {
+                config.record_armed = false;
+            }
+            if let Some(bar) = punch_in {
+                config.record_punch_in = bar;
+            }
+            if let Some(bar) = punch_out {
+                config.record_punch_out = bar;
+            }
+            if overdub.is_some() {
+                config.record_enabled = true;
+                config.record_overdub = overdub;
+            }
             config.polyphonic_enabled = polyphonic;
+            config.mpe_enabled = mpe || config.mpe_enabled;
+            config.string_channels_enabled =
+                string_channels || hex || config.string_channels_enabled;
+            if hex {
+                config.string_assignment = StringAssignmentMode::InputChannel;
+            }
+            if let Some(tuning) = tuning {
+                config.tuning = tuning;
+            }
+            if let Some(capo) = capo {
+                config.capo = capo;
+            }
+            if let Some(channel) = channel {
+                config.midi_channel = channel - 1;
+            }
+            if let Some(transpose) = transpose {
+                config.transpose_semitones = transpose;
+            }
+            if let Some(octave) = octave {
+                config.transpose_octaves = octave;
+            }
+            if let Some(note) = low_note {
+                config.note_range_low = parse_note_arg(&note)?;
+            }
+            if let Some(note) = high_note {
+                config.note_range_high = parse_note_arg(&note)?;
+            }
+            if let Some(scale) = scale {
+                config.scale_quantize_enabled = true;
+                config.scale = (scale != "auto").then_some(scale);
+            }
+            if let Some(snap) = snap {
+                config.scale_snap = snap;
+            }
+            config.validate()?;
 
             info!("Starting instrument to MIDI converter...");
             info!("Buffer size: {}", config.buffer_size);
@@ -119,8 +538,38 @@ async fn main() -> Result<()> {
             if config.polyphonic_enabled {
                 info!("Polyphonic mode enabled");
             }
+            if config.mpe_enabled {
+                info!("MPE output enabled");
+            }
+            if config.string_channels_enabled {
+                info!(
+                    "Per-string MIDI channels enabled (tuning: {}, capo: {})",
+                    config.tuning, config.capo
+                );
+            }
+            if config.midi_channel != 0 {
+                info!("MIDI channel: {}", config.midi_channel + 1);
+            }
             if config.record_enabled {
-                info!("Recording enabled");
+                info!(
+                    "Recording enabled ({} BPM, {}, {} PPQ)",
+                    config.record_bpm, config.record_time_signature, config.record_ppq
+                );
+                if config.record_tempo_tracking {
+                    info!("Recording tempo follows the tracked beat");
+                }
+                if config.record_cleanup_enabled {
+                    info!("Recordings are cleaned up when saved, the raw take is kept");
+                }
+                if let Some(punch) = config.record_punch() {
+                    match punch.end {
+                        Some(end) => info!("Punch in at bar {}, out at bar {}", punch.start, end),
+                        None => info!("Punch in at bar {}", punch.start),
+                    }
+                }
+                if let Some(ref path) = config.record_overdub {
+                    info!("Overdubbing onto {}", path);
+                }
                 if let Some(ref path) = config.record_output {
                     info!("Recording output: {}", path);
                 }
@@ -128,28 +577,382 @@ async fn main() -> Result<()> {
 
             // Create and start processor
             let mut processor = StreamProcessor::new(config)?;
+            let handle = processor.handle();
 
             // Start web server if requested
+            let mut web_task = None;
             if web {
                 info!("Starting web UI on port {}", web_port);
                 let web_server = WebServer::new(web_port);
                 let event_sender = web_server.event_sender();
+                let shutdown = web_server.shutdown_handle();
 
                 // Set the event sender in the processor
                 processor.set_web_event_sender(event_sender);
 
                 // Spawn web server in a background task
-                tokio::spawn(async move {
+                let task = tokio::spawn(async move {
                     if let Err(e) = web_server.start().await {
                         eprintln!("Web server error: {}", e);
                     }
                 });
+                web_task = Some((shutdown, task));
 
                 info!("Web UI available at http://127.0.0.1:{}", web_port);
             }
 
-            processor.start()?;
+            tokio::spawn(shutdown_on_signal(handle.clone()));
+            spawn_terminal_controls(handle);
+            info!("Press Enter to pause or resume, r and Enter to arm or disarm recording, Ctrl+C to stop");
+
+            let result = processor.start();
+            // Silence the outputs and save the recording even if processing failed
+            let stopped = processor.stop();
+
+            if let Some((shutdown, task)) = web_task {
+                shutdown.shutdown();
+                let _ = task.await;
+            }
+
+            result.and(stopped)
+        }
+
+        Commands::Tune {
+            tuning,
+            guided,
+            string,
+            buffer_size,
+            config: config_file,
+            web,
+            web_port,
+            verbose,
+        } => {
+            init_logger(verbose);
+
+            let mut config = if let Some(path) = config_file {
+                Config::from_file(&path)?
+            } else {
+                Config::default()
+            };
+            config.buffer_size = buffer_size;
+            let guided = guided || tuning.is_some() || string.is_some();
+            if let Some(tuning) = tuning {
+                config.tuning = tuning;
+            }
+            config.validate()?;
+
+            let tuner = if guided {
+                let tuning = config.guitar_tuning()?;
+                let mut tuner = Tuner::guided(tuning.clone());
+                if let Some(string) = string {
+                    if !(1..=tuning.string_count()).contains(&string) {
+                        anyhow::bail!("--string must be between 1 and {}", tuning.string_count());
+                    }
+                    tuner.set_target_string(Some(string - 1));
+                }
+                let strings: Vec<String> = tuning
+                    .open_notes()
+                    .iter()
+                    .rev()
+                    .map(|&note| PitchDetector::midi_to_note_name(note))
+                    .collect();
+                info!("Guided tuning: {} ({})", tuning.name(), strings.join(" "));
+                tuner
+            } else {
+                info!("Chromatic tuner");
+                Tuner::chromatic()
+            };
+
+            let mut processor = TunerProcessor::new(&config, tuner)?;
+            let (reading_tx, reading_rx) = crossbeam_channel::unbounded();
+            processor.set_reading_sender(reading_tx);
+            std::thread::spawn(move || {
+                for reading in reading_rx {
+                    print_tuner_reading(&reading);
+                }
+            });
+
+            if web {
+                let web_server = WebServer::new(web_port);
+                processor.set_web_event_sender(web_server.event_sender());
+                tokio::spawn(async move {
+                    if let Err(e) = web_server.start().await {
+                        eprintln!("Web server error: {}", e);
+                    }
+                });
+                info!("Tuner available at http://127.0.0.1:{}/tuner", web_port);
+            }
+
+            tokio::spawn(shutdown_on_signal(processor.handle()));
+
+            let result = processor.start();
+            println!();
+            result
+        }
+
+        Commands::Export {
+            input,
+            format,
+            output,
+            tuning,
+            capo,
+            grid,
+            time_signature,
+            key,
+            config: config_file,
+        } => {
+            init_logger(false);
+
+            let mut config = if let Some(path) = config_file {
+                Config::from_file(&path)?
+            } else {
+                Config::default()
+            };
+            if let Some(tuning) = tuning {
+                config.tuning = tuning;
+            }
+            if let Some(capo) = capo {
+                config.capo = capo;
+            }
+            config.validate()?;
+
+            let mut take = Take::load(&input)?;
+            if let Some(time_signature) = time_signature {
+                take.time_signature = time_signature;
+            }
+            if key.is_some() {
+                take.key_signature = key;
+            }
+            let divisions = grid.parse::<u32>()? / 4;
+            let text = notation::export(&take, format, &config, divisions)?;
 
+            let output = output.unwrap_or_else(|| {
+                std::path::Path::new(&input)
+                    .with_extension(format.extension())
+                    .to_string_lossy()
+                    .into_owned()
+            });
+            std::fs::write(&output, text)?;
+            println!("Exported {} notes to {}", take.notes.len(), output);
+            Ok(())
+        }
+
+        Commands::Clean {
+            input,
+            output,
+            min_length,
+            merge_gap,
+            overlap,
+            grid,
+            strength,
+            swing,
+            velocity,
+            config: config_file,
+        } => {
+            init_logger(false);
+
+            let mut config = if let Some(path) = config_file {
+                Config::from_file(&path)?
+            } else {
+                Config::default()
+            };
+            if let Some(ms) = min_length {
+                config.cleanup_min_note_length = ms / 1000.0;
+            }
+            if let Some(ms) = merge_gap {
+                config.cleanup_merge_gap = ms / 1000.0;
+            }
+            if let Some(overlap) = overlap {
+                config.cleanup_overlap = overlap;
+            }
+            if let Some(grid) = grid {
+                config.cleanup_grid = grid.parse()?;
+            }
+            if let Some(strength) = strength {
+                config.cleanup_quantize_strength = strength;
+            }
+            if let Some(swing) = swing {
+                config.cleanup_swing = swing;
+            }
+            if velocity.is_some() {
+                config.cleanup_velocity_peak = velocity;
+            }
+            config.validate()?;
+
+            let input = std::path::Path::new(&input);
+            let output = output.map(std::path::PathBuf::from).unwrap_or_else(|| {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                input.with_file_name(format!("{}.clean.mid", stem))
+            });
+            let notes = Cleanup::from_config(&config).apply_file(input, &output)?;
+            println!("Cleaned up {} notes into {}", notes, output.display());
+            Ok(())
+        }
+
+        Commands::Recover { input, output } => {
+            init_logger(false);
+
+            let recorder = MidiRecorder::recover(&input)?;
+            if recorder.event_count() == 0 {
+                anyhow::bail!("The journal holds no recorded events");
+            }
+            let input = std::path::Path::new(&input);
+            let output = output.map(std::path::PathBuf::from).unwrap_or_else(|| {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                input.with_file_name(format!("{}.recovered.mid", stem))
+            });
+            recorder.save(&output)?;
+            println!(
+                "Recovered {} events into {}",
+                recorder.event_count(),
+                output.display()
+            );
+            Ok(())
+        }
+
+        Commands::Convert {
+            input,
+            output,
+            note_log,
+            bpm,
+            polyphonic,
+            hex,
+            config: config_file,
+            verbose,
+        } => {
+            init_logger(verbose);
+
+            let mut config = if let Some(path) = config_file {
+                Config::from_file(&path)?
+            } else {
+                Config::default()
+            };
+            let input = std::path::Path::new(&input);
+            let output = output.map(std::path::PathBuf::from).unwrap_or_else(|| {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                input.with_file_name(format!("{}.converted.mid", stem))
+            });
+            config.record_enabled = true;
+            config.record_output = Some(output.to_string_lossy().into_owned());
+            // The input is already on disk and conversion does not need recovering
+            config.record_audio_enabled = false;
+            config.record_journal_enabled = false;
+            config.record_autosave_interval = 0.0;
+            config.record_armed = true;
+            config.record_overdub = None;
+            if note_log.is_some() {
+                config.record_note_log = note_log;
+            }
+            if let Some(bpm) = bpm {
+                config.record_bpm = bpm;
+            }
+            config.polyphonic_enabled = polyphonic || config.polyphonic_enabled;
+            if hex {
+                config.string_channels_enabled = true;
+                config.string_assignment = StringAssignmentMode::InputChannel;
+            }
+            config.validate()?;
+
+            let mut processor = StreamProcessor::from_file(config, input)?;
+            let result = processor.start();
+            let stopped = processor.stop();
+            result.and(stopped)?;
+            println!("Converted {} into {}", input.display(), output.display());
+            Ok(())
+        }
+
+        Commands::Play {
+            input,
+            port,
+            start,
+            looped,
+            verbose,
+        } => {
+            init_logger(verbose);
+
+            let start = start.unwrap_or(0.0);
+            if !start.is_finite() || start < 0.0 {
+                anyhow::bail!("Start offset must be at least 0 seconds");
+            }
+            let player = MidiPlayer::load(&input)?;
+            let mut output = midi::MidiOutputHandler::new()?;
+            output.connect(port.as_deref())?;
+            info!(
+                "Playing {} ({:.1}s, {} events){}",
+                input,
+                player.length().as_secs_f32(),
+                player.event_count(),
+                if looped { ", looped" } else { "" }
+            );
+
+            let handle = ProcessorHandle::default();
+            tokio::spawn(shutdown_on_signal(handle.clone()));
+            spawn_terminal_controls(handle.clone());
+            info!("Press Enter to pause or resume, Ctrl+C to stop");
+
+            let options = PlayOptions {
+                start: std::time::Duration::from_secs_f32(start),
+                looped,
+            };
+            player.play(&mut output, options, &handle)
+        }
+
+        Commands::Render {
+            input,
+            output,
+            voice,
+            sample_rate,
+            bend_range,
+            compare,
+            config: config_file,
+        } => {
+            init_logger(false);
+
+            let config = if let Some(path) = config_file {
+                Config::from_file(&path)?
+            } else {
+                Config::default()
+            };
+            let original = compare
+                .as_deref()
+                .map(|path| read_wav_mono(std::path::Path::new(path)))
+                .transpose()?;
+            let sample_rate = match (&original, sample_rate) {
+                (Some((_, rate)), Some(requested)) if *rate != requested => anyhow::bail!(
+                    "The render must have the sample rate of the compared audio ({} Hz)",
+                    rate
+                ),
+                (Some((_, rate)), _) => *rate,
+                (None, requested) => requested.unwrap_or(44_100),
+            };
+
+            let player = MidiPlayer::load(&input)?;
+            let mut synth = PreviewSynth::new(sample_rate, voice);
+            synth.set_bend_range(bend_range.unwrap_or(config.pitch_bend_range));
+            let render = synth.render(&player);
+
+            let input = std::path::Path::new(&input);
+            let extension = if original.is_some() {
+                "compare.wav"
+            } else {
+                "preview.wav"
+            };
+            let output = output.map(std::path::PathBuf::from).unwrap_or_else(|| {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                input.with_file_name(format!("{}.{}", stem, extension))
+            });
+            match original {
+                Some((original, _)) => {
+                    write_wav(&output, sample_rate, 2, &mix_stereo(&original, &render))?
+                }
+                None => write_wav(&output, sample_rate, 1, &render)?,
+            }
+            println!(
+                "Rendered {} ({:.1}s) into {}",
+                input.display(),
+                render.len() as f32 / sample_rate as f32,
+                output.display()
+            );
             Ok(())
         }
 
@@ -174,3 +977,108 @@ async fn main() -> Result<()> {
         }
     }
 }
+
+/// Draw a tuner reading over the previous one
+fn print_tuner_reading(reading: &TunerReading) {
+    let string = reading
+        .string
+        .map(|s| format!("string {} ", s + 1))
+        .unwrap_or_default();
+    print!(
+        "\r{}{:<4} {} {:+6.1} cents {:>8.2} Hz {}  ",
+        string,
+        reading.note_name,
+        reading.meter(21),
+        reading.cents,
+        reading.frequency,
+        if reading.in_tune() {
+            "in tune"
+        } else {
+            "       "
+        }
+    );
+    let _ = std::io::stdout().flush();
+}
+
+/// Shut the processor down on SIGINT (Ctrl+C) or SIGTERM
+///
+/// A second signal exits right away, for when shutting down hangs.
+async fn shutdown_on_signal(handle: ProcessorHandle) {
+    wait_for_signal().await;
+    info!("Shutting down, press Ctrl+C again to exit immediately");
+    handle.shutdown();
+
+    wait_for_signal().await;
+    std::process::exit(130);
+}
+
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Control the processor from the terminal: Enter pauses or resumes, r and
+/// Enter arms or disarms recording
+fn spawn_terminal_controls(handle: ProcessorHandle) {
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        // Stops at the end of input, e.g. when stdin is not a terminal
+        while std::io::stdin()
+            .read_line(&mut line)
+            .is_ok_and(|read| read > 0)
+        {
+            if handle.is_shutdown() {
+                break;
+            }
+            match line.trim() {
+                "" => {
+                    handle.toggle_pause();
+                }
+                "r" => {
+                    handle.toggle_arm();
+                }
+                other => eprintln!("Unknown command '{}'", other),
+            }
+            line.clear();
+        }
+    });
+}
+
+/// Initialize the logger, with debug output when verbose
+fn init_logger(verbose: bool) {
+    let level = if verbose {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    };
+    env_logger::Builder::from_default_env()
+        .filter_level(level)
+        .init();
+}
+
+/// Parse a note given as a MIDI number or a note name
+fn parse_note_arg(value: &str) -> Result<u8> {
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|&note| note <= 127)
+        .or_else(|| parse_note_name(value))
+        .ok_or_else(|| anyhow::anyhow!("Invalid note '{}'", value))
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::SharedRecorder;
+
+/// Saves a recording at an interval from a background thread
+///
+/// Only a snapshot of the recording is taken under the recorder's lock, so
+/// encoding and writing the file never hold up the processing of the stream.
+/// Autosaves go to the output file, so an interrupted session leaves a
+/// playable take.
+pub struct Autosave {
+    stop: Option<Sender<()>>,
+    saver: Option<JoinHandle<()>>,
+}
+
+impl Autosave {
+    /// Start saving the recording to `path` every `interval`
+    pub fn start(recorder: SharedRecorder, path: PathBuf, interval: Duration) -> Result<Self> {
+        let (stop, stopped) = bounded::<()>(1);
+        let saver = std::thread::Builder::new()
+            .name("autosave".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    save(&recorder, &path);
+                }
+            })
+            .context("Failed to start the autosave thread")?;
+        Ok(Self {
+            stop: Some(stop),
+            saver: Some(saver),
+        })
+    }
+
+    /// Stop saving, waiting for a save in progress to finish
+    pub fn stop(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        // Closing the channel wakes the thread up
+        self.stop = None;
+        if let Some(saver) = self.saver.take() {
+            if saver.join().is_err() {
+                warn!("Autosave thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Autosave {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Save a snapshot of the recording, if it has any events
+fn save(recorder: &SharedRecorder, path: &Path) {
+    let snapshot = match recorder.lock() {
+        Ok(recorder) if recorder.is_recording() && recorder.event_count() > 0 => {
+            recorder.snapshot()
+        }
+        _ => return,
+    };
+    match snapshot.save(path) {
+        Ok(()) => debug!("Autosaved recording to {}", path.display()),
+        Err(err) => warn!("Autosave failed: {:#}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::MidiRecorder;
+    use crate::notation::Take;
+
+    #[test]
+    fn test_autosave() {
+        let path = std::env::temp_dir().join("test_autosave.mid");
+        let _ = std::fs::remove_file(&path);
+        let recorder = MidiRecorder::shared();
+        {
+            let mut recorder = recorder.lock().unwrap();
+            recorder.start();
+            recorder.set_time(Some(0));
+            recorder.record_note_on(60, 80);
+            recorder.set_time(Some(250_000));
+        }
+
+        let autosave =
+            Autosave::start(recorder.clone(), path.clone(), Duration::from_millis(10)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        autosave.stop();
+        // The sounding note ends at the snapshot, while the recording goes on
+        let notes = Take::load(&path).unwrap().notes;
+        assert_eq!((notes.len(), notes[0].end), (1, 250_000));
+        let recorder = recorder.lock().unwrap();
+        assert!(recorder.is_recording());
+        assert_eq!(recorder.event_count(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
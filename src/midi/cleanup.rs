@@ -0,0 +1,609 @@
+use anyhow::{Context, Result};
+use midly::{
+    num::{u28, u4, u7},
+    MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent, TrackEventKind,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::recorder::write_smf;
+use crate::config::Config;
+
+const DEFAULT_TEMPO: u64 = 500_000;
+
+/// How overlapping notes on a channel are fixed
+///
+/// Retriggers of a sounding pitch always end the earlier note; the modes
+/// only differ for notes of different pitches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapFix {
+    /// Leave notes of different pitches overlapping, e.g. for chords and let-ring
+    #[default]
+    Keep,
+    /// End each note no later than the next onset on its channel (monophonic lines)
+    Trim,
+    /// End each note exactly at the next onset on its channel
+    Legato,
+}
+
+impl FromStr for OverlapFix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" => Ok(OverlapFix::Keep),
+            "trim" => Ok(OverlapFix::Trim),
+            "legato" => Ok(OverlapFix::Legato),
+            _ => anyhow::bail!("Unknown overlap fix '{}' (use keep, trim or legato)", s),
+        }
+    }
+}
+
+/// Grid quantization of note starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridQuantize {
+    /// Grid as a note value, e.g. 16 for sixteenth notes
+    pub note_value: u32,
+    /// How far notes move towards the grid (0.0 to 1.0)
+    pub strength: f32,
+    /// Position of every second grid line within a pair of steps (0.5 = straight, 0.67 = triplet feel)
+    pub swing: f32,
+}
+
+/// Post-processing of recorded takes
+///
+/// Stages run in order: same-pitch fragments separated by tiny gaps are
+/// merged, short notes dropped, note starts quantized (lengths are kept),
+/// overlaps fixed and velocities normalized. Controllers, pitch bend and
+/// meta events are left where they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cleanup {
+    /// Notes shorter than this are dropped
+    pub min_length: Duration,
+    /// Same-pitch notes separated by at most this gap are merged (zero merges nothing)
+    pub merge_gap: Duration,
+    pub overlap: OverlapFix,
+    pub quantize: Option<GridQuantize>,
+    /// Scale velocities so the loudest note has this velocity
+    pub velocity_peak: Option<u8>,
+}
+
+impl Cleanup {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            min_length: Duration::from_secs_f32(config.cleanup_min_note_length),
+            merge_gap: Duration::from_secs_f32(config.cleanup_merge_gap),
+            overlap: config.cleanup_overlap,
+            quantize: (config.cleanup_grid > 0).then_some(GridQuantize {
+                note_value: config.cleanup_grid,
+                strength: config.cleanup_quantize_strength,
+                swing: config.cleanup_swing,
+            }),
+            velocity_peak: config.cleanup_velocity_peak,
+        }
+    }
+
+    /// Clean up the notes of a MIDI file, track by track
+    pub fn apply<'a>(&self, smf: &Smf<'a>) -> Result<Smf<'a>> {
+        let Timing::Metrical(ticks_per_beat) = smf.header.timing else {
+            anyhow::bail!("MIDI files with timecode timing are not supported");
+        };
+        let clock = TickClock::new(smf, ticks_per_beat.as_int().max(1) as u64);
+
+        let mut tracks: Vec<SplitTrack<'a>> = smf.tracks.iter().map(split_notes).collect();
+        for SplitTrack { notes, .. } in &mut tracks {
+            self.merge_fragments(notes, &clock);
+            notes.retain(|note| clock.duration(note.start, note.end) >= self.min_length);
+            if let Some(grid) = &self.quantize {
+                for note in notes.iter_mut() {
+                    let length = note.end - note.start;
+                    note.start = grid.quantize(note.start, clock.ticks_per_beat);
+                    note.end = note.start + length;
+                }
+            }
+            fix_retriggers(notes);
+            self.fix_overlaps(notes);
+        }
+
+        if let Some(peak) = self.velocity_peak {
+            let loudest = tracks
+                .iter()
+                .flat_map(|track| &track.notes)
+                .map(|note| note.velocity)
+                .max()
+                .unwrap_or(0);
+            if loudest > 0 {
+                let scale = peak.clamp(1, 127) as f32 / loudest as f32;
+                for note in tracks.iter_mut().flat_map(|track| &mut track.notes) {
+                    note.velocity = (note.velocity as f32 * scale).round().clamp(1.0, 127.0) as u8;
+                }
+            }
+        }
+
+        Ok(Smf {
+            header: smf.header,
+            tracks: tracks.into_iter().map(join_notes).collect(),
+        })
+    }
+
+    /// Clean up a MIDI file into another file; returns the number of notes kept
+    pub fn apply_file(&self, input: &Path, output: &Path) -> Result<usize> {
+        let data = std::fs::read(input).with_context(|| format!("Failed to read {:?}", input))?;
+        let smf = Smf::parse(&data).context("Failed to parse MIDI file")?;
+        let cleaned = self.apply(&smf)?;
+        write_smf(&cleaned, output)?;
+        Ok(cleaned
+            .tracks
+            .iter()
+            .flatten()
+            .filter(|event| {
+                matches!(
+                    event.kind,
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOn { .. },
+                        ..
+                    }
+                )
+            })
+            .count())
+    }
+
+    /// Merge notes of one pitch whose gap is at most `merge_gap`
+    fn merge_fragments(&self, notes: &mut Vec<Note>, clock: &TickClock) {
+        if self.merge_gap.is_zero() {
+            return;
+        }
+        notes.sort_by_key(|note| (note.channel, note.key, note.start));
+        let mut merged: Vec<Note> = Vec::with_capacity(notes.len());
+        for note in notes.drain(..) {
+            match merged.last_mut() {
+                Some(last)
+                    if last.channel == note.channel
+                        && last.key == note.key
+                        && clock.duration(last.end, note.start) <= self.merge_gap =>
+                {
+                    last.end = last.end.max(note.end);
+                    last.velocity = last.velocity.max(note.velocity);
+                }
+                _ => merged.push(note),
+            }
+        }
+        *notes = merged;
+    }
+
+    /// End notes at the next onset on their channel, depending on the overlap mode
+    fn fix_overlaps(&self, notes: &mut [Note]) {
+        if self.overlap == OverlapFix::Keep {
+            return;
+        }
+        let mut onsets: HashMap<u8, Vec<u64>> = HashMap::new();
+        for note in notes.iter() {
+            onsets.entry(note.channel).or_default().push(note.start);
+        }
+        for starts in onsets.values_mut() {
+            starts.sort_unstable();
+            starts.dedup();
+        }
+        for note in notes.iter_mut() {
+            let starts = &onsets[&note.channel];
+            let index = starts.partition_point(|&start| start <= note.start);
+            let Some(&next) = starts.get(index) else {
+                continue;
+            };
+            note.end = match self.overlap {
+                OverlapFix::Trim => note.end.min(next),
+                _ => next,
+            };
+        }
+    }
+}
+
+impl GridQuantize {
+    /// Move a tick towards the nearest (swung) grid line
+    fn quantize(&self, tick: u64, ticks_per_beat: u64) -> u64 {
+        let step = (ticks_per_beat * 4 / self.note_value.max(1) as u64).max(1) as f64;
+        let pair = 2.0 * step;
+        let start = (tick as f64 / pair).floor() * pair;
+        let swung = start + pair * self.swing.clamp(0.0, 1.0) as f64;
+        let position = tick as f64;
+        let target = [start, swung, start + pair]
+            .into_iter()
+            .min_by(|a, b| (a - position).abs().total_cmp(&(b - position).abs()))
+            .unwrap_or(start);
+        let strength = self.strength.clamp(0.0, 1.0) as f64;
+        (position + (target - position) * strength).round().max(0.0) as u64
+    }
+}
+
+/// Path of the unprocessed take saved next to a cleaned one, e.g. take.raw.mid
+pub fn raw_take_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.raw.mid", stem))
+}
+
+/// A note with start and end in ticks
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Note {
+    channel: u8,
+    key: u8,
+    velocity: u8,
+    start: u64,
+    end: u64,
+}
+
+/// A track split into its notes and its other events
+struct SplitTrack<'a> {
+    /// Events other than notes at absolute ticks
+    events: Vec<(u64, TrackEventKind<'a>)>,
+    notes: Vec<Note>,
+    /// Tick of the end of the track
+    end: u64,
+}
+
+/// Separate the notes of a track from its other events
+///
+/// A note on for a sounding pitch ends the earlier note; notes still
+/// sounding at the end of the track end there.
+fn split_notes<'a>(track: &Track<'a>) -> SplitTrack<'a> {
+    let mut events = Vec::new();
+    let mut notes = Vec::new();
+    let mut sounding: HashMap<(u8, u8), (u64, u8)> = HashMap::new();
+    let mut tick = 0u64;
+
+    for event in track {
+        tick += event.delta.as_int() as u64;
+        let (channel, key, velocity) = match event.kind {
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel },
+            } => (channel.as_int(), key.as_int(), vel.as_int()),
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff { key, .. },
+            } => (channel.as_int(), key.as_int(), 0),
+            TrackEventKind::Meta(MetaMessage::EndOfTrack) => continue,
+            kind => {
+                events.push((tick, kind));
+                continue;
+            }
+        };
+        if let Some((start, on_velocity)) = sounding.remove(&(channel, key)) {
+            notes.push(Note {
+                channel,
+                key,
+                velocity: on_velocity,
+                start,
+                end: tick,
+            });
+        }
+        if velocity > 0 {
+            sounding.insert((channel, key), (tick, velocity));
+        }
+    }
+    for ((channel, key), (start, velocity)) in sounding {
+        notes.push(Note {
+            channel,
+            key,
+            velocity,
+            start,
+            end: tick,
+        });
+    }
+    SplitTrack {
+        events,
+        notes,
+        end: tick,
+    }
+}
+
+/// End notes where a later note of the same pitch starts; drops notes left without length
+fn fix_retriggers(notes: &mut Vec<Note>) {
+    notes.sort_by_key(|note| (note.channel, note.key, note.start));
+    for i in 1..notes.len() {
+        let (before, after) = notes.split_at_mut(i);
+        let (previous, note) = (&mut before[i - 1], &after[0]);
+        if previous.channel == note.channel && previous.key == note.key {
+            previous.end = previous.end.min(note.start);
+        }
+    }
+    notes.retain(|note| note.end > note.start);
+}
+
+/// Rebuild a track from its other events and notes
+///
+/// At one tick, note offs come first and note ons last, so controllers
+/// and pitch bend sent with a note still precede it.
+fn join_notes(track: SplitTrack) -> Track {
+    let SplitTrack { events, notes, end } = track;
+    let mut timed: Vec<(u64, u8, TrackEventKind)> = events
+        .into_iter()
+        .map(|(tick, kind)| (tick, 1, kind))
+        .collect();
+    for note in &notes {
+        let channel = u4::new(note.channel);
+        let key = u7::new(note.key);
+        timed.push((
+            note.start,
+            2,
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(note.velocity),
+                },
+            },
+        ));
+        timed.push((
+            note.end,
+            0,
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(0),
+                },
+            },
+        ));
+    }
+    timed.sort_by_key(|(tick, order, _)| (*tick, *order));
+
+    let end = timed.last().map_or(end, |(tick, _, _)| end.max(*tick));
+    let mut last_tick = 0u64;
+    let mut track: Track = timed
+        .into_iter()
+        .map(|(tick, _, kind)| {
+            let delta = tick - last_tick;
+            last_tick = tick;
+            TrackEvent {
+                delta: u28::new(delta as u32),
+                kind,
+            }
+        })
+        .collect();
+    track.push(TrackEvent {
+        delta: u28::new((end - last_tick) as u32),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    track
+}
+
+/// Converts ticks to time with the tempo changes of a file
+pub(super) struct TickClock {
+    ticks_per_beat: u64,
+    /// Tempo changes as (tick, microseconds at that tick, tempo), in tick order
+    changes: Vec<(u64, u64, u64)>,
+}
+
+impl TickClock {
+    pub(super) fn new(smf: &Smf, ticks_per_beat: u64) -> Self {
+        let mut tempos: Vec<(u64, u64)> = Vec::new();
+        for track in &smf.tracks {
+            let mut tick = 0u64;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                if let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = event.kind {
+                    tempos.push((tick, tempo.as_int().max(1) as u64));
+                }
+            }
+        }
+        tempos.sort_by_key(|&(tick, _)| tick);
+
+        let mut changes = vec![(0, 0, DEFAULT_TEMPO)];
+        for (tick, tempo) in tempos {
+            let micros = Self::micros_from(&changes, tick, ticks_per_beat);
+            changes.retain(|&(change_tick, _, _)| change_tick < tick);
+            changes.push((tick, micros, tempo));
+        }
+        Self {
+            ticks_per_beat,
+            changes,
+        }
+    }
+
+    fn micros_from(changes: &[(u64, u64, u64)], tick: u64, ticks_per_beat: u64) -> u64 {
+        let index = changes.partition_point(|&(change_tick, _, _)| change_tick <= tick);
+        let (change_tick, micros, tempo) = changes[index.saturating_sub(1)];
+        micros + (tick - change_tick) * tempo / ticks_per_beat
+    }
+
+    pub(super) fn micros(&self, tick: u64) -> u64 {
+        Self::micros_from(&self.changes, tick, self.ticks_per_beat)
+    }
+
+    /// Tempo changes as (microseconds, microseconds per quarter note), the first at 0
+    pub(super) fn tempo_changes(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        self.changes
+            .iter()
+            .map(|&(_, micros, tempo)| (micros, tempo.min(0xFF_FFFF) as u32))
+    }
+
+    /// Time between two ticks (zero if the second is earlier)
+    fn duration(&self, from: u64, to: u64) -> Duration {
+        Duration::from_micros(self.micros(to).saturating_sub(self.micros(from)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{num::u15, Format, Header};
+
+    /// A one-track file at 120 BPM and 480 PPQ from (tick, key, velocity) note ons and offs
+    fn smf(events: &[(u64, u8, u8)]) -> Smf<'static> {
+        let mut last = 0;
+        let track: Track = events
+            .iter()
+            .map(|&(tick, key, vel)| {
+                let delta = tick - last;
+                last = tick;
+                TrackEvent {
+                    delta: u28::new(delta as u32),
+                    kind: TrackEventKind::Midi {
+                        channel: u4::new(0),
+                        message: MidiMessage::NoteOn {
+                            key: u7::new(key),
+                            vel: u7::new(vel),
+                        },
+                    },
+                }
+            })
+            .collect();
+        Smf {
+            header: Header {
+                format: Format::SingleTrack,
+                timing: Timing::Metrical(u15::new(480)),
+            },
+            tracks: vec![track],
+        }
+    }
+
+    /// The (start, end, key, velocity) notes of the first track
+    fn notes(smf: &Smf) -> Vec<(u64, u64, u8, u8)> {
+        let mut notes = split_notes(&smf.tracks[0]).notes;
+        notes.sort_by_key(|note| (note.start, note.key));
+        notes
+            .iter()
+            .map(|note| (note.start, note.end, note.key, note.velocity))
+            .collect()
+    }
+
+    fn cleanup() -> Cleanup {
+        Cleanup {
+            min_length: Duration::ZERO,
+            merge_gap: Duration::ZERO,
+            overlap: OverlapFix::Keep,
+            quantize: None,
+            velocity_peak: None,
+        }
+    }
+
+    #[test]
+    fn test_overlap_fix_parse() {
+        assert_eq!("legato".parse::<OverlapFix>().unwrap(), OverlapFix::Legato);
+        assert_eq!("Trim".parse::<OverlapFix>().unwrap(), OverlapFix::Trim);
+        assert!("smear".parse::<OverlapFix>().is_err());
+    }
+
+    #[test]
+    fn test_merge_and_drop_fragments() {
+        // 480 ticks are 500 ms: a note broken by a 10 ms gap, then a 20 ms blip
+        let input = smf(&[
+            (0, 64, 90),
+            (240, 64, 0),
+            (250, 64, 70),
+            (480, 64, 0),
+            (960, 67, 80),
+            (979, 67, 0),
+        ]);
+        let cleaned = Cleanup {
+            min_length: Duration::from_millis(30),
+            merge_gap: Duration::from_millis(20),
+            ..cleanup()
+        }
+        .apply(&input)
+        .unwrap();
+        assert_eq!(notes(&cleaned), vec![(0, 480, 64, 90)]);
+    }
+
+    #[test]
+    fn test_quantize_with_strength_and_swing() {
+        let grid = GridQuantize {
+            note_value: 8,
+            strength: 1.0,
+            swing: 0.5,
+        };
+        assert_eq!(grid.quantize(250, 480), 240);
+        assert_eq!(grid.quantize(470, 480), 480);
+        let half = GridQuantize {
+            strength: 0.5,
+            ..grid
+        };
+        assert_eq!(half.quantize(280, 480), 260);
+        // Swung eighths: the offbeat is two thirds into the beat
+        let swung = GridQuantize {
+            swing: 2.0 / 3.0,
+            ..grid
+        };
+        assert_eq!(swung.quantize(300, 480), 320);
+        assert_eq!(swung.quantize(130, 480), 0);
+
+        // Lengths are kept
+        let input = smf(&[(10, 60, 80), (250, 60, 0)]);
+        let cleaned = Cleanup {
+            quantize: Some(grid),
+            ..cleanup()
+        }
+        .apply(&input)
+        .unwrap();
+        assert_eq!(notes(&cleaned), vec![(0, 240, 60, 80)]);
+    }
+
+    #[test]
+    fn test_overlaps_and_retriggers() {
+        // A retrigger of 60 before its note off, and 62 overlapping 64
+        let input = smf(&[
+            (0, 60, 80),
+            (200, 60, 80),
+            (400, 60, 0),
+            (480, 62, 80),
+            (600, 64, 80),
+            (700, 62, 0),
+            (800, 64, 0),
+        ]);
+        let keep = cleanup().apply(&input).unwrap();
+        assert_eq!(
+            notes(&keep),
+            vec![
+                (0, 200, 60, 80),
+                (200, 400, 60, 80),
+                (480, 700, 62, 80),
+                (600, 800, 64, 80)
+            ]
+        );
+
+        let trim = Cleanup {
+            overlap: OverlapFix::Trim,
+            ..cleanup()
+        }
+        .apply(&input)
+        .unwrap();
+        assert_eq!(notes(&trim)[2], (480, 600, 62, 80));
+        assert_eq!(notes(&trim)[1], (200, 400, 60, 80));
+
+        let legato = Cleanup {
+            overlap: OverlapFix::Legato,
+            ..cleanup()
+        }
+        .apply(&input)
+        .unwrap();
+        assert_eq!(notes(&legato)[1], (200, 480, 60, 80));
+    }
+
+    #[test]
+    fn test_velocity_peak() {
+        let input = smf(&[(0, 60, 50), (100, 60, 0), (100, 62, 100), (200, 62, 0)]);
+        let cleaned = Cleanup {
+            velocity_peak: Some(120),
+            ..cleanup()
+        }
+        .apply(&input)
+        .unwrap();
+        let velocities: Vec<u8> = notes(&cleaned).iter().map(|n| n.3).collect();
+        assert_eq!(velocities, vec![60, 120]);
+    }
+
+    #[test]
+    fn test_raw_take_path() {
+        assert_eq!(
+            raw_take_path(Path::new("takes/solo.mid")),
+            PathBuf::from("takes/solo.raw.mid")
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{bounded, unbounded, RecvTimeoutError, Sender};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::recorder::{TakeStorage, TempoChange, TrackLayout};
+
+/// How often the writer flushes new entries to the disk, so they also
+/// survive a power failure
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// One line of a recording journal
+///
+/// A journal starts with the recording's settings, followed by its events
+/// in the order they were recorded. Settings are written again whenever
+/// they change during the recording; the last ones apply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum JournalEntry {
+    Settings {
+        ticks_per_beat: u16,
+        /// e.g. "6/8"
+        time_signature: String,
+        tempo_map: Vec<TempoChange>,
+        layout: TrackLayout,
+        tracks: Vec<String>,
+        channel_names: BTreeMap<u8, String>,
+        #[serde(default)]
+        take_storage: TakeStorage,
+        /// Take that the recording is layered onto
+        #[serde(default)]
+        overdub: Option<PathBuf>,
+    },
+    /// A take starts; the times of the events after it count from its start
+    Take { number: usize },
+    /// A channel message as sent, with its status byte
+    Channel {
+        time: u64,
+        track: usize,
+        bytes: Vec<u8>,
+    },
+    Marker {
+        time: u64,
+        track: usize,
+        text: String,
+    },
+    KeySignature {
+        time: u64,
+        track: usize,
+        sharps: i8,
+        minor: bool,
+    },
+}
+
+/// Request to the journal's writer thread
+enum Request {
+    Entry(JournalEntry),
+    /// Flush to the disk and report back
+    Sync(Sender<std::io::Result<()>>),
+}
+
+/// An append-only log of a recording, one JSON entry per line
+///
+/// Entries are written by a background thread, so recording an event never
+/// waits for the disk. The writer flushes whenever it has caught up with the
+/// queue, so a take survives a crash or a killed process up to about its
+/// last event, and syncs to the disk every `SYNC_INTERVAL`.
+pub(super) struct Journal {
+    path: PathBuf,
+    tx: Option<Sender<Request>>,
+    writer: Option<JoinHandle<Result<()>>>,
+}
+
+impl Journal {
+    /// Create a journal, replacing an existing one, and start its writer thread
+    pub(super) fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording journal {:?}", path))?;
+
+        let (tx, rx) = unbounded::<Request>();
+        let writer = std::thread::Builder::new()
+            .name("recording-journal".to_string())
+            .spawn(move || {
+                let mut file = BufWriter::new(file);
+                let mut unsynced = false;
+                loop {
+                    match rx.recv_timeout(SYNC_INTERVAL) {
+                        Ok(Request::Entry(entry)) => {
+                            serde_json::to_writer(&mut file, &entry)?;
+                            file.write_all(b"\n")?;
+                            unsynced = true;
+                        }
+                        Ok(Request::Sync(reply)) => {
+                            let synced = file.flush().and_then(|_| file.get_ref().sync_data());
+                            unsynced = false;
+                            let _ = reply.send(synced);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            if unsynced {
+                                file.get_ref().sync_data()?;
+                                unsynced = false;
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    if rx.is_empty() {
+                        file.flush()?;
+                    }
+                }
+                file.flush()?;
+                Ok(())
+            })
+            .context("Failed to start the recording journal thread")?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue an entry for writing; fails once the writer has stopped on an error
+    pub(super) fn append(&self, entry: JournalEntry) -> Result<()> {
+        let Some(tx) = &self.tx else {
+            anyhow::bail!("Recording journal {:?} is closed", self.path);
+        };
+        tx.send(Request::Entry(entry))
+            .map_err(|_| anyhow::anyhow!("Recording journal writer has stopped"))
+    }
+
+    /// Write the queued entries and flush them to the disk, waiting until they are
+    pub(super) fn sync(&self) -> Result<()> {
+        let (reply, synced) = bounded(1);
+        self.tx
+            .as_ref()
+            .and_then(|tx| tx.send(Request::Sync(reply)).ok())
+            .context("Recording journal writer has stopped")?;
+        synced
+            .recv()
+            .context("Recording journal writer has stopped")?
+            .with_context(|| format!("Failed to sync recording journal {:?}", self.path))
+    }
+
+    /// Write the queued entries and close the file
+    pub(super) fn close(mut self) -> Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // Closing the channel ends the writer once the queue is written
+        self.tx = None;
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        match writer.join() {
+            Ok(result) => {
+                result.with_context(|| format!("Failed to write recording journal {:?}", self.path))
+            }
+            Err(_) => anyhow::bail!("Recording journal thread panicked"),
+        }
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            warn!("{:#}", err);
+        }
+    }
+}
+
+/// Read the entries of a journal
+///
+/// A last line cut off by a crash is skipped.
+pub(super) fn read(path: &Path) -> Result<Vec<JournalEntry>> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open recording journal {:?}", path))?;
+    let lines: Vec<String> = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+
+    let mut entries = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) if index + 1 == lines.len() => {
+                warn!("Skipping incomplete last journal entry");
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Invalid journal entry on line {}", index + 1));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Path of the journal kept while recording to a file, e.g. take.journal
+pub fn journal_path(path: &Path) -> PathBuf {
+    path.with_extension("journal")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let path = std::env::temp_dir().join("test_round_trip.journal");
+        let entries = vec![
+            JournalEntry::Settings {
+                ticks_per_beat: 480,
+                time_signature: "3/4".to_string(),
+                tempo_map: vec![TempoChange::new(0, 96.0)],
+                layout: TrackLayout::Channel,
+                tracks: vec!["Guitar".to_string()],
+                channel_names: BTreeMap::from([(2, "String 3 (G3)".to_string())]),
+                take_storage: TakeStorage::Tracks,
+                overdub: None,
+            },
+            JournalEntry::Take { number: 2 },
+            JournalEntry::Channel {
+                time: 1_500,
+                track: 0,
+                bytes: vec![0x92, 55, 80],
+            },
+            JournalEntry::Marker {
+                time: 2_000,
+                track: 0,
+                text: "G".to_string(),
+            },
+        ];
+        let journal = Journal::create(&path).unwrap();
+        for entry in &entries {
+            journal.append(entry.clone()).unwrap();
+        }
+        // Synced entries are on the disk while the journal is still open
+        journal.sync().unwrap();
+        assert_eq!(read(&path).unwrap(), entries);
+        journal.close().unwrap();
+        assert_eq!(read(&path).unwrap(), entries);
+
+        // A line cut off by a crash is skipped
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"{\"Channel\":{\"time\":3").unwrap();
+        assert_eq!(read(&path).unwrap(), entries);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_path() {
+        assert_eq!(
+            journal_path(Path::new("takes/solo.mid")),
+            PathBuf::from("takes/solo.journal")
+        );
+    }
+}
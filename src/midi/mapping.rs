@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+
+/// Note mapping applied to every detected note before it is sent, recorded or displayed
+///
+/// Notes are transposed first (virtual capo), then dropped if they fall outside
+/// the configured range, then looked up in the remap table, so a remap entry is
+/// keyed by the transposed note and may point anywhere (e.g. to a drum sound).
+#[derive(Debug, Clone)]
+pub struct NoteMapper {
+    channel: u8,
+    transpose: i16,
+    low: u8,
+    high: u8,
+    remap: BTreeMap<u8, u8>,
+}
+
+impl Default for NoteMapper {
+    fn default() -> Self {
+        Self {
+            channel: 0,
+            transpose: 0,
+            low: 0,
+            high: 127,
+            remap: BTreeMap::new(),
+        }
+    }
+}
+
+impl NoteMapper {
+    /// Create a mapper from the note mapping settings of a configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            channel: config.midi_channel & 0x0F,
+            transpose: config.transpose_octaves as i16 * 12 + config.transpose_semitones as i16,
+            low: config.note_range_low,
+            high: config.note_range_high,
+            remap: config.note_remap.clone(),
+        }
+    }
+
+    /// Output channel for mapped notes (0-based)
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Total transposition in semitones
+    pub fn transpose(&self) -> i16 {
+        self.transpose
+    }
+
+    /// Map a detected note to the note that is sent, or `None` if it is filtered out
+    pub fn map(&self, note: u8) -> Option<u8> {
+        let transposed = note as i16 + self.transpose;
+        if !(0..=127).contains(&transposed) {
+            return None;
+        }
+        let transposed = transposed as u8;
+
+        if !(self.low..=self.high).contains(&transposed) {
+            return None;
+        }
+
+        Some(
+            self.remap
+                .get(&transposed)
+                .copied()
+                .unwrap_or(transposed)
+                .min(127),
+        )
+    }
+
+    /// Check if the mapper changes nothing
+    pub fn is_identity(&self) -> bool {
+        self.transpose == 0 && self.low == 0 && self.high == 127 && self.remap.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_identity() {
+        let mapper = NoteMapper::default();
+        assert!(mapper.is_identity());
+        assert_eq!(mapper.map(60), Some(60));
+        assert_eq!(mapper.channel(), 0);
+    }
+
+    #[test]
+    fn test_transpose() {
+        let config = Config {
+            transpose_octaves: -1,
+            transpose_semitones: 2,
+            ..Default::default()
+        };
+        let mapper = NoteMapper::from_config(&config);
+        assert_eq!(mapper.transpose(), -10);
+        assert_eq!(mapper.map(60), Some(50));
+        // Notes pushed outside the MIDI range are dropped
+        assert_eq!(mapper.map(5), None);
+    }
+
+    #[test]
+    fn test_range_filter() {
+        let config = Config {
+            note_range_low: 40,
+            note_range_high: 64,
+            ..Default::default()
+        };
+        let mapper = NoteMapper::from_config(&config);
+        assert_eq!(mapper.map(39), None);
+        assert_eq!(mapper.map(40), Some(40));
+        assert_eq!(mapper.map(64), Some(64));
+        assert_eq!(mapper.map(65), None);
+    }
+
+    #[test]
+    fn test_remap_after_transpose() {
+        let config = Config {
+            midi_channel: 9,
+            transpose_semitones: 12,
+            note_remap: BTreeMap::from([(52, 36), (57, 38)]),
+            ..Default::default()
+        };
+        let mapper = NoteMapper::from_config(&config);
+        assert_eq!(mapper.channel(), 9);
+        // Low E (40) transposed to 52 triggers the kick drum
+        assert_eq!(mapper.map(40), Some(36));
+        assert_eq!(mapper.map(45), Some(38));
+        assert_eq!(mapper.map(47), Some(59));
+    }
+}
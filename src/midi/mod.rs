@@ -1,23 +1,83 @@
+mod autosave;
+mod cleanup;
+mod journal;
+mod mapping;
+pub mod mpe;
+mod player;
 mod recorder;
-
-pub use recorder::MidiRecorder;
+mod zones;
+
+pub use autosave::Autosave;
+pub use cleanup::{raw_take_path, Cleanup, GridQuantize, OverlapFix};
+pub use journal::journal_path;
+pub use mapping::NoteMapper;
+pub use mpe::{MpeZone, NoteExpression};
+pub use player::{MidiPlayer, PlayOptions};
+pub use recorder::{
+    take_path, ControllerThinning, MidiRecorder, PunchRange, SharedRecorder, TakeStorage,
+    TempoChange, TrackLayout, RAW_DETECTION_TRACK,
+};
+pub use zones::{ZoneRoute, ZoneRouter};
 
 use anyhow::{Context, Result};
 use log::{debug, info};
 use midir::{MidiOutput, MidiOutputConnection};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 const NOTE_ON: u8 = 0x90;
 const NOTE_OFF: u8 = 0x80;
+const CONTROL_CHANGE: u8 = 0xB0;
+const CHANNEL_PRESSURE: u8 = 0xD0;
 const PITCH_BEND: u8 = 0xE0;
 #[allow(dead_code)]
 const DEFAULT_VELOCITY: u8 = 80;
 const DEFAULT_CHANNEL: u8 = 0;
 
+// Controller numbers
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_DATA_INCREMENT: u8 = 96;
+const CC_DATA_DECREMENT: u8 = 97;
+const CC_TIMBRE: u8 = 74;
+const CC_ALL_SOUND_OFF: u8 = 120;
+const CC_ALL_NOTES_OFF: u8 = 123;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+const CC_NRPN_LSB: u8 = 98;
+const CC_NRPN_MSB: u8 = 99;
+
+// Registered parameter numbers (LSB; MSB is 0 for both)
+const RPN_PITCH_BEND_SENSITIVITY: u8 = 0;
+const RPN_MPE_CONFIGURATION: u8 = 6;
+
+/// Controller messages setting a registered parameter, followed by the null RPN
+fn rpn_controllers(parameter: u8, value: u8) -> [(u8, u8); 6] {
+    [
+        (CC_RPN_MSB, 0),
+        (CC_RPN_LSB, parameter),
+        (CC_DATA_ENTRY_MSB, value),
+        (CC_DATA_ENTRY_LSB, 0),
+        (CC_RPN_MSB, 127),
+        (CC_RPN_LSB, 127),
+    ]
+}
+
 pub struct MidiOutputHandler {
     connection: Option<MidiOutputConnection>,
-    active_notes: HashMap<u8, u64>,
+    /// Active notes keyed by (channel, note)
+    active_notes: HashMap<(u8, u8), u64>,
     note_counter: u64,
+    mpe: Option<MpeZone>,
+    /// Recorder that captures every message sent
+    recorder: Option<SharedRecorder>,
+    /// Channels any channel message has been sent on
+    used_channels: BTreeSet<u8>,
+    /// Record messages without a connection instead of failing to send them
+    record_only: bool,
+    /// Timbre and pressure last sent on each MPE member channel
+    sent_expression: HashMap<u8, (u8, u8)>,
+    /// Registered parameters set on each channel, as (channel, parameter) -> value
+    parameters: BTreeMap<(u8, u8), u8>,
 }
 
 impl MidiOutputHandler {
@@ -27,9 +87,60 @@ impl MidiOutputHandler {
             connection: None,
             active_notes: HashMap::new(),
             note_counter: 0,
+            mpe: None,
+            recorder: None,
+            used_channels: BTreeSet::new(),
+            record_only: false,
+            sent_expression: HashMap::new(),
+            parameters: BTreeMap::new(),
         })
     }
 
+    /// Record every message sent from now on, whatever its type or channel
+    ///
+    /// The registered parameters set so far, such as the MPE configuration
+    /// and pitch bend ranges, are handed to the recorder, which writes them
+    /// at the start of every take.
+    pub fn set_recorder(&mut self, recorder: SharedRecorder) {
+        if let Ok(mut recorder) = recorder.lock() {
+            for (&(channel, parameter), &value) in &self.parameters {
+                recorder.set_parameter(channel, parameter, value);
+            }
+        }
+        self.recorder = Some(recorder);
+    }
+
+    /// Only record the messages, without connecting to a port, for offline conversion
+    ///
+    /// Like `connect`, this sends the MPE configuration if MPE is enabled.
+    pub fn record_only(&mut self) -> Result<()> {
+        self.record_only = true;
+        if self.mpe.is_some() {
+            self.send_mpe_configuration()?;
+        }
+        Ok(())
+    }
+
+    /// Enable MPE (MIDI Polyphonic Expression) output using a lower zone
+    ///
+    /// Each note is sent on its own member channel so that pitch bend, pressure
+    /// and timbre apply per note. Call before `connect` so the MPE Configuration
+    /// Message is sent when the port opens.
+    pub fn enable_mpe(&mut self, member_channels: u8, pitch_bend_range: u8) {
+        let zone = MpeZone::new(member_channels, pitch_bend_range);
+        info!(
+            "MPE enabled: {} member channels, ±{} semitone pitch bend",
+            zone.member_channels(),
+            zone.pitch_bend_range()
+        );
+        self.mpe = Some(zone);
+    }
+
+    /// Check if MPE output is enabled
+    pub fn is_mpe_enabled(&self) -> bool {
+        self.mpe.is_some()
+    }
+
     /// Connect to a MIDI output port by name or create a virtual port
     pub fn connect(&mut self, port_name: Option<&str>) -> Result<()> {
         let midi_out = MidiOutput::new("instrument_to_midi")?;
@@ -76,49 +187,221 @@ impl MidiOutputHandler {
         };
 
         self.connection = Some(connection);
+
+        if self.mpe.is_some() {
+            self.send_mpe_configuration()?;
+        }
         Ok(())
     }
 
-    /// Send a note on message
-    pub fn note_on(&mut self, note: u8, velocity: u8) -> Result<()> {
-        if let Some(conn) = &mut self.connection {
-            let message = [NOTE_ON | DEFAULT_CHANNEL, note, velocity];
-            conn.send(&message)?;
+    /// Send the MPE Configuration Message and member channel pitch bend ranges
+    fn send_mpe_configuration(&mut self) -> Result<()> {
+        let Some(zone) = self.mpe.clone() else {
+            return Ok(());
+        };
 
-            self.note_counter += 1;
-            self.active_notes.insert(note, self.note_counter);
+        // MCM on the master channel: RPN 6 = number of member channels
+        self.send_rpn(
+            mpe::MPE_MASTER_CHANNEL,
+            RPN_MPE_CONFIGURATION,
+            zone.member_channels(),
+        )?;
 
-            debug!("Note ON: {} velocity: {}", note, velocity);
-            Ok(())
-        } else {
+        // Pitch bend sensitivity on each member channel
+        for channel in zone.member_channel_numbers() {
+            self.send_rpn(channel, RPN_PITCH_BEND_SENSITIVITY, zone.pitch_bend_range())?;
+        }
+
+        debug!("Sent MPE configuration message");
+        Ok(())
+    }
+
+    /// Set the pitch bend range of a channel in semitones (RPN 0)
+    pub fn set_pitch_bend_range(&mut self, channel: u8, semitones: u8) -> Result<()> {
+        self.send_rpn(channel, RPN_PITCH_BEND_SENSITIVITY, semitones)
+    }
+
+    /// Send a registered parameter number followed by the null RPN
+    fn send_rpn(&mut self, channel: u8, parameter: u8, value: u8) -> Result<()> {
+        self.parameters.insert((channel, parameter), value);
+        if let Some(recorder) = &self.recorder {
+            if let Ok(mut recorder) = recorder.lock() {
+                recorder.set_parameter(channel, parameter, value);
+            }
+        }
+        for (controller, value) in rpn_controllers(parameter, value) {
+            self.control_change(channel, controller, value)?;
+        }
+        Ok(())
+    }
+
+    /// Send raw MIDI bytes to the connected port and the recorder
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        if let Some(conn) = &mut self.connection {
+            conn.send(message)?;
+        } else if !self.record_only {
             anyhow::bail!("MIDI output not connected")
         }
+        if let Some(&status) = message.first() {
+            if (NOTE_OFF..0xF0).contains(&status) {
+                self.used_channels.insert(status & 0x0F);
+            }
+        }
+        if let Some(recorder) = &self.recorder {
+            if let Ok(mut recorder) = recorder.lock() {
+                recorder.record_bytes(message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a note on message
+    ///
+    /// In MPE mode the note is given its own member channel with neutral expression.
+    pub fn note_on(&mut self, note: u8, velocity: u8) -> Result<()> {
+        if self.mpe.is_some() {
+            return self.mpe_note_on(note, velocity, NoteExpression::default());
+        }
+        self.note_on_channel(DEFAULT_CHANNEL, note, velocity)
+    }
+
+    /// Send a note on message on a specific channel
+    pub fn note_on_channel(&mut self, channel: u8, note: u8, velocity: u8) -> Result<()> {
+        self.send(&[NOTE_ON | (channel & 0x0F), note, velocity])?;
+
+        self.note_counter += 1;
+        self.active_notes.insert((channel, note), self.note_counter);
+
+        debug!(
+            "Note ON: {} velocity: {} channel: {}",
+            note, velocity, channel
+        );
+        Ok(())
     }
 
     /// Send a note off message
     pub fn note_off(&mut self, note: u8) -> Result<()> {
-        if let Some(conn) = &mut self.connection {
-            let message = [NOTE_OFF | DEFAULT_CHANNEL, note, 0];
-            conn.send(&message)?;
+        let channel = match &mut self.mpe {
+            Some(zone) => match zone.release(note) {
+                Some(channel) => channel,
+                None => return Ok(()),
+            },
+            None => DEFAULT_CHANNEL,
+        };
+        self.note_off_channel(channel, note)
+    }
 
-            self.active_notes.remove(&note);
+    /// Send a note off message on a specific channel
+    pub fn note_off_channel(&mut self, channel: u8, note: u8) -> Result<()> {
+        self.send(&[NOTE_OFF | (channel & 0x0F), note, 0])?;
 
-            debug!("Note OFF: {}", note);
-            Ok(())
-        } else {
-            anyhow::bail!("MIDI output not connected")
-        }
+        self.active_notes.remove(&(channel, note));
+
+        debug!("Note OFF: {} channel: {}", note, channel);
+        Ok(())
     }
 
     /// Stop all currently active notes
     pub fn all_notes_off(&mut self) -> Result<()> {
-        let notes: Vec<u8> = self.active_notes.keys().copied().collect();
-        for note in notes {
-            self.note_off(note)?;
+        let notes: Vec<(u8, u8)> = self.active_notes.keys().copied().collect();
+        for (channel, note) in notes {
+            self.note_off_channel(channel, note)?;
+        }
+        if let Some(zone) = &mut self.mpe {
+            zone.reset();
+        }
+        Ok(())
+    }
+
+    /// Silence the output: note off for every active note, then all sound off
+    /// (CC 120) and all notes off (CC 123) on every channel used so far
+    ///
+    /// The channel mode messages also catch notes a receiver still holds,
+    /// such as ones sustained by a pedal or missed note offs.
+    pub fn silence(&mut self) -> Result<()> {
+        self.all_notes_off()?;
+        let channels: Vec<u8> = self.used_channels.iter().copied().collect();
+        for channel in channels {
+            self.control_change(channel, CC_ALL_SOUND_OFF, 0)?;
+            self.control_change(channel, CC_ALL_NOTES_OFF, 0)?;
         }
+        debug!("Silenced {} MIDI channels", self.used_channels.len());
         Ok(())
     }
 
+    /// Channels any channel message has been sent on, in ascending order
+    pub fn used_channels(&self) -> impl Iterator<Item = u8> + '_ {
+        self.used_channels.iter().copied()
+    }
+
+    /// Start a note on its own MPE member channel with initial expression
+    ///
+    /// The expression is sent before the note on so the attack already carries
+    /// the note's bend, pressure and timbre. Falls back to a plain note on when
+    /// MPE is disabled.
+    pub fn mpe_note_on(
+        &mut self,
+        note: u8,
+        velocity: u8,
+        expression: NoteExpression,
+    ) -> Result<()> {
+        let Some(zone) = &mut self.mpe else {
+            return self.note_on_channel(DEFAULT_CHANNEL, note, velocity);
+        };
+
+        let (channel, stolen) = zone.allocate(note);
+        if let Some((stolen_note, stolen_channel)) = stolen {
+            debug!("MPE channel {} stolen from note {}", channel, stolen_note);
+            self.note_off_channel(stolen_channel, stolen_note)?;
+        }
+
+        self.sent_expression.remove(&channel);
+        self.send_expression(channel, expression)?;
+        self.note_on_channel(channel, note, velocity)
+    }
+
+    /// Update the per-note expression of a sounding MPE note
+    pub fn note_expression(&mut self, note: u8, expression: NoteExpression) -> Result<()> {
+        match self.mpe.as_ref().and_then(|zone| zone.channel_for(note)) {
+            Some(channel) => self.send_expression(channel, expression),
+            None => Ok(()),
+        }
+    }
+
+    /// Send the bend, and timbre and pressure where they changed since the note started
+    fn send_expression(&mut self, channel: u8, expression: NoteExpression) -> Result<()> {
+        self.pitch_bend_channel(channel, expression.bend)?;
+        let timbre = Self::unit_to_7bit(expression.timbre);
+        let pressure = Self::unit_to_7bit(expression.pressure);
+        let sent = self.sent_expression.insert(channel, (timbre, pressure));
+        if sent.is_none_or(|(sent, _)| sent != timbre) {
+            self.control_change(channel, CC_TIMBRE, timbre)?;
+        }
+        if sent.is_none_or(|(_, sent)| sent != pressure) {
+            self.channel_pressure(channel, pressure)?;
+        }
+        Ok(())
+    }
+
+    /// Send a control change message
+    pub fn control_change(&mut self, channel: u8, controller: u8, value: u8) -> Result<()> {
+        self.send(&[
+            CONTROL_CHANGE | (channel & 0x0F),
+            controller & 0x7F,
+            value & 0x7F,
+        ])
+    }
+
+    /// Send a channel pressure (aftertouch) message
+    pub fn channel_pressure(&mut self, channel: u8, pressure: u8) -> Result<()> {
+        self.send(&[CHANNEL_PRESSURE | (channel & 0x0F), pressure & 0x7F])
+    }
+
+    /// Convert a 0.0-1.0 value to a 7-bit MIDI data byte
+    fn unit_to_7bit(value: f32) -> u8 {
+        (value.clamp(0.0, 1.0) * 127.0).round() as u8
+    }
+
     /// Send a pitch bend message
     ///
     /// # Arguments
@@ -127,32 +410,35 @@ impl MidiOutputHandler {
     ///   - 0.0 = no bend (centered)
     ///   - +1.0 = maximum upward bend
     pub fn pitch_bend(&mut self, bend: f32) -> Result<()> {
-        if let Some(conn) = &mut self.connection {
-            // Clamp bend value to valid range
-            let bend = bend.clamp(-1.0, 1.0);
+        self.pitch_bend_channel(DEFAULT_CHANNEL, bend)
+    }
 
-            // Convert to 14-bit MIDI pitch bend value (0-16383, center is 8192)
-            let bend_value = ((bend + 1.0) * 8192.0) as u16;
-            let bend_value = bend_value.clamp(0, 16383);
+    /// Send a pitch bend message on a specific channel
+    pub fn pitch_bend_channel(&mut self, channel: u8, bend: f32) -> Result<()> {
+        // Clamp bend value to valid range
+        let bend = bend.clamp(-1.0, 1.0);
 
-            // Split into LSB and MSB (7 bits each)
-            let lsb = (bend_value & 0x7F) as u8;
-            let msb = ((bend_value >> 7) & 0x7F) as u8;
+        // Convert to 14-bit MIDI pitch bend value (0-16383, center is 8192)
+        let bend_value = ((bend + 1.0) * 8192.0) as u16;
+        let bend_value = bend_value.clamp(0, 16383);
 
-            let message = [PITCH_BEND | DEFAULT_CHANNEL, lsb, msb];
-            conn.send(&message)?;
+        // Split into LSB and MSB (7 bits each)
+        let lsb = (bend_value & 0x7F) as u8;
+        let msb = ((bend_value >> 7) & 0x7F) as u8;
 
-            debug!("Pitch bend: {:.3} (value: {})", bend, bend_value);
-            Ok(())
-        } else {
-            anyhow::bail!("MIDI output not connected")
-        }
+        self.send(&[PITCH_BEND | (channel & 0x0F), lsb, msb])?;
+
+        debug!(
+            "Pitch bend: {:.3} (value: {}) channel: {}",
+            bend, bend_value, channel
+        );
+        Ok(())
     }
 
     /// Check if a note is currently active
     #[allow(dead_code)]
     pub fn is_note_active(&self, note: u8) -> bool {
-        self.active_notes.contains_key(&note)
+        self.active_notes.keys().any(|&(_, n)| n == note)
     }
 
     /// Get the number of active notes
@@ -210,7 +496,7 @@ mod tests {
         assert!(!handler.is_note_active(60));
 
         // Simulate note tracking (without actual MIDI connection)
-        handler.active_notes.insert(60, 1);
+        handler.active_notes.insert((DEFAULT_CHANNEL, 60), 1);
         assert_eq!(handler.active_note_count(), 1);
         assert!(handler.is_note_active(60));
     }
@@ -231,4 +517,162 @@ mod tests {
         let bend_value = ((-1.0 + 1.0) * 8192.0) as u16;
         assert_eq!(bend_value, 0);
     }
+
+    #[test]
+    fn test_mpe_enable() {
+        let mut handler = MidiOutputHandler::new().unwrap();
+        assert!(!handler.is_mpe_enabled());
+        handler.enable_mpe(15, 48);
+        assert!(handler.is_mpe_enabled());
+    }
+
+    #[test]
+    fn test_silence_without_connection() {
+        let mut handler = MidiOutputHandler::new().unwrap();
+        // Nothing sent yet: no notes to release and no channels to sweep
+        assert!(handler.silence().is_ok());
+        assert_eq!(handler.used_channels().count(), 0);
+
+        handler.used_channels.insert(3);
+        handler.used_channels.insert(0);
+        assert_eq!(handler.used_channels().collect::<Vec<_>>(), vec![0, 3]);
+        assert!(handler.silence().is_err());
+    }
+
+    #[test]
+    fn test_record_only() {
+        let recorder = MidiRecorder::shared();
+        recorder.lock().unwrap().start();
+        let mut handler = MidiOutputHandler::new().unwrap();
+        handler.set_recorder(recorder.clone());
+        assert!(handler.note_on(60, 100).is_err());
+
+        handler.record_only().unwrap();
+        handler.note_on(60, 100).unwrap();
+        handler.note_off(60).unwrap();
+        assert_eq!(recorder.lock().unwrap().event_count(), 2);
+    }
+
+    #[test]
+    fn test_mpe_expression_changes_only() {
+        let recorder = MidiRecorder::shared();
+        recorder.lock().unwrap().start();
+        let mut handler = MidiOutputHandler::new().unwrap();
+        handler.enable_mpe(15, 48);
+        handler.record_only().unwrap();
+        handler.set_recorder(recorder.clone());
+
+        // The take starts with the MCM and 15 bend ranges, six controllers each
+        let setup = 16 * 6;
+        // Bend, timbre, pressure and the note on
+        handler
+            .mpe_note_on(60, 100, NoteExpression::default())
+            .unwrap();
+        assert_eq!(recorder.lock().unwrap().event_count(), setup + 4);
+
+        // Steady timbre and pressure are not sent again, only the bend
+        for bend in [0.1, 0.2] {
+            let expression = NoteExpression {
+                bend,
+                ..Default::default()
+            };
+            handler.note_expression(60, expression).unwrap();
+        }
+        assert_eq!(recorder.lock().unwrap().event_count(), setup + 6);
+
+        let expression = NoteExpression {
+            bend: 0.2,
+            pressure: 0.5,
+            ..Default::default()
+        };
+        handler.note_expression(60, expression).unwrap();
+        assert_eq!(recorder.lock().unwrap().event_count(), setup + 8);
+
+        // A new note on the channel starts from scratch
+        handler.note_off(60).unwrap();
+        handler
+            .mpe_note_on(60, 100, NoteExpression::default())
+            .unwrap();
+        assert_eq!(recorder.lock().unwrap().event_count(), setup + 13);
+    }
+
+    #[test]
+    fn test_mpe_setup_in_every_take() {
+        use midly::{MidiMessage, Smf, TrackEventKind};
+
+        let recorder = MidiRecorder::shared();
+        let mut handler = MidiOutputHandler::new().unwrap();
+        handler.enable_mpe(15, 48);
+        handler.record_only().unwrap();
+        // The configuration is sent before recording starts, as when connecting
+        handler.set_recorder(recorder.clone());
+        recorder
+            .lock()
+            .unwrap()
+            .set_take_storage(TakeStorage::Tracks);
+        recorder.lock().unwrap().start();
+
+        let expression = NoteExpression {
+            bend: 0.5,
+            ..Default::default()
+        };
+        handler.mpe_note_on(60, 100, expression).unwrap();
+        handler.note_off(60).unwrap();
+        {
+            let mut recorder = recorder.lock().unwrap();
+            recorder.disarm();
+            recorder.arm();
+        }
+        handler.mpe_note_on(64, 100, expression).unwrap();
+        handler.note_off(64).unwrap();
+        recorder.lock().unwrap().stop();
+
+        let path = std::env::temp_dir().join("test_mpe_setup.mid");
+        recorder.lock().unwrap().save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        let mut takes = 0;
+        for track in &smf.tracks {
+            // Selected RPN and pitch bend range per channel, as a synth tracks them
+            let mut rpn = [(127u8, 127u8); 16];
+            let mut ranges = [None; 16];
+            let mut mcm = None;
+            for event in track {
+                let TrackEventKind::Midi { channel, message } = event.kind else {
+                    continue;
+                };
+                let channel = channel.as_int() as usize;
+                match message {
+                    MidiMessage::Controller { controller, value } => {
+                        let (controller, value) = (controller.as_int(), value.as_int());
+                        match (controller, rpn[channel]) {
+                            (CC_RPN_MSB, _) => rpn[channel].0 = value,
+                            (CC_RPN_LSB, _) => rpn[channel].1 = value,
+                            (CC_DATA_ENTRY_MSB, (0, 0)) => ranges[channel] = Some(value),
+                            (CC_DATA_ENTRY_MSB, (0, 6)) => mcm = Some(value),
+                            _ => {}
+                        }
+                    }
+                    MidiMessage::PitchBend { .. } => {
+                        assert_eq!(mcm, Some(15));
+                        assert_eq!(ranges[channel], Some(48));
+                        takes += 1;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // Each take's track starts with the setup
+        assert_eq!(takes, 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unit_to_7bit() {
+        assert_eq!(MidiOutputHandler::unit_to_7bit(0.0), 0);
+        assert_eq!(MidiOutputHandler::unit_to_7bit(0.5), 64);
+        assert_eq!(MidiOutputHandler::unit_to_7bit(1.0), 127);
+        assert_eq!(MidiOutputHandler::unit_to_7bit(2.0), 127);
+    }
 }
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+/// Master channel of the MPE lower zone (MIDI channel 1)
+pub const MPE_MASTER_CHANNEL: u8 = 0;
+
+/// Maximum number of member channels in a single MPE zone
+pub const MAX_MEMBER_CHANNELS: u8 = 15;
+
+/// Per-note expression values sent on an MPE member channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteExpression {
+    /// Pitch bend from -1.0 to +1.0 relative to the member pitch bend range
+    pub bend: f32,
+    /// Pressure (channel aftertouch) from 0.0 to 1.0
+    pub pressure: f32,
+    /// Timbre (CC74) from 0.0 to 1.0, 0.5 is neutral
+    pub timbre: f32,
+}
+
+impl Default for NoteExpression {
+    fn default() -> Self {
+        Self {
+            bend: 0.0,
+            pressure: 0.0,
+            timbre: 0.5,
+        }
+    }
+}
+
+/// MPE lower zone with one member channel allocated per sounding note
+#[derive(Debug, Clone)]
+pub struct MpeZone {
+    member_channels: u8,
+    pitch_bend_range: u8,
+    /// Free member channels, least recently released first
+    free_channels: VecDeque<u8>,
+    /// Sounding notes and their member channel, oldest first
+    assigned: VecDeque<(u8, u8)>,
+}
+
+impl MpeZone {
+    /// Create a lower zone using channels 2..=(1 + member_channels)
+    ///
+    /// # Arguments
+    /// * `member_channels` - Number of member channels (1-15)
+    /// * `pitch_bend_range` - Member channel pitch bend range in semitones (MPE default is 48)
+    pub fn new(member_channels: u8, pitch_bend_range: u8) -> Self {
+        let member_channels = member_channels.clamp(1, MAX_MEMBER_CHANNELS);
+        Self {
+            member_channels,
+            pitch_bend_range,
+            free_channels: (1..=member_channels).collect(),
+            assigned: VecDeque::new(),
+        }
+    }
+
+    /// Number of member channels in the zone
+    pub fn member_channels(&self) -> u8 {
+        self.member_channels
+    }
+
+    /// Pitch bend range of the member channels in semitones
+    pub fn pitch_bend_range(&self) -> u8 {
+        self.pitch_bend_range
+    }
+
+    /// Iterate over the member channel numbers (0-based)
+    pub fn member_channel_numbers(&self) -> impl Iterator<Item = u8> {
+        1..=self.member_channels
+    }
+
+    /// Allocate a member channel for a note
+    ///
+    /// Returns the channel and, if every channel was busy, the note that was
+    /// stolen from it (the oldest sounding note) and must be turned off first.
+    pub fn allocate(&mut self, note: u8) -> (u8, Option<(u8, u8)>) {
+        if let Some(channel) = self.channel_for(note) {
+            return (channel, None);
+        }
+
+        if let Some(channel) = self.free_channels.pop_front() {
+            self.assigned.push_back((note, channel));
+            return (channel, None);
+        }
+
+        // All channels busy: steal the oldest note's channel
+        let (stolen_note, channel) = self
+            .assigned
+            .pop_front()
+            .expect("zone has at least one member channel");
+        self.assigned.push_back((note, channel));
+        (channel, Some((stolen_note, channel)))
+    }
+
+    /// Release the member channel held by a note
+    pub fn release(&mut self, note: u8) -> Option<u8> {
+        let index = self.assigned.iter().position(|&(n, _)| n == note)?;
+        let (_, channel) = self.assigned.remove(index)?;
+        self.free_channels.push_back(channel);
+        Some(channel)
+    }
+
+    /// Get the member channel currently assigned to a note
+    pub fn channel_for(&self, note: u8) -> Option<u8> {
+        self.assigned
+            .iter()
+            .find(|&&(n, _)| n == note)
+            .map(|&(_, channel)| channel)
+    }
+
+    /// Release every member channel
+    pub fn reset(&mut self) {
+        self.assigned.clear();
+        self.free_channels = (1..=self.member_channels).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_distinct_channels() {
+        let mut zone = MpeZone::new(15, 48);
+        let (a, _) = zone.allocate(60);
+        let (b, _) = zone.allocate(64);
+        let (c, _) = zone.allocate(67);
+        assert_ne!(a, b);
+        assert_ne!(b, c);
+        assert!(a != MPE_MASTER_CHANNEL && b != MPE_MASTER_CHANNEL && c != MPE_MASTER_CHANNEL);
+        assert_eq!(zone.channel_for(64), Some(b));
+    }
+
+    #[test]
+    fn test_release_reuses_least_recent_channel() {
+        let mut zone = MpeZone::new(2, 48);
+        let (first, _) = zone.allocate(60);
+        zone.release(60);
+        // The next note gets the channel that has been idle longest
+        let (second, _) = zone.allocate(62);
+        assert_ne!(first, second);
+        let (third, _) = zone.allocate(64);
+        assert_eq!(third, first);
+    }
+
+    #[test]
+    fn test_steal_oldest_note_when_full() {
+        let mut zone = MpeZone::new(2, 48);
+        let (first, _) = zone.allocate(60);
+        zone.allocate(62);
+        let (channel, stolen) = zone.allocate(64);
+        assert_eq!(channel, first);
+        assert_eq!(stolen, Some((60, first)));
+        assert_eq!(zone.channel_for(60), None);
+    }
+
+    #[test]
+    fn test_member_channel_clamping() {
+        let zone = MpeZone::new(20, 48);
+        assert_eq!(zone.member_channels(), MAX_MEMBER_CHANNELS);
+        let zone = MpeZone::new(0, 48);
+        assert_eq!(zone.member_channels(), 1);
+    }
+}
@@ -0,0 +1,528 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use midly::{live::LiveEvent, Smf, Timing, TrackEventKind};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::cleanup::TickClock;
+use super::{
+    MidiOutputHandler, CC_DATA_DECREMENT, CC_DATA_ENTRY_LSB, CC_DATA_ENTRY_MSB, CC_DATA_INCREMENT,
+    CC_NRPN_LSB, CC_NRPN_MSB, CC_RPN_LSB, CC_RPN_MSB, CONTROL_CHANGE, NOTE_OFF, NOTE_ON,
+};
+use crate::lifecycle::ProcessorHandle;
+
+/// Longest sleep between checks for pause and shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Playback settings of a `MidiPlayer`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayOptions {
+    /// Time in the file playback starts at, and loops back to
+    pub start: Duration,
+    /// Play again from the start offset at the end of the file, until shut down
+    pub looped: bool,
+}
+
+/// Plays a MIDI file to an output in real time
+///
+/// The channel messages of all tracks are merged and timed with the file's
+/// tempo map, so takes recorded with a tracked tempo play back as they were
+/// performed. Meta and system exclusive events are not sent.
+pub struct MidiPlayer {
+    /// Channel messages as (microseconds, bytes), in time order
+    events: Vec<(u64, Vec<u8>)>,
+    /// Length of the file in microseconds, up to the last end of track
+    length: u64,
+}
+
+impl MidiPlayer {
+    /// Load a standard MIDI file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let smf = Smf::parse(&data).context("Failed to parse MIDI file")?;
+        Self::from_smf(&smf)
+    }
+
+    pub fn from_smf(smf: &Smf) -> Result<Self> {
+        let Timing::Metrical(ticks_per_beat) = smf.header.timing else {
+            anyhow::bail!("MIDI files with timecode timing are not supported");
+        };
+        let clock = TickClock::new(smf, ticks_per_beat.as_int().max(1) as u64);
+
+        let mut events = Vec::new();
+        let mut length = 0;
+        for track in &smf.tracks {
+            let mut tick = 0u64;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                if let TrackEventKind::Midi { channel, message } = event.kind {
+                    let mut bytes = Vec::with_capacity(3);
+                    LiveEvent::Midi { channel, message }.write_std(&mut bytes)?;
+                    events.push((clock.micros(tick), bytes));
+                }
+            }
+            length = length.max(clock.micros(tick));
+        }
+        // Stable, so messages at the same time keep their track order
+        events.sort_by_key(|&(time, _)| time);
+
+        Ok(Self { events, length })
+    }
+
+    /// Length of the file
+    pub fn length(&self) -> Duration {
+        Duration::from_micros(self.length)
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Channel messages with their time in the file, in time order
+    pub fn messages(&self) -> impl Iterator<Item = (Duration, &[u8])> + '_ {
+        self.events
+            .iter()
+            .map(|(time, bytes)| (Duration::from_micros(*time), bytes.as_slice()))
+    }
+
+    /// The last program, controller, pressure and pitch bend values before a time
+    ///
+    /// Sent before starting at an offset, so the notes from there sound the
+    /// way they do when the file is played from the beginning. RPN and NRPN
+    /// values are chased as complete groups (select, then data entry), and
+    /// each channel ends with the parameter selected at that time, or the
+    /// null RPN.
+    fn chase(&self, until: u64) -> Vec<Vec<u8>> {
+        let mut latest: BTreeMap<(u8, u8), usize> = BTreeMap::new();
+        let mut parameters: BTreeMap<u8, ParameterChase> = BTreeMap::new();
+        for (index, (time, bytes)) in self.events.iter().enumerate() {
+            if *time >= until {
+                break;
+            }
+            let status = bytes[0];
+            match (status & 0xF0, bytes[1]) {
+                (NOTE_ON | NOTE_OFF, _) => {}
+                (
+                    CONTROL_CHANGE,
+                    controller @ (CC_RPN_MSB | CC_RPN_LSB | CC_NRPN_MSB | CC_NRPN_LSB
+                    | CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB | CC_DATA_INCREMENT
+                    | CC_DATA_DECREMENT),
+                ) => {
+                    parameters
+                        .entry(status)
+                        .or_default()
+                        .control(index, controller, bytes[2]);
+                }
+                (CONTROL_CHANGE, controller) => {
+                    latest.insert((status, controller), index);
+                }
+                _ => {
+                    latest.insert((status, 0), index);
+                }
+            }
+        }
+
+        let mut groups: Vec<(usize, Vec<Vec<u8>>)> = latest
+            .into_values()
+            .map(|index| (index, vec![self.events[index].1.clone()]))
+            .collect();
+        for (&status, chase) in &parameters {
+            groups.extend(chase.groups(status));
+        }
+        groups.sort_by_key(|&(index, _)| index);
+        let mut messages: Vec<Vec<u8>> = groups.into_iter().flat_map(|(_, group)| group).collect();
+        for (&status, chase) in &parameters {
+            messages.extend(chase.selection(status));
+        }
+        messages
+    }
+
+    /// Play the file until its end, or until shut down through `handle`
+    ///
+    /// Pausing through the handle releases the sounding notes and holds
+    /// playback until resumed. The output is silenced when playback ends.
+    pub fn play(
+        &self,
+        output: &mut MidiOutputHandler,
+        options: PlayOptions,
+        handle: &ProcessorHandle,
+    ) -> Result<()> {
+        let start = options.start.as_micros() as u64;
+        if start >= self.length {
+            anyhow::bail!(
+                "Start offset {:.1}s is past the end of the file ({:.1}s)",
+                options.start.as_secs_f32(),
+                self.length().as_secs_f32()
+            );
+        }
+        let first = self.events.partition_point(|&(time, _)| time < start);
+
+        let result = self.play_from(output, options.looped, start, first, handle);
+        let silenced = output.silence();
+        result.and(silenced)
+    }
+
+    fn play_from(
+        &self,
+        output: &mut MidiOutputHandler,
+        looped: bool,
+        start: u64,
+        first: usize,
+        handle: &ProcessorHandle,
+    ) -> Result<()> {
+        loop {
+            for message in self.chase(start) {
+                output.send(&message)?;
+            }
+            let mut origin = Instant::now();
+            for (time, message) in &self.events[first..] {
+                if !wait(output, handle, &mut origin, time - start)? {
+                    return Ok(());
+                }
+                send(output, message)?;
+            }
+            // Loops keep the file's length, including silence at its end
+            if !wait(output, handle, &mut origin, self.length - start)? || !looped {
+                return Ok(());
+            }
+            output.all_notes_off()?;
+            debug!("Looping back to {:.1}s", start as f64 / 1_000_000.0);
+        }
+    }
+}
+
+/// Null parameter number, deselecting the RPN and NRPN
+const NULL_PARAMETER: (u8, u8) = (127, 127);
+
+/// RPN and NRPN state of a channel, up to the time being chased
+///
+/// Data increments and decrements are not chased.
+struct ParameterChase {
+    rpn: (u8, u8),
+    nrpn: (u8, u8),
+    /// Whether data entry applies to the RPN (true) or the NRPN
+    registered: bool,
+    /// Last value per (select MSB controller, MSB, LSB)
+    values: BTreeMap<(u8, u8, u8), ParameterValue>,
+}
+
+impl Default for ParameterChase {
+    fn default() -> Self {
+        Self {
+            rpn: NULL_PARAMETER,
+            nrpn: NULL_PARAMETER,
+            registered: true,
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl ParameterChase {
+    fn control(&mut self, index: usize, controller: u8, value: u8) {
+        match controller {
+            CC_RPN_MSB => (self.rpn.0, self.registered) = (value, true),
+            CC_RPN_LSB => (self.rpn.1, self.registered) = (value, true),
+            CC_NRPN_MSB => (self.nrpn.0, self.registered) = (value, false),
+            CC_NRPN_LSB => (self.nrpn.1, self.registered) = (value, false),
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB => {
+                let Some(parameter) = self.selected() else {
+                    return;
+                };
+                let entry = self.values.entry(parameter).or_default();
+                entry.index = index;
+                if controller == CC_DATA_ENTRY_MSB {
+                    entry.msb = Some(value);
+                } else {
+                    entry.lsb = Some(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The selected parameter as (select MSB controller, MSB, LSB), if not null
+    fn selected(&self) -> Option<(u8, u8, u8)> {
+        let (controller, number) = if self.registered {
+            (CC_RPN_MSB, self.rpn)
+        } else {
+            (CC_NRPN_MSB, self.nrpn)
+        };
+        (number != NULL_PARAMETER).then_some((controller, number.0, number.1))
+    }
+
+    /// The select and data entry messages of each parameter, with the index of its last value
+    fn groups(&self, status: u8) -> impl Iterator<Item = (usize, Vec<Vec<u8>>)> + '_ {
+        self.values
+            .iter()
+            .map(move |(&(controller, msb, lsb), value)| {
+                let mut group = select(status, controller, (msb, lsb)).to_vec();
+                group.extend(value.msb.map(|msb| vec![status, CC_DATA_ENTRY_MSB, msb]));
+                group.extend(value.lsb.map(|lsb| vec![status, CC_DATA_ENTRY_LSB, lsb]));
+                (value.index, group)
+            })
+    }
+
+    /// Messages restoring the parameter selected at the chased time
+    fn selection(&self, status: u8) -> Vec<Vec<u8>> {
+        match self.selected() {
+            Some((controller, msb, lsb)) => select(status, controller, (msb, lsb)).to_vec(),
+            None if !self.values.is_empty() => select(status, CC_RPN_MSB, NULL_PARAMETER).to_vec(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Data entry MSB and LSB of a parameter, with the index of the last one
+#[derive(Default)]
+struct ParameterValue {
+    index: usize,
+    msb: Option<u8>,
+    lsb: Option<u8>,
+}
+
+/// Messages selecting a parameter number; the LSB controller follows the MSB one
+fn select(status: u8, msb_controller: u8, (msb, lsb): (u8, u8)) -> [Vec<u8>; 2] {
+    [
+        vec![status, msb_controller, msb],
+        vec![status, msb_controller - 1, lsb],
+    ]
+}
+
+/// Wait until `time` microseconds after `origin`, holding while paused
+///
+/// Time spent paused moves the origin, so playback resumes where it was.
+/// Returns false once shut down.
+fn wait(
+    output: &mut MidiOutputHandler,
+    handle: &ProcessorHandle,
+    origin: &mut Instant,
+    time: u64,
+) -> Result<bool> {
+    loop {
+        if handle.is_shutdown() {
+            return Ok(false);
+        }
+        if handle.is_paused() {
+            output.all_notes_off()?;
+            info!("Playback paused");
+            let paused_at = Instant::now();
+            while handle.is_paused() && !handle.is_shutdown() {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            *origin += paused_at.elapsed();
+            info!("Playback resumed");
+            continue;
+        }
+        let due = *origin + Duration::from_micros(time);
+        let now = Instant::now();
+        if due <= now {
+            return Ok(true);
+        }
+        std::thread::sleep((due - now).min(POLL_INTERVAL));
+    }
+}
+
+/// Send a message, tracking notes so they can be released
+fn send(output: &mut MidiOutputHandler, message: &[u8]) -> Result<()> {
+    let channel = message[0] & 0x0F;
+    match (message[0] & 0xF0, message) {
+        (NOTE_ON, &[_, key, vel]) if vel > 0 => output.note_on_channel(channel, key, vel),
+        (NOTE_ON | NOTE_OFF, &[_, key, _]) => output.note_off_channel(channel, key),
+        _ => output.send(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::MidiRecorder;
+    use midly::{
+        num::{u15, u24, u28, u4, u7},
+        Format, Header, MetaMessage, MidiMessage, TrackEvent,
+    };
+
+    fn event(delta: u32, kind: TrackEventKind<'static>) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        }
+    }
+
+    fn midi(message: MidiMessage) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: u4::new(1),
+            message,
+        }
+    }
+
+    /// 480 PPQ: a beat at 120 BPM, then 60 BPM, with a program and a note per beat
+    fn smf() -> Smf<'static> {
+        let note_on = |key| {
+            midi(MidiMessage::NoteOn {
+                key: u7::new(key),
+                vel: u7::new(100),
+            })
+        };
+        let note_off = |key| {
+            midi(MidiMessage::NoteOff {
+                key: u7::new(key),
+                vel: u7::new(0),
+            })
+        };
+        Smf {
+            header: Header {
+                format: Format::Parallel,
+                timing: Timing::Metrical(u15::new(480)),
+            },
+            tracks: vec![
+                vec![
+                    event(
+                        0,
+                        TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500_000))),
+                    ),
+                    event(
+                        480,
+                        TrackEventKind::Meta(MetaMessage::Tempo(u24::new(1_000_000))),
+                    ),
+                    event(480, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+                ],
+                vec![
+                    event(
+                        0,
+                        midi(MidiMessage::ProgramChange {
+                            program: u7::new(25),
+                        }),
+                    ),
+                    event(0, note_on(60)),
+                    event(240, note_off(60)),
+                    event(240, note_on(64)),
+                    event(240, note_off(64)),
+                    event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+                ],
+            ],
+        }
+    }
+
+    #[test]
+    fn test_player_tempo_map() {
+        let player = MidiPlayer::from_smf(&smf()).unwrap();
+        let times: Vec<u64> = player.events.iter().map(|&(time, _)| time).collect();
+        // Half a beat is 250ms at 120 BPM and 500ms at 60 BPM
+        assert_eq!(times, vec![0, 0, 250_000, 500_000, 1_000_000]);
+        assert_eq!(player.events[1].1, vec![0x91, 60, 100]);
+        assert_eq!(player.length(), Duration::from_millis(1500));
+        assert_eq!(player.event_count(), 5);
+    }
+
+    #[test]
+    fn test_player_chase() {
+        let player = MidiPlayer::from_smf(&smf()).unwrap();
+        assert!(player.chase(0).is_empty());
+        // Starting after the program change sends it, but not the notes before
+        assert_eq!(player.chase(600_000), vec![vec![0xC1, 25]]);
+    }
+
+    #[test]
+    fn test_player_chase_parameters() {
+        let control = |controller, value| {
+            midi(MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            })
+        };
+        let mut smf = smf();
+        // A pitch bend range of 12 semitones (RPN 0), with volume set in between
+        smf.tracks[1].splice(
+            1..1,
+            [
+                event(0, control(CC_RPN_MSB, 0)),
+                event(0, control(CC_RPN_LSB, 0)),
+                event(0, control(7, 90)),
+                event(0, control(CC_DATA_ENTRY_MSB, 12)),
+                event(0, control(CC_DATA_ENTRY_LSB, 0)),
+                event(0, control(CC_RPN_MSB, 127)),
+                event(0, control(CC_RPN_LSB, 127)),
+            ],
+        );
+        let player = MidiPlayer::from_smf(&smf).unwrap();
+        assert_eq!(
+            player.chase(600_000),
+            vec![
+                vec![0xC1, 25],
+                vec![0xB1, 7, 90],
+                vec![0xB1, CC_RPN_MSB, 0],
+                vec![0xB1, CC_RPN_LSB, 0],
+                vec![0xB1, CC_DATA_ENTRY_MSB, 12],
+                vec![0xB1, CC_DATA_ENTRY_LSB, 0],
+                vec![0xB1, CC_RPN_MSB, 127],
+                vec![0xB1, CC_RPN_LSB, 127],
+            ]
+        );
+
+        // A later value replaces the earlier one (the RPN MSB alone selects RPN 0 again,
+        // its LSB is still 0), and a parameter left selected stays selected
+        smf.tracks[1].splice(
+            7..8,
+            [
+                event(0, control(CC_NRPN_MSB, 1)),
+                event(0, control(CC_NRPN_LSB, 8)),
+                event(0, control(CC_DATA_ENTRY_MSB, 64)),
+                event(0, control(CC_RPN_MSB, 0)),
+                event(0, control(CC_DATA_ENTRY_MSB, 2)),
+            ],
+        );
+        let player = MidiPlayer::from_smf(&smf).unwrap();
+        assert_eq!(
+            player.chase(600_000)[2..],
+            [
+                vec![0xB1, CC_NRPN_MSB, 1],
+                vec![0xB1, CC_NRPN_LSB, 8],
+                vec![0xB1, CC_DATA_ENTRY_MSB, 64],
+                vec![0xB1, CC_RPN_MSB, 0],
+                vec![0xB1, CC_RPN_LSB, 0],
+                vec![0xB1, CC_DATA_ENTRY_MSB, 2],
+                vec![0xB1, CC_DATA_ENTRY_LSB, 0],
+                vec![0xB1, CC_RPN_MSB, 0],
+                vec![0xB1, CC_RPN_LSB, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_player_play() {
+        let recorder = MidiRecorder::shared();
+        recorder.lock().unwrap().start();
+        let mut output = MidiOutputHandler::new().unwrap();
+        output.record_only().unwrap();
+        output.set_recorder(recorder.clone());
+
+        let player = MidiPlayer::from_smf(&smf()).unwrap();
+        let handle = ProcessorHandle::default();
+        let options = PlayOptions {
+            start: Duration::from_millis(1400),
+            looped: false,
+        };
+        player.play(&mut output, options, &handle).unwrap();
+        // From 1.4s only the program is chased, then the output is silenced
+        assert!(!output.is_note_active(64));
+        assert_eq!(output.used_channels().collect::<Vec<_>>(), vec![1]);
+        assert!(recorder.lock().unwrap().event_count() >= 1);
+
+        let options = PlayOptions {
+            start: Duration::from_secs(2),
+            looped: false,
+        };
+        assert!(player.play(&mut output, options, &handle).is_err());
+
+        // A shut down handle stops looped playback right away
+        handle.shutdown();
+        let options = PlayOptions {
+            start: Duration::ZERO,
+            looped: true,
+        };
+        player.play(&mut output, options, &handle).unwrap();
+        assert!(!output.is_note_active(60));
+    }
+}
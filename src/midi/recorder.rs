@@ -1,23 +1,271 @@
 use anyhow::{Context, Result};
-use log::{debug, info};
+use log::{debug, info, warn};
 use midly::{
+    live::LiveEvent,
     num::{u15, u24, u28, u4, u7},
     Format, Header, MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent, TrackEventKind,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::cleanup::{raw_take_path, Cleanup, TickClock};
+use super::journal::{self, Journal, JournalEntry};
+use crate::notation::{KeySignature, Take, TimeSignature};
 
 const DEFAULT_TICKS_PER_BEAT: u16 = 480;
+const DEFAULT_BPM: f32 = 120.0;
 const MICROSECONDS_PER_MINUTE: u32 = 60_000_000;
+const DEFAULT_TRACK_NAME: &str = "Guitar";
+const CONDUCTOR_TRACK_NAME: &str = "Conductor";
+
+/// Name of the track holding the raw pitch detection in `TrackLayout::Detection`
+pub const RAW_DETECTION_TRACK: &str = "Raw detection";
+
+/// How a recording is split into tracks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackLayout {
+    /// Everything on one track (SMF format 0)
+    #[default]
+    Single,
+    /// One track per MIDI channel, e.g. per string in per-string mode
+    Channel,
+    /// One track per keyboard-split zone
+    Zone,
+    /// The raw pitch detection next to the fuzzy-resolved notes that were played
+    Detection,
+}
+
+impl FromStr for TrackLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "single" => Ok(TrackLayout::Single),
+            "channel" => Ok(TrackLayout::Channel),
+            "zone" => Ok(TrackLayout::Zone),
+            "detection" => Ok(TrackLayout::Detection),
+            _ => anyhow::bail!(
+                "Unknown track layout '{}' (use single, channel, zone or detection)",
+                s
+            ),
+        }
+    }
+}
+
+/// Where the takes of a recording are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TakeStorage {
+    /// Each take in its own file (see `take_path`)
+    #[default]
+    Files,
+    /// All takes in one file, each on its own tracks
+    Tracks,
+}
+
+impl FromStr for TakeStorage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "files" => Ok(TakeStorage::Files),
+            "tracks" => Ok(TakeStorage::Tracks),
+            _ => anyhow::bail!("Unknown take storage '{}' (use files or tracks)", s),
+        }
+    }
+}
+
+/// Bars of a take that are recorded, bar 1 being the start of the take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PunchRange {
+    /// First bar recorded (punch in)
+    pub start: u32,
+    /// Bar where recording stops (punch out); None records to the end of the take
+    pub end: Option<u32>,
+}
+
+impl PunchRange {
+    pub fn new(start: u32, end: Option<u32>) -> Self {
+        Self { start, end }
+    }
+}
+
+/// Path of a numbered take: the first take keeps the path, later ones are
+/// numbered, e.g. solo.mid, solo-take2.mid, solo-take3.mid
+pub fn take_path(path: &Path, take: usize) -> PathBuf {
+    if take <= 1 {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-take{}.{}", stem, take, extension.to_string_lossy()),
+        None => format!("{}-take{}", stem, take),
+    };
+    path.with_file_name(name)
+}
+
+/// A tempo change at a time since recording started
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TempoChange {
+    /// Time in microseconds
+    pub time: u64,
+    /// Microseconds per quarter note
+    pub tempo: u32,
+}
+
+impl TempoChange {
+    pub fn new(time: u64, bpm: f32) -> Self {
+        Self {
+            time,
+            tempo: bpm_to_tempo(bpm),
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        MICROSECONDS_PER_MINUTE as f32 / self.tempo as f32
+    }
+}
+
+/// Write a MIDI file
+///
+/// The file is written next to the target and renamed over it, so an
+/// interrupted write (e.g. of an autosave) never leaves a broken file.
+pub(crate) fn write_smf(smf: &Smf, path: &Path) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let mut file = File::create(&partial).context("Failed to create MIDI file")?;
+    smf.write_std(&mut file)
+        .context("Failed to write MIDI data")?;
+    file.flush().context("Failed to flush MIDI file")?;
+    std::fs::rename(&partial, path).context("Failed to replace MIDI file")?;
+    Ok(())
+}
+
+/// Microseconds per quarter note of a tempo in BPM
+fn bpm_to_tempo(bpm: f32) -> u32 {
+    (MICROSECONDS_PER_MINUTE as f32 / bpm.max(1.0))
+        .round()
+        .clamp(1.0, 0xFF_FFFF as f32) as u32
+}
+
+/// A recorder shared between the processor and the MIDI outputs it taps
+pub type SharedRecorder = Arc<Mutex<MidiRecorder>>;
+
+/// Thinning of dense controller data when a recording is saved
+///
+/// Pitch bend, pressure and continuous controller changes closer together
+/// than `interval` or smaller than `delta` (in 7-bit steps) are dropped. The
+/// last value before a pause is always kept, so the controller settles
+/// where it was played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControllerThinning {
+    pub interval: Duration,
+    pub delta: u8,
+}
+
+impl ControllerThinning {
+    pub fn new(interval: Duration, delta: u8) -> Self {
+        Self { interval, delta }
+    }
+}
+
+/// What was recorded: a channel message or a meta event
+#[derive(Debug, Clone)]
+enum RecordedKind {
+    Channel { channel: u8, message: MidiMessage },
+    Marker(String),
+    KeySignature { sharps: i8, minor: bool },
+}
+
+/// A recorded event with its time since its take started, its track and its take
+#[derive(Debug, Clone)]
+struct RecordedEvent {
+    timestamp: u64,
+    track: usize,
+    take: usize,
+    kind: RecordedKind,
+}
+
+impl RecordedEvent {
+    fn journal_entry(&self) -> JournalEntry {
+        let (time, track) = (self.timestamp, self.track);
+        match &self.kind {
+            RecordedKind::Channel { channel, message } => {
+                let mut bytes = Vec::with_capacity(3);
+                let event = LiveEvent::Midi {
+                    channel: u4::new(*channel),
+                    message: *message,
+                };
+                // Writing to a Vec cannot fail
+                let _ = event.write_std(&mut bytes);
+                JournalEntry::Channel { time, track, bytes }
+            }
+            RecordedKind::Marker(text) => JournalEntry::Marker {
+                time,
+                track,
+                text: text.clone(),
+            },
+            RecordedKind::KeySignature { sharps, minor } => JournalEntry::KeySignature {
+                time,
+                track,
+                sharps: *sharps,
+                minor: *minor,
+            },
+        }
+    }
+}
 
 pub struct MidiRecorder {
-    events: Vec<(u64, MidiMessage)>,
+    events: Vec<RecordedEvent>,
+    /// Start of the recording on the wall clock
     start_time: Instant,
-    tempo: u32, // Microseconds per quarter note
+    /// Time since the recording started from an external clock, e.g. the audio input
+    clock_time: Option<u64>,
+    /// Start of the current take, in time since the recording started
+    take_start: u64,
+    /// Tempo changes in time order, the first at time 0
+    tempo_map: Vec<TempoChange>,
+    time_signature: TimeSignature,
     ticks_per_beat: u16,
     is_recording: bool,
+    thinning: Option<ControllerThinning>,
+    cleanup: Option<Cleanup>,
+    layout: TrackLayout,
+    /// Track names; track 0 receives everything not sent to another track
+    tracks: Vec<String>,
+    /// Track that channel messages are recorded to
+    current_track: usize,
+    /// Track names per channel in `TrackLayout::Channel`
+    channel_names: HashMap<u8, String>,
+    /// Where the journal of the next recording is written
+    journal_path: Option<PathBuf>,
+    journal: Option<Journal>,
+    /// Events are only recorded while armed
+    armed: bool,
+    /// Number of the current (or last) take, 0 before the first
+    take: usize,
+    take_storage: TakeStorage,
+    punch: Option<PunchRange>,
+    /// Recorded notes still sounding, as (track, channel, key)
+    open_notes: HashSet<(usize, u8, u8)>,
+    /// File and contents of the take that new takes are layered onto
+    overdub: Option<(PathBuf, Vec<u8>)>,
+    /// Registered parameters of the outputs, as (channel, parameter) -> value
+    parameters: BTreeMap<(u8, u8), u8>,
+    /// Whether the current take starts with the registered parameters yet
+    parameters_recorded: bool,
 }
 
 impl Default for MidiRecorder {
@@ -25,9 +273,28 @@ impl Default for MidiRecorder {
         Self {
             events: Vec::new(),
             start_time: Instant::now(),
-            tempo: MICROSECONDS_PER_MINUTE / 120, // 120 BPM default
+            clock_time: None,
+            take_start: 0,
+            tempo_map: vec![TempoChange::new(0, DEFAULT_BPM)],
+            time_signature: TimeSignature::default(),
             ticks_per_beat: DEFAULT_TICKS_PER_BEAT,
             is_recording: false,
+            thinning: None,
+            cleanup: None,
+            layout: TrackLayout::Single,
+            tracks: vec![DEFAULT_TRACK_NAME.to_string()],
+            current_track: 0,
+            channel_names: HashMap::new(),
+            journal_path: None,
+            journal: None,
+            armed: true,
+            take: 0,
+            take_storage: TakeStorage::default(),
+            punch: None,
+            open_notes: HashSet::new(),
+            overdub: None,
+            parameters: BTreeMap::new(),
+            parameters_recorded: false,
         }
     }
 }
@@ -38,16 +305,231 @@ impl MidiRecorder {
         Self::default()
     }
 
+    /// Create a recorder that can be shared with MIDI outputs
+    pub fn shared() -> SharedRecorder {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Thin dense controller data when saving (None keeps every message)
+    pub fn set_thinning(&mut self, thinning: Option<ControllerThinning>) {
+        self.thinning = thinning;
+    }
+
+    /// Clean up the notes when saving, keeping the unprocessed take next to the file
+    pub fn set_cleanup(&mut self, cleanup: Option<Cleanup>) {
+        self.cleanup = cleanup;
+    }
+
+    /// Journal recordings to a file as they are made (see `recover`)
+    pub fn set_journal(&mut self, path: Option<PathBuf>) {
+        self.journal_path = path;
+    }
+
+    /// Set a constant tempo in beats (quarter notes) per minute
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.tempo_map = vec![TempoChange::new(0, bpm)];
+        self.journal_settings();
+    }
+
+    /// Set the tempo map; a change at time 0 keeps the current initial tempo if missing
+    pub fn set_tempo_map(&mut self, mut changes: Vec<TempoChange>) {
+        changes.sort_by_key(|change| change.time);
+        if changes.first().is_none_or(|change| change.time > 0) {
+            changes.insert(0, self.tempo_map[0]);
+            changes[0].time = 0;
+        }
+        self.tempo_map = changes;
+        self.journal_settings();
+    }
+
+    /// Set tempo changes at bar numbers (bar 1 starts the recording)
+    ///
+    /// Bars are converted to times with the time signature and the tempo
+    /// in effect before each change.
+    pub fn set_bar_tempos(&mut self, changes: &[(u32, f32)]) {
+        let TimeSignature {
+            numerator,
+            denominator,
+        } = self.time_signature;
+        let mut changes = changes.to_vec();
+        changes.sort_by_key(|&(bar, _)| bar);
+
+        let mut map = vec![self.tempo_map[0]];
+        let (mut bar, mut time) = (1u32, 0u64);
+        for (change_bar, bpm) in changes {
+            let tempo = map.last().map_or(0, |change| change.tempo as u64);
+            // Bars of e.g. 7/8 are not whole quarters, so multiply before dividing
+            let bars = change_bar.saturating_sub(bar) as u64;
+            time += bars * numerator as u64 * 4 * tempo / denominator as u64;
+            bar = bar.max(change_bar);
+            map.retain(|change| change.time < time);
+            map.push(TempoChange::new(time, bpm));
+        }
+        self.set_tempo_map(map);
+    }
+
+    /// Record a tempo change at the current time, e.g. when the tempo changes mid-take
+    pub fn record_tempo(&mut self, bpm: f32) {
+        if !self.is_recording {
+            return;
+        }
+        let time = self.take_time();
+        self.tempo_map
+            .retain(|change| change.time < time || change.time == 0);
+        self.tempo_map.push(TempoChange::new(time, bpm));
+        self.journal_settings();
+        debug!("Recorded tempo {:.1} BPM at {}μs", bpm, time);
+    }
+
+    pub fn tempo_map(&self) -> &[TempoChange] {
+        &self.tempo_map
+    }
+
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+        self.journal_settings();
+    }
+
+    /// Set the resolution in ticks per quarter note (PPQ)
+    pub fn set_ticks_per_beat(&mut self, ticks_per_beat: u16) {
+        self.ticks_per_beat = ticks_per_beat.clamp(1, 0x7FFF);
+        self.journal_settings();
+    }
+
+    /// Split the saved file into tracks (anything but `Single` writes SMF format 1)
+    pub fn set_layout(&mut self, layout: TrackLayout) {
+        self.layout = layout;
+        self.journal_settings();
+    }
+
+    pub fn layout(&self) -> TrackLayout {
+        self.layout
+    }
+
+    /// Add a named track and return its index
+    pub fn add_track(&mut self, name: &str) -> usize {
+        self.tracks.push(name.to_string());
+        self.journal_settings();
+        self.tracks.len() - 1
+    }
+
+    /// Rename a track, e.g. the default track 0
+    pub fn set_track_name(&mut self, track: usize, name: &str) {
+        if let Some(existing) = self.tracks.get_mut(track) {
+            *existing = name.to_string();
+        }
+        self.journal_settings();
+    }
+
+    /// Record channel messages to a track until changed (0 is the default track)
+    pub fn set_track(&mut self, track: usize) {
+        self.current_track = track.min(self.tracks.len() - 1);
+    }
+
+    /// Name the track of a channel in `TrackLayout::Channel`, e.g. after its string
+    pub fn set_channel_name(&mut self, channel: u8, name: &str) {
+        self.channel_names.insert(channel & 0x0F, name.to_string());
+        self.journal_settings();
+    }
+
+    /// Keep each take in its own file or on its own tracks
+    pub fn set_take_storage(&mut self, storage: TakeStorage) {
+        self.take_storage = storage;
+        self.journal_settings();
+    }
+
+    /// Only record the bars of each take in the range (None records whole takes)
+    pub fn set_punch(&mut self, punch: Option<PunchRange>) {
+        self.punch = punch;
+    }
+
+    /// Layer new takes onto an existing take, which is saved along unchanged
+    ///
+    /// The take's resolution, time signature and tempo map are adopted so the
+    /// new tracks line up with it.
+    pub fn overdub<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let smf = Smf::parse(&data).context("Failed to parse MIDI file")?;
+        let Timing::Metrical(ticks_per_beat) = smf.header.timing else {
+            anyhow::bail!("Cannot overdub {:?}, it has timecode timing", path);
+        };
+        let ticks_per_beat = ticks_per_beat.as_int().clamp(1, 0x7FFF);
+        let tempo_map = TickClock::new(&smf, ticks_per_beat as u64)
+            .tempo_changes()
+            .map(|(time, tempo)| TempoChange { time, tempo })
+            .collect();
+        let time_signature = smf
+            .tracks
+            .iter()
+            .flatten()
+            .find_map(|event| match event.kind {
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, power, _, _)) => {
+                    Some(TimeSignature {
+                        numerator,
+                        denominator: 1 << power.min(6),
+                    })
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        self.ticks_per_beat = ticks_per_beat;
+        self.time_signature = time_signature;
+        self.tempo_map = tempo_map;
+        self.overdub = Some((path.to_path_buf(), data));
+        self.journal_settings();
+        info!("Overdubbing {:?}", path);
+        Ok(())
+    }
+
+    /// Whether new takes are layered onto an existing one
+    pub fn is_overdub(&self) -> bool {
+        self.overdub.is_some()
+    }
+
+    /// Note a registered parameter an output has set, e.g. a pitch bend range
+    ///
+    /// Outputs are set up once, before recording starts, so every take
+    /// begins with the parameters to play back the way it was performed.
+    pub fn set_parameter(&mut self, channel: u8, parameter: u8, value: u8) {
+        self.parameters.insert((channel & 0x0F, parameter), value);
+    }
+
     /// Start recording
+    ///
+    /// Starts a new journal if journaling is enabled; a recording goes on
+    /// without one if it cannot be created. The first take starts right
+    /// away if armed, or else once armed.
     pub fn start(&mut self) {
         self.start_time = Instant::now();
+        self.clock_time = None;
         self.events.clear();
+        self.open_notes.clear();
+        self.take = 0;
         self.is_recording = true;
+        self.journal = self
+            .journal_path
+            .as_deref()
+            .and_then(|path| match Journal::create(path) {
+                Ok(journal) => Some(journal),
+                Err(err) => {
+                    warn!("Recording without a journal: {:#}", err);
+                    None
+                }
+            });
+        self.journal_settings();
         info!("MIDI recording started");
+        if self.armed {
+            self.begin_take(0);
+        }
     }
 
     /// Stop recording
     pub fn stop(&mut self) {
+        if self.is_recording && self.armed {
+            self.end_take();
+        }
         self.is_recording = false;
         info!(
             "MIDI recording stopped, {} events recorded",
@@ -61,107 +543,842 @@ impl MidiRecorder {
         self.is_recording
     }
 
-    /// Record a note on event
-    pub fn record_note_on(&mut self, note: u8, velocity: u8) {
-        if !self.is_recording {
+    /// Arm recording: while recording, this starts a new take
+    pub fn arm(&mut self) {
+        self.arm_at(self.now());
+    }
+
+    /// Arm recording with the new take starting at a time since recording
+    /// started, e.g. the first sample of the audio captured with the take
+    pub fn arm_at(&mut self, start: u64) {
+        if self.armed {
+            return;
+        }
+        self.armed = true;
+        if self.is_recording {
+            self.begin_take(start);
+        }
+    }
+
+    /// Disarm recording: the current take ends, while the recording goes on
+    pub fn disarm(&mut self) {
+        if !self.armed {
             return;
         }
+        if self.is_recording {
+            self.end_take();
+        }
+        self.armed = false;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Number of the current take, or of the last one while disarmed (0 before the first)
+    pub fn take_number(&self) -> usize {
+        self.take
+    }
+
+    /// Stamp the events recorded from now on with a time since recording
+    /// started, e.g. the sample position of the audio they were detected in
+    ///
+    /// Without a time (the default) events are stamped when they are
+    /// recorded. Takes started afterwards also start at the clock's time.
+    pub fn set_time(&mut self, time: Option<u64>) {
+        self.clock_time = time;
+    }
+
+    /// Time since recording started, from the external clock if set
+    fn now(&self) -> u64 {
+        self.clock_time
+            .unwrap_or_else(|| self.start_time.elapsed().as_micros() as u64)
+    }
+
+    /// Time since the current take started
+    fn take_time(&self) -> u64 {
+        self.now().saturating_sub(self.take_start)
+    }
+
+    /// Start a take at a time since recording started; every take begins at bar 1
+    fn begin_take(&mut self, start: u64) {
+        // A take without events keeps its number
+        if self.take == 0 || self.events.iter().any(|event| event.take == self.take) {
+            self.take += 1;
+        }
+        self.take_start = start;
+        self.open_notes.clear();
+        self.parameters_recorded = false;
+        self.journal_append(JournalEntry::Take { number: self.take });
+        info!("Recording take {}", self.take);
+    }
+
+    /// End the notes still sounding in the take
+    fn end_take(&mut self) {
+        self.close_take_notes();
+        let events = self
+            .events
+            .iter()
+            .filter(|event| event.take == self.take)
+            .count();
+        info!("Take {} finished, {} events recorded", self.take, events);
+    }
+
+    /// End the notes still sounding in the take now, or at the punch out if it has passed
+    fn close_take_notes(&mut self) {
+        let mut time = self.take_time();
+        if let (_, Some(punch_out)) = self.punch_times() {
+            time = time.min(punch_out);
+        }
+        self.close_open_notes(time);
+    }
+
+    /// Record note offs for the notes still sounding in the take
+    fn close_open_notes(&mut self, time: u64) {
+        let mut notes: Vec<(usize, u8, u8)> = self.open_notes.drain().collect();
+        notes.sort_unstable();
+        for (track, channel, key) in notes {
+            let message = MidiMessage::NoteOff {
+                key: u7::new(key),
+                vel: u7::new(0),
+            };
+            self.record_at(time, track, RecordedKind::Channel { channel, message });
+        }
+    }
+
+    /// Punch in and punch out times of the punch range within a take
+    fn punch_times(&self) -> (u64, Option<u64>) {
+        match self.punch {
+            Some(punch) => (
+                self.bar_time(punch.start),
+                punch.end.map(|bar| self.bar_time(bar)),
+            ),
+            None => (0, None),
+        }
+    }
+
+    /// Time of the start of a bar (bar 1 starts the take), following the tempo map
+    fn bar_time(&self, bar: u32) -> u64 {
+        let TimeSignature {
+            numerator,
+            denominator,
+        } = self.time_signature;
+        // Bars of e.g. 7/8 are not whole quarters, so multiply before dividing
+        let ticks =
+            bar.saturating_sub(1) as u64 * numerator as u64 * 4 * self.ticks_per_beat as u64
+                / denominator as u64;
+        self.ticks_to_micros(ticks)
+    }
+
+    /// Record a note on event
+    pub fn record_note_on(&mut self, note: u8, velocity: u8) {
+        self.record_note_on_channel(0, note, velocity);
+    }
 
-        let timestamp = self.start_time.elapsed().as_micros() as u64;
+    /// Record a note on event on a specific channel
+    pub fn record_note_on_channel(&mut self, channel: u8, note: u8, velocity: u8) {
         let message = MidiMessage::NoteOn {
             key: u7::new(note),
             vel: u7::new(velocity),
         };
-        self.events.push((timestamp, message));
-        debug!("Recorded note ON: {} at {}μs", note, timestamp);
+        if let Some(timestamp) = self.push(channel, message) {
+            debug!("Recorded note ON: {} at {}μs", note, timestamp);
+        }
     }
 
     /// Record a note off event
     pub fn record_note_off(&mut self, note: u8) {
-        if !self.is_recording {
-            return;
-        }
+        self.record_note_off_channel(0, note);
+    }
 
-        let timestamp = self.start_time.elapsed().as_micros() as u64;
+    /// Record a note off event on a specific channel
+    pub fn record_note_off_channel(&mut self, channel: u8, note: u8) {
         let message = MidiMessage::NoteOff {
             key: u7::new(note),
             vel: u7::new(0),
         };
-        self.events.push((timestamp, message));
-        debug!("Recorded note OFF: {} at {}μs", note, timestamp);
+        if let Some(timestamp) = self.push(channel, message) {
+            debug!("Recorded note OFF: {} at {}μs", note, timestamp);
+        }
+    }
+
+    /// Record any channel message, e.g. a pitch bend or control change
+    pub fn record_message(&mut self, channel: u8, message: MidiMessage) {
+        if let Some(timestamp) = self.push(channel, message) {
+            debug!(
+                "Recorded {:?} on channel {} at {}μs",
+                message, channel, timestamp
+            );
+        }
+    }
+
+    /// Record a channel message to a specific track
+    pub fn record_message_on_track(&mut self, track: usize, channel: u8, message: MidiMessage) {
+        let current = self.current_track;
+        self.set_track(track);
+        self.record_message(channel, message);
+        self.current_track = current;
+    }
+
+    /// Record a raw MIDI message as sent to an output port
+    ///
+    /// Anything that is not a channel message (e.g. SysEx) is ignored.
+    pub fn record_bytes(&mut self, bytes: &[u8]) {
+        if !self.is_recording {
+            return;
+        }
+        match LiveEvent::parse(bytes) {
+            Ok(LiveEvent::Midi { channel, message }) => {
+                self.record_message(channel.as_int(), message)
+            }
+            Ok(_) => {}
+            Err(err) => debug!("Not recording MIDI message {:02X?}: {}", bytes, err),
+        }
+    }
+
+    /// Record a marker meta event, e.g. a chord name
+    pub fn record_marker(&mut self, text: &str) {
+        if let Some(timestamp) = self.push_event(RecordedKind::Marker(text.to_string())) {
+            debug!("Recorded marker '{}' at {}μs", text, timestamp);
+        }
+    }
+
+    /// Record a key signature meta event (negative sharps are flats)
+    pub fn record_key_signature(&mut self, sharps: i8, minor: bool) {
+        let kind = RecordedKind::KeySignature {
+            sharps: sharps.clamp(-7, 7),
+            minor,
+        };
+        if let Some(timestamp) = self.push_event(kind) {
+            debug!("Recorded key signature {} at {}μs", sharps, timestamp);
+        }
+    }
+
+    /// Append a channel message stamped with the current time, if recording
+    fn push(&mut self, channel: u8, message: MidiMessage) -> Option<u64> {
+        self.push_event(RecordedKind::Channel {
+            channel: channel & 0x0F,
+            message,
+        })
+    }
+
+    /// Append an event stamped with the current time, if recording and armed
+    ///
+    /// Outside the punch range nothing is recorded. Note offs are only
+    /// recorded for notes started in the take, so a note held over the
+    /// punch in does not leave a stray note off.
+    fn push_event(&mut self, kind: RecordedKind) -> Option<u64> {
+        if !self.is_recording || !self.armed {
+            return None;
+        }
+
+        let timestamp = self.take_time();
+        let (punch_in, punch_out) = self.punch_times();
+        if let Some(punch_out) = punch_out.filter(|&punch_out| timestamp >= punch_out) {
+            self.close_open_notes(punch_out);
+            return None;
+        }
+        if timestamp < punch_in {
+            return None;
+        }
+
+        if let RecordedKind::Channel { channel, message } = &kind {
+            let note = |key: u7| (self.current_track, *channel, key.as_int());
+            match *message {
+                MidiMessage::NoteOn { key, vel } if vel > 0 => {
+                    self.open_notes.insert(note(key));
+                }
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    let started = self.open_notes.remove(&note(key));
+                    if !started {
+                        return None;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.record_parameters();
+        self.record_at(timestamp, self.current_track, kind);
+        Some(timestamp)
+    }
+
+    /// Record the registered parameters at the start of the take, before its first event
+    ///
+    /// They go to the default track, ahead of the channel data they apply to.
+    /// Takes that stay empty are not given any.
+    fn record_parameters(&mut self) {
+        if std::mem::replace(&mut self.parameters_recorded, true) {
+            return;
+        }
+        let parameters: Vec<((u8, u8), u8)> = self
+            .parameters
+            .iter()
+            .map(|(&key, &value)| (key, value))
+            .collect();
+        for ((channel, parameter), value) in parameters {
+            for (controller, value) in super::rpn_controllers(parameter, value) {
+                let message = MidiMessage::Controller {
+                    controller: u7::new(controller),
+                    value: u7::new(value),
+                };
+                self.record_at(0, 0, RecordedKind::Channel { channel, message });
+            }
+        }
+    }
+
+    /// Append an event to the current take
+    fn record_at(&mut self, timestamp: u64, track: usize, kind: RecordedKind) {
+        let event = RecordedEvent {
+            timestamp,
+            track,
+            take: self.take,
+            kind,
+        };
+        self.journal_append(event.journal_entry());
+        self.events.push(event);
+    }
+
+    /// Write the current settings to the journal, if one is open
+    fn journal_settings(&mut self) {
+        if self.journal.is_none() {
+            return;
+        }
+        let entry = JournalEntry::Settings {
+            ticks_per_beat: self.ticks_per_beat,
+            time_signature: self.time_signature.to_string(),
+            tempo_map: self.tempo_map.clone(),
+            layout: self.layout,
+            tracks: self.tracks.clone(),
+            channel_names: self.channel_names.clone().into_iter().collect(),
+            take_storage: self.take_storage,
+            overdub: self.overdub.as_ref().map(|(path, _)| path.clone()),
+        };
+        self.journal_append(entry);
+    }
+
+    /// Append to the journal; on failure the recording goes on without it
+    fn journal_append(&mut self, entry: JournalEntry) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        if let Err(err) = journal.append(entry) {
+            warn!("Recording journal disabled: {:#}", err);
+            self.journal = None;
+        }
+    }
+
+    /// Write the queued journal entries and flush them to the disk, waiting until they are
+    pub fn sync_journal(&self) {
+        if let Some(journal) = &self.journal {
+            if let Err(err) = journal.sync() {
+                warn!("Failed to sync the recording journal: {:#}", err);
+            }
+        }
+    }
+
+    /// Close the journal, removing it once the recording is safely saved
+    pub fn close_journal(&mut self, remove: bool) {
+        let Some(journal) = self.journal.take() else {
+            return;
+        };
+        let path = journal.path().to_path_buf();
+        if let Err(err) = journal.close() {
+            warn!("{:#}", err);
+        }
+        if remove {
+            if let Err(err) = std::fs::remove_file(&path) {
+                warn!("Failed to remove the recording journal: {}", err);
+            }
+        }
+    }
+
+    /// A copy of the recording without its journal, e.g. to save it on another thread
+    ///
+    /// Notes still sounding end in the copy at the time of the snapshot, as
+    /// they do when recording stops, so a saved snapshot has no hanging notes.
+    pub fn snapshot(&self) -> Self {
+        let mut snapshot = Self {
+            events: self.events.clone(),
+            start_time: self.start_time,
+            clock_time: self.clock_time,
+            take_start: self.take_start,
+            tempo_map: self.tempo_map.clone(),
+            time_signature: self.time_signature,
+            ticks_per_beat: self.ticks_per_beat,
+            is_recording: self.is_recording,
+            thinning: self.thinning,
+            cleanup: self.cleanup.clone(),
+            layout: self.layout,
+            tracks: self.tracks.clone(),
+            current_track: self.current_track,
+            channel_names: self.channel_names.clone(),
+            journal_path: None,
+            journal: None,
+            armed: self.armed,
+            take: self.take,
+            take_storage: self.take_storage,
+            punch: self.punch,
+            open_notes: self.open_notes.clone(),
+            overdub: self.overdub.clone(),
+            parameters: self.parameters.clone(),
+            parameters_recorded: self.parameters_recorded,
+        };
+        if snapshot.is_recording && snapshot.armed {
+            snapshot.close_take_notes();
+        }
+        snapshot
+    }
+
+    /// Rebuild a recording from the journal of an interrupted session
+    pub fn recover<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let entries = journal::read(path.as_ref())?;
+        if !matches!(entries.first(), Some(JournalEntry::Settings { .. })) {
+            anyhow::bail!("{:?} is not a recording journal", path.as_ref());
+        }
+
+        let mut recorder = Self::new();
+        for entry in entries {
+            let (timestamp, track, kind) = match entry {
+                JournalEntry::Settings {
+                    ticks_per_beat,
+                    time_signature,
+                    tempo_map,
+                    layout,
+                    tracks,
+                    channel_names,
+                    take_storage,
+                    overdub,
+                } => {
+                    if let Some(overdub) = overdub.filter(|_| !recorder.is_overdub()) {
+                        if let Err(err) = recorder.overdub(&overdub) {
+                            warn!("Recovering without the overdubbed take: {:#}", err);
+                        }
+                    }
+                    recorder.take_storage = take_storage;
+                    recorder.ticks_per_beat = ticks_per_beat.clamp(1, 0x7FFF);
+                    recorder.time_signature = time_signature.parse().unwrap_or_default();
+                    if !tempo_map.is_empty() {
+                        recorder.set_tempo_map(tempo_map);
+                    }
+                    recorder.layout = layout;
+                    if !tracks.is_empty() {
+                        recorder.tracks = tracks;
+                    }
+                    recorder.channel_names = channel_names.into_iter().collect();
+                    continue;
+                }
+                JournalEntry::Take { number } => {
+                    recorder.take = number;
+                    continue;
+                }
+                JournalEntry::Channel { time, track, bytes } => match LiveEvent::parse(&bytes) {
+                    Ok(LiveEvent::Midi { channel, message }) => (
+                        time,
+                        track,
+                        RecordedKind::Channel {
+                            channel: channel.as_int(),
+                            message,
+                        },
+                    ),
+                    _ => {
+                        warn!("Skipping invalid journal message {:02X?}", bytes);
+                        continue;
+                    }
+                },
+                JournalEntry::Marker { time, track, text } => {
+                    (time, track, RecordedKind::Marker(text))
+                }
+                JournalEntry::KeySignature {
+                    time,
+                    track,
+                    sharps,
+                    minor,
+                } => (time, track, RecordedKind::KeySignature { sharps, minor }),
+            };
+            recorder.events.push(RecordedEvent {
+                timestamp,
+                track: track.min(recorder.tracks.len() - 1),
+                take: recorder.take,
+                kind,
+            });
+        }
+        Ok(recorder)
     }
 
     /// Save recorded MIDI events to a file
+    ///
+    /// The single layout writes one track (SMF format 0). The other layouts
+    /// write format 1: a conductor track with tempo, time signature, markers
+    /// and key signatures, then one named track per channel, zone or
+    /// detection variant. Empty tracks are left out.
+    ///
+    /// With `TakeStorage::Files` each take goes to its own file (see
+    /// `take_path`); otherwise every take gets its own tracks. When
+    /// overdubbing, the new tracks are added to those of the existing take.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         if self.events.is_empty() {
             anyhow::bail!("No MIDI events to save");
         }
 
         let path = path.as_ref();
-        info!("Saving {} MIDI events to {:?}", self.events.len(), path);
+        let base = match &self.overdub {
+            Some((base_path, data)) => Some(
+                Smf::parse(data)
+                    .with_context(|| format!("Failed to parse overdubbed take {:?}", base_path))?,
+            ),
+            None => None,
+        };
+        let events = self.saved_events();
+        match self.take_storage {
+            TakeStorage::Tracks => self.save_events(path, base.as_ref(), events, true),
+            TakeStorage::Files => {
+                let mut takes: BTreeMap<usize, Vec<&RecordedEvent>> = BTreeMap::new();
+                for event in events {
+                    takes.entry(event.take).or_default().push(event);
+                }
+                for (take, events) in takes {
+                    self.save_events(&take_path(path, take), base.as_ref(), events, false)?;
+                }
+                Ok(())
+            }
+        }
+    }
 
-        // Convert events to MIDI track events with delta times
-        let mut track_events = Vec::new();
+    /// Write events to a file, with one set of tracks per take if `split_takes`
+    fn save_events(
+        &self,
+        path: &Path,
+        base: Option<&Smf>,
+        events: Vec<&RecordedEvent>,
+        split_takes: bool,
+    ) -> Result<()> {
+        info!("Saving {} MIDI events to {:?}", events.len(), path);
 
-        // Add tempo meta event at the beginning
-        track_events.push(TrackEvent {
-            delta: u28::new(0),
-            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(self.tempo))),
-        });
+        let (meta, channel): (Vec<&RecordedEvent>, Vec<&RecordedEvent>) = events
+            .iter()
+            .partition(|event| !matches!(event.kind, RecordedKind::Channel { .. }));
+        let split = self.split_tracks(channel, split_takes);
+        let (format, tracks) = match base {
+            Some(base) => {
+                // The existing take has the tempo map, the markers go on the first new track
+                let mut tracks = base.tracks.clone();
+                for (i, (name, events)) in split.iter().enumerate() {
+                    let markers = if i == 0 { meta.as_slice() } else { &[] };
+                    let events = events.iter().chain(markers).copied();
+                    tracks.push(self.track(Some(name), false, events));
+                }
+                (Format::Parallel, tracks)
+            }
+            None if self.layout == TrackLayout::Single && split.len() <= 1 => {
+                let track = self.track(None, true, events.iter().copied());
+                (Format::SingleTrack, vec![track])
+            }
+            None => {
+                let mut tracks = vec![self.track(Some(CONDUCTOR_TRACK_NAME), true, meta)];
+                for (name, events) in &split {
+                    tracks.push(self.track(Some(name), false, events.iter().copied()));
+                }
+                (Format::Parallel, tracks)
+            }
+        };
+
+        // Create SMF structure
+        let header = Header {
+            format,
+            timing: Timing::Metrical(u15::new(self.ticks_per_beat)),
+        };
+        let smf = Smf { header, tracks };
+
+        match &self.cleanup {
+            Some(cleanup) => {
+                let raw_path = raw_take_path(path);
+                write_smf(&smf, &raw_path)?;
+                info!("Unprocessed take saved to {:?}", raw_path);
+                write_smf(&cleanup.apply(&smf)?, path)?;
+            }
+            None => write_smf(&smf, path)?,
+        }
 
-        // Convert recorded events to track events
-        let mut last_timestamp = 0u64;
-        for (timestamp, message) in &self.events {
-            // Calculate delta time in ticks
-            let delta_micros = timestamp.saturating_sub(last_timestamp);
-            let delta_ticks = self.micros_to_ticks(delta_micros);
-
-            track_events.push(TrackEvent {
-                delta: u28::new(delta_ticks),
-                kind: TrackEventKind::Midi {
-                    channel: u4::new(0),
+        info!("MIDI file saved successfully to {:?}", path);
+        Ok(())
+    }
+
+    /// Channel events grouped into named tracks by the layout
+    ///
+    /// With `split_takes`, each track is split further by take when there
+    /// is more than one.
+    fn split_tracks<'a>(
+        &self,
+        events: Vec<&'a RecordedEvent>,
+        split_takes: bool,
+    ) -> Vec<(String, Vec<&'a RecordedEvent>)> {
+        let takes: BTreeSet<usize> = events.iter().map(|event| event.take).collect();
+        let split_takes = split_takes && takes.len() > 1;
+
+        let tracks = if self.layout == TrackLayout::Channel {
+            let mut channels: BTreeMap<u8, Vec<&RecordedEvent>> = BTreeMap::new();
+            for event in events {
+                if let RecordedKind::Channel { channel, .. } = event.kind {
+                    channels.entry(channel).or_default().push(event);
+                }
+            }
+            channels
+                .into_iter()
+                .map(|(channel, events)| {
+                    let name = self
+                        .channel_names
+                        .get(&channel)
+                        .cloned()
+                        .unwrap_or_else(|| format!("Channel {}", channel + 1));
+                    (name, events)
+                })
+                .collect()
+        } else {
+            let mut tracks: Vec<(String, Vec<&RecordedEvent>)> = self
+                .tracks
+                .iter()
+                .map(|name| (name.clone(), Vec::new()))
+                .collect();
+            for event in events {
+                tracks[event.track].1.push(event);
+            }
+            tracks.retain(|(_, events)| !events.is_empty());
+            tracks
+        };
+
+        let mut split = Vec::with_capacity(tracks.len());
+        for (name, events) in tracks {
+            if !split_takes {
+                split.push((self.track_name(&name, None), events));
+                continue;
+            }
+            let mut by_take: BTreeMap<usize, Vec<&RecordedEvent>> = BTreeMap::new();
+            for event in events {
+                by_take.entry(event.take).or_default().push(event);
+            }
+            for (take, events) in by_take {
+                split.push((self.track_name(&name, Some(take)), events));
+            }
+        }
+        split
+    }
+
+    /// Name of a track, marked as an overdub and with its take if split by take
+    fn track_name(&self, name: &str, take: Option<usize>) -> String {
+        let mut labels = Vec::new();
+        if self.overdub.is_some() {
+            labels.push("Overdub".to_string());
+        }
+        if let Some(take) = take {
+            labels.push(format!("Take {}", take));
+        }
+        if labels.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} ({})", name, labels.join(", "))
+        }
+    }
+
+    /// Build one track with delta times
+    ///
+    /// The conductor track also carries the time signature and the tempo map.
+    fn track<'a>(
+        &self,
+        name: Option<&'a str>,
+        conductor: bool,
+        events: impl IntoIterator<Item = &'a RecordedEvent>,
+    ) -> Track<'a> {
+        let mut timed: Vec<(u32, TrackEventKind<'a>)> = Vec::new();
+        if let Some(name) = name {
+            timed.push((
+                0,
+                TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())),
+            ));
+        }
+        if conductor {
+            timed.push((0, TrackEventKind::Meta(self.time_signature_meta())));
+            for change in &self.tempo_map {
+                let tick = self.micros_to_ticks(change.time);
+                timed.push((
+                    tick,
+                    TrackEventKind::Meta(MetaMessage::Tempo(u24::new(change.tempo))),
+                ));
+            }
+        }
+
+        for event in events {
+            let kind = match &event.kind {
+                RecordedKind::Channel { channel, message } => TrackEventKind::Midi {
+                    channel: u4::new(*channel),
                     message: *message,
                 },
-            });
-
-            last_timestamp = *timestamp;
+                RecordedKind::Marker(text) => {
+                    TrackEventKind::Meta(MetaMessage::Marker(text.as_bytes()))
+                }
+                RecordedKind::KeySignature { sharps, minor } => {
+                    TrackEventKind::Meta(MetaMessage::KeySignature(*sharps, *minor))
+                }
+            };
+            timed.push((self.micros_to_ticks(event.timestamp), kind));
         }
+        // Stable, so meta events at a tick stay ahead of the notes there
+        timed.sort_by_key(|(tick, _)| *tick);
+
+        // Ticks are computed from absolute times so rounding does not accumulate
+        let mut last_tick = 0u32;
+        let mut track_events: Vec<TrackEvent> = timed
+            .into_iter()
+            .map(|(tick, kind)| {
+                let delta = tick - last_tick;
+                last_tick = tick;
+                TrackEvent {
+                    delta: u28::new(delta),
+                    kind,
+                }
+            })
+            .collect();
 
-        // Add end of track meta event
         track_events.push(TrackEvent {
             delta: u28::new(0),
             kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
         });
+        Track::from(track_events)
+    }
 
-        // Create SMF structure
-        let header = Header {
-            format: Format::SingleTrack,
-            timing: Timing::Metrical(u15::new(self.ticks_per_beat)),
-        };
+    /// Time signature meta event, with a metronome click per beat (per dotted beat in compound meters)
+    fn time_signature_meta(&self) -> MetaMessage<'static> {
+        let TimeSignature {
+            numerator,
+            denominator,
+        } = self.time_signature;
+        let compound = denominator >= 8 && numerator > 3 && numerator.is_multiple_of(3);
+        let clocks = 96 / denominator as u16 * if compound { 3 } else { 1 };
+        MetaMessage::TimeSignature(
+            numerator,
+            denominator.trailing_zeros() as u8,
+            clocks.min(255) as u8,
+            8,
+        )
+    }
 
-        let track = Track::from(track_events);
-        let smf = Smf {
-            header,
-            tracks: vec![track],
+    /// The events to save, with dense controller data thinned if enabled
+    fn saved_events(&self) -> Vec<&RecordedEvent> {
+        let Some(thinning) = self.thinning else {
+            return self.events.iter().collect();
         };
+        let interval = thinning.interval.as_micros() as u64;
 
-        // Write to file
-        let mut file = File::create(path).context("Failed to create MIDI file")?;
-        smf.write_std(&mut file)
-            .context("Failed to write MIDI data")?;
-        file.flush().context("Failed to flush MIDI file")?;
+        // Time of the next event of the same controller, to keep the last value before a pause
+        let mut next_time = vec![None; self.events.len()];
+        // Streams are kept apart per take, as every take has its own clock
+        let mut upcoming: HashMap<(usize, u8, u16), u64> = HashMap::new();
+        for (i, event) in self.events.iter().enumerate().rev() {
+            if let Some(((channel, key), _)) = controller_value(&event.kind) {
+                next_time[i] = upcoming.insert((event.take, channel, key), event.timestamp);
+            }
+        }
 
-        info!("MIDI file saved successfully to {:?}", path);
-        Ok(())
+        let mut kept: HashMap<(usize, u8, u16), (u64, i32)> = HashMap::new();
+        let mut events = Vec::with_capacity(self.events.len());
+        for (i, event) in self.events.iter().enumerate() {
+            let Some(((channel, controller), value)) = controller_value(&event.kind) else {
+                events.push(event);
+                continue;
+            };
+            let key = (event.take, channel, controller);
+            let keep = match kept.get(&key) {
+                None => true,
+                Some(&(time, last)) => {
+                    let changed = value != last;
+                    // Pitch bend is 14-bit, the delta is in 7-bit steps
+                    let delta = if controller == PITCH_BEND_KEY {
+                        thinning.delta as i32 * 128
+                    } else {
+                        thinning.delta as i32
+                    };
+                    let settles =
+                        next_time[i].is_none_or(|next| next - event.timestamp >= interval);
+                    (changed && settles)
+                        || (event.timestamp - time >= interval && (value - last).abs() >= delta)
+                }
+            };
+            if keep {
+                kept.insert(key, (event.timestamp, value));
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// The notes of the last take, for notation export
+    pub fn take(&self) -> Take {
+        let raw_track = self
+            .tracks
+            .iter()
+            .position(|name| name == RAW_DETECTION_TRACK);
+        // The last take with events, as every take starts from the beginning
+        let take = self.events.last().map(|event| event.take);
+        let messages = self
+            .events
+            .iter()
+            .filter(|event| Some(event.take) == take && Some(event.track) != raw_track)
+            .filter_map(|event| match event.kind {
+                RecordedKind::Channel { channel, message } => {
+                    Some((event.timestamp, channel, message))
+                }
+                _ => None,
+            });
+        let key_signature = self.events.iter().find_map(|event| match event.kind {
+            RecordedKind::KeySignature { sharps, minor } => Some(KeySignature::new(sharps, minor)),
+            _ => None,
+        });
+        Take {
+            tempo: self.tempo_map[0].tempo,
+            tempo_changes: self.tempo_map[1..].to_vec(),
+            time_signature: self.time_signature,
+            key_signature,
+            ..Take::from_messages(messages)
+        }
     }
 
-    /// Convert microseconds to MIDI ticks
+    /// Convert microseconds to MIDI ticks, following the tempo map
     fn micros_to_ticks(&self, micros: u64) -> u32 {
-        // ticks = (microseconds * ticks_per_beat) / tempo
-        let ticks = (micros * self.ticks_per_beat as u64) / self.tempo as u64;
+        // ticks = (microseconds * ticks_per_beat) / tempo, per tempo segment
+        let ticks_per_beat = self.ticks_per_beat as u64;
+        let mut ticks = 0u64;
+        for (i, change) in self.tempo_map.iter().enumerate() {
+            if change.time >= micros {
+                break;
+            }
+            let end = self
+                .tempo_map
+                .get(i + 1)
+                .map_or(micros, |next| next.time.min(micros));
+            ticks += (end - change.time) * ticks_per_beat / change.tempo as u64;
+        }
         ticks.min(u32::MAX as u64) as u32
     }
 
+    /// Convert MIDI ticks to microseconds, following the tempo map
+    fn ticks_to_micros(&self, ticks: u64) -> u64 {
+        let ticks_per_beat = self.ticks_per_beat as u64;
+        let mut start_tick = 0u64;
+        let mut changes = self.tempo_map.iter().peekable();
+        while let Some(change) = changes.next() {
+            let tempo = change.tempo as u64;
+            if let Some(next) = changes.peek() {
+                let end_tick = start_tick + (next.time - change.time) * ticks_per_beat / tempo;
+                if ticks >= end_tick {
+                    start_tick = end_tick;
+                    continue;
+                }
+            }
+            return change.time + (ticks - start_tick) * tempo / ticks_per_beat;
+        }
+        0
+    }
+
     /// Get the number of recorded events
     #[allow(dead_code)]
     pub fn event_count(&self) -> usize {
@@ -176,11 +1393,40 @@ impl MidiRecorder {
     }
 }
 
+/// Stream key of pitch bend and channel pressure in `controller_value`
+const PITCH_BEND_KEY: u16 = 0x100;
+const CHANNEL_PRESSURE_KEY: u16 = 0x101;
+/// Poly pressure keys are offset by the note number
+const POLY_PRESSURE_KEY: u16 = 0x200;
+
+/// The (channel, controller) stream and value of continuous controller data
+///
+/// RPN, NRPN and data entry controllers are parameter settings rather than
+/// continuous data, and channel mode messages are never thinned.
+fn controller_value(kind: &RecordedKind) -> Option<((u8, u16), i32)> {
+    let RecordedKind::Channel { channel, message } = kind else {
+        return None;
+    };
+    let (key, value) = match *message {
+        MidiMessage::PitchBend { bend } => (PITCH_BEND_KEY, bend.0.as_int() as i32),
+        MidiMessage::ChannelAftertouch { vel } => (CHANNEL_PRESSURE_KEY, vel.as_int() as i32),
+        MidiMessage::Aftertouch { key, vel } => {
+            (POLY_PRESSURE_KEY + key.as_int() as u16, vel.as_int() as i32)
+        }
+        MidiMessage::Controller { controller, value } => match controller.as_int() {
+            6 | 38 | 96..=101 | 120.. => return None,
+            controller => (controller as u16, value.as_int() as i32),
+        },
+        _ => return None,
+    };
+    Some(((*channel, key), value))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use midly::num::u14;
     use std::thread;
-    use std::time::Duration;
 
     #[test]
     fn test_recorder_creation() {
@@ -261,6 +1507,654 @@ mod tests {
         assert!(path.exists());
     }
 
+    #[test]
+    fn test_save_with_cleanup() {
+        let mut recorder = MidiRecorder::new();
+        recorder.set_cleanup(Some(Cleanup {
+            min_length: Duration::from_millis(10),
+            merge_gap: Duration::ZERO,
+            overlap: Default::default(),
+            quantize: None,
+            velocity_peak: Some(100),
+        }));
+        recorder.start();
+        recorder.record_note_on(60, 80);
+        thread::sleep(Duration::from_millis(30));
+        recorder.record_note_off(60);
+        recorder.record_note_on(62, 40);
+        recorder.record_note_off(62);
+        recorder.stop();
+
+        let path = std::env::temp_dir().join("test_cleanup_recording.mid");
+        recorder.save(&path).unwrap();
+        // The blip is dropped from the cleaned take and kept in the raw one
+        let cleaned = Take::load(&path).unwrap();
+        assert_eq!(cleaned.notes.len(), 1);
+        assert_eq!(cleaned.notes[0].velocity, 100);
+        let raw = Take::load(raw_take_path(&path)).unwrap();
+        assert_eq!(raw.notes.len(), 2);
+    }
+
+    #[test]
+    fn test_recover_from_journal() {
+        let path = std::env::temp_dir().join("test_recover.journal");
+        let mut recorder = MidiRecorder::new();
+        recorder.set_journal(Some(path.clone()));
+        recorder.set_layout(TrackLayout::Detection);
+        recorder.start();
+        let raw = recorder.add_track(RAW_DETECTION_TRACK);
+        recorder.record_marker("Em");
+        recorder.record_note_on_channel(2, 52, 90);
+        recorder.record_message_on_track(
+            raw,
+            2,
+            MidiMessage::NoteOn {
+                key: u7::new(53),
+                vel: u7::new(90),
+            },
+        );
+        recorder.record_note_off_channel(2, 52);
+        recorder.set_bpm(90.0);
+        // The session ends without saving, as in a crash after the journal is written
+        recorder.sync_journal();
+
+        let recovered = MidiRecorder::recover(&path).unwrap();
+        assert_eq!(recovered.event_count(), 4);
+        assert_eq!(recovered.layout(), TrackLayout::Detection);
+        assert_eq!(recovered.tracks, recorder.tracks);
+        assert_eq!(recovered.tempo_map(), recorder.tempo_map());
+        assert_eq!(recovered.events[2].track, raw);
+        assert_eq!(recovered.take().notes, recorder.take().notes);
+
+        // Closing after a save removes the journal
+        recorder.close_journal(true);
+        assert!(!path.exists());
+        assert!(MidiRecorder::recover(&path).is_err());
+    }
+
+    #[test]
+    fn test_record_on_channel() {
+        let mut recorder = MidiRecorder::new();
+        recorder.start();
+        recorder.record_note_on_channel(3, 52, 80);
+        recorder.record_note_off_channel(3, 52);
+        recorder.stop();
+
+        assert_eq!(recorder.event_count(), 2);
+        assert!(recorder
+            .events
+            .iter()
+            .all(|e| matches!(e.kind, RecordedKind::Channel { channel: 3, .. })));
+    }
+
+    #[test]
+    fn test_save_markers() {
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.start();
+        recorder.record_marker("Am7");
+        recorder.record_key_signature(-1, true);
+        recorder.record_note_on(57, 80);
+        recorder.record_note_off(57);
+        recorder.stop();
+
+        let path = env::temp_dir().join("test_markers.mid");
+        recorder.save(&path).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        assert!(smf.tracks[0]
+            .iter()
+            .any(|e| matches!(e.kind, TrackEventKind::Meta(MetaMessage::Marker(b"Am7")))));
+        assert!(smf.tracks[0].iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::KeySignature(-1, true))
+        )));
+    }
+
+    #[test]
+    fn test_record_bytes() {
+        use midly::PitchBend;
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.start();
+        recorder.record_bytes(&[0xC1, 25]);
+        recorder.record_bytes(&[0xE3, 0x00, 0x50]);
+        recorder.record_bytes(&[0xB3, 74, 90]);
+        recorder.record_bytes(&[0xD3, 100]);
+        recorder.record_bytes(&[0xA3, 52, 60]);
+        recorder.record_bytes(&[0xF0, 0x7E, 0xF7]);
+        recorder.stop();
+        assert_eq!(recorder.event_count(), 5);
+
+        let path = env::temp_dir().join("test_controllers.mid");
+        recorder.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        let messages: Vec<(u8, MidiMessage)> = smf.tracks[0]
+            .iter()
+            .filter_map(|e| match e.kind {
+                TrackEventKind::Midi { channel, message } => Some((channel.as_int(), message)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, MidiMessage::ProgramChange { program: 25.into() }),
+                (
+                    3,
+                    MidiMessage::PitchBend {
+                        bend: PitchBend(u14::new(0x50 << 7))
+                    }
+                ),
+                (
+                    3,
+                    MidiMessage::Controller {
+                        controller: 74.into(),
+                        value: 90.into()
+                    }
+                ),
+                (3, MidiMessage::ChannelAftertouch { vel: 100.into() }),
+                (
+                    3,
+                    MidiMessage::Aftertouch {
+                        key: 52.into(),
+                        vel: 60.into()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_controller_thinning() {
+        let mut recorder = MidiRecorder::new();
+        // A bend every millisecond for 100ms, then a note off
+        for i in 0..100u16 {
+            recorder.events.push(RecordedEvent {
+                timestamp: i as u64 * 1000,
+                track: 0,
+                take: 1,
+                kind: RecordedKind::Channel {
+                    channel: 0,
+                    message: MidiMessage::PitchBend {
+                        bend: midly::PitchBend(u14::new(8192 + i * 20)),
+                    },
+                },
+            });
+        }
+        recorder.events.push(RecordedEvent {
+            timestamp: 100_000,
+            track: 0,
+            take: 1,
+            kind: RecordedKind::Channel {
+                channel: 0,
+                message: MidiMessage::NoteOff {
+                    key: 60.into(),
+                    vel: 0.into(),
+                },
+            },
+        });
+        assert_eq!(recorder.saved_events().len(), 101);
+
+        recorder.set_thinning(Some(ControllerThinning::new(Duration::from_millis(10), 1)));
+        let events = recorder.saved_events();
+        // One bend per 10ms, the final bend value and the note off
+        assert_eq!(events.len(), 12);
+        assert!(matches!(
+            events[10].kind,
+            RecordedKind::Channel {
+                message: MidiMessage::PitchBend { bend },
+                ..
+            } if bend.0.as_int() == 8192 + 99 * 20
+        ));
+    }
+
+    #[test]
+    fn test_take() {
+        let mut recorder = MidiRecorder::new();
+        recorder.start();
+        recorder.record_marker("E");
+        recorder.record_note_on_channel(2, 52, 80);
+        thread::sleep(Duration::from_millis(5));
+        recorder.record_note_off_channel(2, 52);
+        recorder.stop();
+
+        let take = recorder.take();
+        assert_eq!(take.notes.len(), 1);
+        assert_eq!((take.notes[0].note, take.notes[0].channel), (52, 2));
+        assert!(take.notes[0].end > take.notes[0].start);
+        assert_eq!(take.bpm(), 120.0);
+    }
+
+    /// Track names of a saved file, in order
+    fn track_names(smf: &Smf) -> Vec<String> {
+        smf.tracks
+            .iter()
+            .map(|track| {
+                track
+                    .iter()
+                    .find_map(|e| match e.kind {
+                        TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+                            Some(String::from_utf8_lossy(name).into_owned())
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_track_layout_parse() {
+        assert_eq!(
+            "Channel".parse::<TrackLayout>().unwrap(),
+            TrackLayout::Channel
+        );
+        assert_eq!(
+            "detection".parse::<TrackLayout>().unwrap(),
+            TrackLayout::Detection
+        );
+        assert!("strings".parse::<TrackLayout>().is_err());
+    }
+
+    #[test]
+    fn test_save_track_per_channel() {
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.set_layout(TrackLayout::Channel);
+        recorder.set_channel_name(5, "String 6 (E2)");
+        recorder.start();
+        recorder.record_key_signature(1, false);
+        recorder.record_note_on_channel(5, 40, 80);
+        recorder.record_note_on_channel(0, 64, 80);
+        recorder.record_note_off_channel(5, 40);
+        recorder.record_note_off_channel(0, 64);
+        recorder.stop();
+
+        let path = env::temp_dir().join("test_track_per_channel.mid");
+        recorder.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(
+            track_names(&smf),
+            vec!["Conductor", "Channel 1", "String 6 (E2)"]
+        );
+        // Tempo, time and key signature live on the conductor track only
+        assert!(smf.tracks[0]
+            .iter()
+            .any(|e| matches!(e.kind, TrackEventKind::Meta(MetaMessage::Tempo(_)))));
+        assert!(smf.tracks[0].iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, _, _))
+        )));
+        assert!(smf.tracks[0].iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::KeySignature(1, false))
+        )));
+        assert!(smf.tracks[2].iter().all(|e| match e.kind {
+            TrackEventKind::Midi { channel, .. } => channel.as_int() == 5,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn test_save_named_tracks() {
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.set_layout(TrackLayout::Detection);
+        recorder.set_track_name(0, "Fuzzy-resolved");
+        let raw = recorder.add_track(RAW_DETECTION_TRACK);
+        let unused = recorder.add_track("Unused");
+        assert_eq!((raw, unused), (1, 2));
+        recorder.start();
+        let raw_note = MidiMessage::NoteOn {
+            key: 61.into(),
+            vel: 80.into(),
+        };
+        recorder.record_message_on_track(raw, 0, raw_note);
+        recorder.record_note_on(60, 80);
+        recorder.record_note_off(60);
+        recorder.stop();
+
+        let path = env::temp_dir().join("test_named_tracks.mid");
+        recorder.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        assert_eq!(
+            track_names(&smf),
+            vec!["Conductor", "Fuzzy-resolved", RAW_DETECTION_TRACK]
+        );
+        // The take holds only what was played
+        let take = recorder.take();
+        assert_eq!(take.notes.len(), 1);
+        assert_eq!(take.notes[0].note, 60);
+    }
+
+    #[test]
+    fn test_tempo_map_ticks() {
+        let mut recorder = MidiRecorder::new();
+        recorder.set_ticks_per_beat(960);
+        recorder.set_tempo_map(vec![
+            TempoChange::new(0, 120.0),
+            TempoChange::new(1_000_000, 60.0),
+        ]);
+        // Two beats at 120 BPM, then one beat per second
+        assert_eq!(recorder.micros_to_ticks(1_000_000), 1920);
+        assert_eq!(recorder.micros_to_ticks(2_000_000), 2880);
+
+        // A map without a change at 0 keeps the initial tempo
+        recorder.set_bpm(100.0);
+        recorder.set_tempo_map(vec![TempoChange::new(600_000, 150.0)]);
+        assert_eq!(recorder.tempo_map().len(), 2);
+        assert_eq!(recorder.tempo_map()[0].bpm(), 100.0);
+    }
+
+    #[test]
+    fn test_tracked_tempo_map() {
+        use crate::rhythm::{BeatTracker, Onset};
+
+        // A performance that speeds up from 100 to 120 BPM, starting after a pause
+        let mut time = 1_200_000.0;
+        let mut onsets = Vec::new();
+        for beat in 0..32 {
+            onsets.push(Onset {
+                time: time as u64,
+                strength: 1.0,
+            });
+            time += 600_000.0 - 100_000.0 * (beat as f64 / 31.0);
+        }
+        let tracker = BeatTracker::track(onsets.clone(), 60.0, 200.0);
+
+        let mut recorder = MidiRecorder::new();
+        recorder.set_tempo_map(tracker.tempo_map(120.0));
+        // Every note lands on a quarter note, after two beats of lead-in
+        assert!(recorder.micros_to_ticks(onsets[0].time).abs_diff(960) <= 24);
+        for onset in &onsets {
+            let ticks = recorder.micros_to_ticks(onset.time);
+            let offset = (ticks + 240) % 480;
+            assert!((offset as i32 - 240).abs() <= 24, "ticks {}", ticks);
+        }
+    }
+
+    #[test]
+    fn test_bar_tempos() {
+        let mut recorder = MidiRecorder::new();
+        recorder.set_bpm(60.0);
+        recorder.set_time_signature("6/8".parse().unwrap());
+        recorder.set_bar_tempos(&[(3, 120.0), (5, 90.0)]);
+        // A 6/8 bar is three quarters: 3s at 60 BPM, 1.5s at 120 BPM
+        let times: Vec<u64> = recorder.tempo_map().iter().map(|c| c.time).collect();
+        assert_eq!(times, vec![0, 6_000_000, 9_000_000]);
+
+        recorder.set_bar_tempos(&[(1, 72.0)]);
+        assert_eq!(recorder.tempo_map().len(), 1);
+        assert_eq!(recorder.tempo_map()[0].tempo, 833_333);
+    }
+
+    #[test]
+    fn test_save_tempo_and_time_signature() {
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.set_bpm(90.0);
+        recorder.set_time_signature("3/4".parse().unwrap());
+        recorder.set_ticks_per_beat(960);
+        recorder.start();
+        recorder.record_note_on(60, 80);
+        recorder.record_tempo(140.0);
+        recorder.record_note_off(60);
+        recorder.stop();
+        assert_eq!(recorder.tempo_map().len(), 2);
+
+        let path = env::temp_dir().join("test_tempo_map.mid");
+        recorder.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        assert_eq!(smf.header.timing, Timing::Metrical(u15::new(960)));
+        let tempos: Vec<u32> = smf.tracks[0]
+            .iter()
+            .filter_map(|e| match e.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => Some(tempo.as_int()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tempos, vec![666_667, 428_571]);
+        assert!(smf.tracks[0].iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8))
+        )));
+        assert_eq!(recorder.take().time_signature.numerator, 3);
+    }
+
+    /// Pretend the recording started `millis` ago
+    fn rewind(recorder: &mut MidiRecorder, millis: u64) {
+        recorder.start_time = Instant::now() - Duration::from_millis(millis);
+    }
+
+    #[test]
+    fn test_take_numbering() {
+        let mut recorder = MidiRecorder::new();
+        recorder.disarm();
+        recorder.start();
+        assert_eq!(recorder.take_number(), 0);
+        recorder.record_note_on(60, 80);
+        assert_eq!(recorder.event_count(), 0);
+
+        recorder.arm();
+        assert_eq!(recorder.take_number(), 1);
+        // A take without events keeps its number
+        recorder.disarm();
+        recorder.arm();
+        assert_eq!(recorder.take_number(), 1);
+        recorder.record_note_on(60, 80);
+        recorder.disarm();
+        // The note still sounding is ended with the take
+        assert_eq!(recorder.event_count(), 2);
+        recorder.record_note_off(60);
+        assert_eq!(recorder.event_count(), 2);
+
+        recorder.arm();
+        assert_eq!(recorder.take_number(), 2);
+    }
+
+    #[test]
+    fn test_takes_as_files() {
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.start();
+        recorder.record_note_on(60, 80);
+        recorder.record_note_off(60);
+        recorder.disarm();
+        recorder.arm();
+        recorder.record_note_on(62, 80);
+        recorder.record_note_off(62);
+        recorder.stop();
+
+        let path = env::temp_dir().join("test_takes_as_files.mid");
+        let second = take_path(&path, 2);
+        let _ = std::fs::remove_file(&second);
+        recorder.save(&path).unwrap();
+        for (path, key) in [(path, 60), (second, 62)] {
+            let data = std::fs::read(&path).unwrap();
+            let smf = Smf::parse(&data).unwrap();
+            assert_eq!(smf.header.format, Format::SingleTrack);
+            let keys: Vec<u8> = smf.tracks[0]
+                .iter()
+                .filter_map(|e| match e.kind {
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOn { key, .. },
+                        ..
+                    } => Some(key.as_int()),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(keys, vec![key]);
+        }
+        // Notation export uses the last take
+        assert_eq!(recorder.take().notes[0].note, 62);
+    }
+
+    #[test]
+    fn test_takes_as_tracks() {
+        use std::env;
+        let mut recorder = MidiRecorder::new();
+        recorder.set_take_storage(TakeStorage::Tracks);
+        recorder.start();
+        recorder.record_note_on(60, 80);
+        recorder.record_note_off(60);
+        recorder.disarm();
+        recorder.arm();
+        recorder.record_note_on(62, 80);
+        recorder.record_note_off(62);
+        recorder.stop();
+
+        let path = env::temp_dir().join("test_takes_as_tracks.mid");
+        recorder.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(
+            track_names(&smf),
+            vec!["Conductor", "Guitar (Take 1)", "Guitar (Take 2)"]
+        );
+    }
+
+    #[test]
+    fn test_take_path() {
+        assert_eq!(
+            take_path(Path::new("takes/solo.mid"), 1),
+            PathBuf::from("takes/solo.mid")
+        );
+        assert_eq!(
+            take_path(Path::new("takes/solo.mid"), 3),
+            PathBuf::from("takes/solo-take3.mid")
+        );
+        assert_eq!(
+            "tracks".parse::<TakeStorage>().unwrap(),
+            TakeStorage::Tracks
+        );
+        assert!("lanes".parse::<TakeStorage>().is_err());
+    }
+
+    #[test]
+    fn test_punch_range() {
+        let mut recorder = MidiRecorder::new();
+        // A 4/4 bar lasts 100ms at 2400 BPM
+        recorder.set_bpm(2400.0);
+        recorder.set_punch(Some(PunchRange::new(2, Some(3))));
+        assert_eq!(recorder.punch_times(), (100_000, Some(200_000)));
+        recorder.start();
+
+        // Held over the punch in: neither its note on nor its note off is recorded
+        recorder.record_note_on(60, 80);
+        rewind(&mut recorder, 120);
+        recorder.record_note_off(60);
+        recorder.record_note_on(64, 80);
+        assert_eq!(recorder.event_count(), 1);
+
+        // Still sounding at the punch out: ended there
+        rewind(&mut recorder, 250);
+        recorder.record_note_off(64);
+        recorder.record_note_on(67, 80);
+        recorder.stop();
+        let times: Vec<u64> = recorder.events.iter().map(|e| e.timestamp).collect();
+        assert_eq!(times.len(), 2);
+        assert!((100_000..200_000).contains(&times[0]));
+        assert_eq!(times[1], 200_000);
+        assert!(matches!(
+            recorder.events[1].kind,
+            RecordedKind::Channel {
+                message: MidiMessage::NoteOff { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_bar_time() {
+        let mut recorder = MidiRecorder::new();
+        recorder.set_bpm(60.0);
+        recorder.set_time_signature("6/8".parse().unwrap());
+        recorder.set_bar_tempos(&[(3, 120.0)]);
+        // Two bars of three quarters at 60 BPM, then 1.5s per bar
+        assert_eq!(recorder.bar_time(1), 0);
+        assert_eq!(recorder.bar_time(3), 6_000_000);
+        assert_eq!(recorder.bar_time(4), 7_500_000);
+    }
+
+    #[test]
+    fn test_overdub() {
+        use std::env;
+        let base_path = env::temp_dir().join("test_overdub_base.mid");
+        let mut base = MidiRecorder::new();
+        base.set_bpm(90.0);
+        base.set_time_signature("3/4".parse().unwrap());
+        base.set_ticks_per_beat(960);
+        base.start();
+        base.record_note_on(48, 80);
+        base.record_note_off(48);
+        base.stop();
+        base.save(&base_path).unwrap();
+
+        let mut recorder = MidiRecorder::new();
+        recorder.overdub(&base_path).unwrap();
+        assert!(recorder.is_overdub());
+        assert_eq!(recorder.tempo_map()[0].tempo, 666_667);
+        assert_eq!(recorder.time_signature.numerator, 3);
+        assert_eq!(recorder.ticks_per_beat, 960);
+        recorder.start();
+        recorder.record_note_on(72, 80);
+        recorder.record_note_off(72);
+        recorder.stop();
+
+        let path = env::temp_dir().join("test_overdub.mid");
+        recorder.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(smf.header.timing, Timing::Metrical(u15::new(960)));
+        // The existing take, then the new layer
+        assert_eq!(track_names(&smf), vec!["", "Guitar (Overdub)"]);
+        let notes = |track: &Track| {
+            track
+                .iter()
+                .filter(|e| matches!(e.kind, TrackEventKind::Midi { .. }))
+                .count()
+        };
+        assert_eq!(notes(&smf.tracks[0]), 2);
+        assert_eq!(notes(&smf.tracks[1]), 2);
+    }
+
+    #[test]
+    fn test_external_clock() {
+        let mut recorder = MidiRecorder::new();
+        recorder.start();
+        recorder.set_time(Some(1_500_000));
+        recorder.record_note_on(60, 80);
+        recorder.set_time(Some(2_250_000));
+        recorder.record_note_off(60);
+
+        // A take armed later starts at the clock's time
+        recorder.disarm();
+        recorder.set_time(Some(4_000_000));
+        recorder.arm();
+        recorder.set_time(Some(4_100_000));
+        recorder.record_note_on(62, 80);
+        recorder.stop();
+
+        let times: Vec<(usize, u64)> = recorder
+            .events
+            .iter()
+            .map(|e| (e.take, e.timestamp))
+            .collect();
+        assert_eq!(
+            times,
+            vec![(1, 1_500_000), (1, 2_250_000), (2, 100_000), (2, 100_000)]
+        );
+    }
+
     #[test]
     fn test_micros_to_ticks() {
         let recorder = MidiRecorder::new();
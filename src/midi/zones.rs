@@ -0,0 +1,192 @@
+use crate::config::ZoneConfig;
+
+/// Where a note is sent: output port, channel and (zone-transposed) note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZoneRoute {
+    /// Output index, 0 = main MIDI port, `n` = `ZoneRouter::ports()[n - 1]`
+    pub output: usize,
+    pub channel: u8,
+    pub note: u8,
+    /// Index of the zone in the configuration (None without zones)
+    pub zone: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Zone {
+    notes: (u8, u8),
+    output: usize,
+    channel: u8,
+    transpose: i8,
+}
+
+/// Routes notes to keyboard-split zones by note range
+///
+/// Without zones every note goes to the main port on the default channel.
+/// With zones a note is sent to every zone whose range contains it (so
+/// overlapping zones layer) and dropped if no zone matches.
+#[derive(Debug, Clone)]
+pub struct ZoneRouter {
+    zones: Vec<Zone>,
+    ports: Vec<String>,
+    default_channel: u8,
+}
+
+impl ZoneRouter {
+    /// Create a router; `main_port` is the port of output 0, zones naming it share that output
+    pub fn new(zones: &[ZoneConfig], default_channel: u8, main_port: Option<&str>) -> Self {
+        let mut ports: Vec<String> = Vec::new();
+        let zones = zones
+            .iter()
+            .map(|zone| {
+                let output = match zone.port.as_deref() {
+                    None => 0,
+                    Some(port) if Some(port) == main_port => 0,
+                    Some(port) => match ports.iter().position(|p| p == port) {
+                        Some(index) => index + 1,
+                        None => {
+                            ports.push(port.to_string());
+                            ports.len()
+                        }
+                    },
+                };
+                Zone {
+                    notes: (zone.note_low, zone.note_high),
+                    output,
+                    channel: zone.channel.unwrap_or(default_channel) & 0x0F,
+                    transpose: zone.transpose,
+                }
+            })
+            .collect();
+
+        Self {
+            zones,
+            ports,
+            default_channel,
+        }
+    }
+
+    /// Additional output ports used by zones, in output index order (starting at 1)
+    pub fn ports(&self) -> &[String] {
+        &self.ports
+    }
+
+    /// Routes for a note
+    pub fn route(&self, note: u8) -> Vec<ZoneRoute> {
+        if self.zones.is_empty() {
+            return vec![ZoneRoute {
+                output: 0,
+                channel: self.default_channel,
+                note,
+                zone: None,
+            }];
+        }
+
+        self.zones
+            .iter()
+            .enumerate()
+            .filter(|(_, zone)| (zone.notes.0..=zone.notes.1).contains(&note))
+            .filter_map(|(index, zone)| {
+                let transposed = note as i16 + zone.transpose as i16;
+                (0..=127).contains(&transposed).then_some(ZoneRoute {
+                    output: zone.output,
+                    channel: zone.channel,
+                    note: transposed as u8,
+                    zone: Some(index),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(low: u8, high: u8) -> ZoneConfig {
+        ZoneConfig {
+            note_low: low,
+            note_high: high,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_no_zones_routes_to_default() {
+        let router = ZoneRouter::new(&[], 3, None);
+        assert_eq!(
+            router.route(60),
+            vec![ZoneRoute {
+                output: 0,
+                channel: 3,
+                note: 60,
+                zone: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_split_by_note_range() {
+        let zones = [
+            ZoneConfig {
+                channel: Some(1),
+                transpose: -12,
+                ..zone(0, 51)
+            },
+            ZoneConfig {
+                channel: Some(2),
+                ..zone(52, 127)
+            },
+        ];
+        let router = ZoneRouter::new(&zones, 0, None);
+        assert_eq!(
+            router.route(45),
+            vec![ZoneRoute {
+                output: 0,
+                channel: 1,
+                note: 33,
+                zone: Some(0)
+            }]
+        );
+        assert_eq!(router.route(64)[0].channel, 2);
+        assert_eq!(router.route(64)[0].zone, Some(1));
+    }
+
+    #[test]
+    fn test_layering() {
+        let zones = [
+            ZoneConfig {
+                channel: Some(1),
+                ..zone(0, 127)
+            },
+            ZoneConfig {
+                channel: Some(5),
+                ..zone(60, 72)
+            },
+        ];
+        let router = ZoneRouter::new(&zones, 0, None);
+        assert_eq!(router.route(40).len(), 1);
+        assert_eq!(router.route(40)[0].channel, 1);
+        // Overlapping zones layer
+        let channels: Vec<u8> = router.route(64).iter().map(|route| route.channel).collect();
+        assert_eq!(channels, vec![1, 5]);
+    }
+
+    #[test]
+    fn test_ports_and_unmatched_notes() {
+        let zones = [
+            ZoneConfig {
+                port: Some("Bass Synth".to_string()),
+                ..zone(0, 51)
+            },
+            ZoneConfig {
+                port: Some("Main".to_string()),
+                ..zone(52, 76)
+            },
+        ];
+        let router = ZoneRouter::new(&zones, 0, Some("Main"));
+        assert_eq!(router.ports(), &["Bass Synth".to_string()]);
+        assert_eq!(router.route(40)[0].output, 1);
+        assert_eq!(router.route(60)[0].output, 0);
+        assert!(router.route(90).is_empty());
+    }
+}
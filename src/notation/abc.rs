@@ -0,0 +1,208 @@
+use super::beam::{self, Beam};
+use super::{NotatedEvent, Pitch, PitchSpeller, Score};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Measures per line of ABC music
+const MEASURES_PER_LINE: usize = 4;
+
+/// Render a score as ABC notation
+///
+/// Lengths are multiples of an eighth note (`L:1/8`). Accidentals are
+/// written where they differ from the key signature or an earlier
+/// accidental in the measure, and beamed notes are written without spaces
+/// between them.
+pub fn score(score: &Score) -> String {
+    let speller = PitchSpeller::new(score.key);
+    let quantizer = score.quantizer();
+
+    let mut abc = String::from("X:1\n");
+    let _ = writeln!(abc, "T:{}", score.title.as_deref().unwrap_or("Untitled"));
+    let _ = writeln!(abc, "M:{}", score.time_signature);
+    abc.push_str("L:1/8\n");
+    let _ = writeln!(abc, "Q:1/4={:.0}", score.bpm());
+    let tonic = speller.spell(60 + score.key.tonic());
+    let _ = writeln!(
+        abc,
+        "K:{}{}{} clef=treble-8",
+        tonic.step,
+        match tonic.alter {
+            1 => "#",
+            -1 => "b",
+            _ => "",
+        },
+        if score.key.minor { "m" } else { "" }
+    );
+
+    for (i, measure) in score.measures.iter().enumerate() {
+        let beams = beam::beams(&measure.events, &quantizer, score.time_signature);
+        // Accidentals carry to the end of the measure, per letter and octave
+        let mut accidentals: HashMap<(char, i32), i8> = HashMap::new();
+        for (event, beams) in measure.events.iter().zip(&beams) {
+            abc.push_str(&event_text(score, &speller, event, &mut accidentals));
+            let beamed_to_next = beams
+                .iter()
+                .any(|&(level, beam)| level == 1 && matches!(beam, Beam::Begin | Beam::Continue));
+            if !beamed_to_next {
+                abc.push(' ');
+            }
+        }
+
+        let last = i + 1 == score.measures.len();
+        abc.push_str(if last { "|]\n" } else { "| " });
+        if !last && (i + 1) % MEASURES_PER_LINE == 0 {
+            abc.pop();
+            abc.push('\n');
+        }
+    }
+    abc
+}
+
+/// A note, chord or rest with its length and tie
+fn event_text(
+    score: &Score,
+    speller: &PitchSpeller,
+    event: &NotatedEvent,
+    accidentals: &mut HashMap<(char, i32), i8>,
+) -> String {
+    let length = length(score, event.duration);
+    let mut pitch_text = |note: u8| {
+        let pitch = speller.spell(note);
+        let current = accidentals
+            .get(&(pitch.step, pitch.octave))
+            .copied()
+            .unwrap_or_else(|| speller.signature_alter(pitch.step));
+        let accidental = if pitch.alter == current {
+            String::new()
+        } else {
+            accidentals.insert((pitch.step, pitch.octave), pitch.alter);
+            match pitch.alter {
+                0 => "=".to_string(),
+                a if a > 0 => "^".repeat(a as usize),
+                a => "_".repeat(a.unsigned_abs() as usize),
+            }
+        };
+        format!("{}{}", accidental, note_name(pitch))
+    };
+
+    let mut text = match event.notes.as_slice() {
+        [] => format!("z{}", length),
+        [note] => format!("{}{}", pitch_text(*note), length),
+        notes => {
+            let pitches: String = notes.iter().map(|&note| pitch_text(note)).collect();
+            format!("[{}]{}", pitches, length)
+        }
+    };
+    if event.tie_start {
+        text.push('-');
+    }
+    text
+}
+
+/// Length in eighth notes, e.g. "3" for a dotted quarter or "/2" for a sixteenth
+fn length(score: &Score, duration: u32) -> String {
+    // An eighth note is half a quarter's divisions
+    let (mut numerator, mut denominator) = (duration * 2, score.divisions);
+    let divisor = gcd(numerator, denominator);
+    numerator /= divisor;
+    denominator /= divisor;
+    match (numerator, denominator) {
+        (1, 1) => String::new(),
+        (n, 1) => n.to_string(),
+        (1, d) => format!("/{}", d),
+        (n, d) => format!("{}/{}", n, d),
+    }
+}
+
+/// ABC note name with octave: C = middle C, c an octave above, C, an octave below
+fn note_name(pitch: Pitch) -> String {
+    if pitch.octave >= 5 {
+        format!(
+            "{}{}",
+            pitch.step.to_ascii_lowercase(),
+            "'".repeat((pitch.octave - 5) as usize)
+        )
+    } else {
+        format!(
+            "{}{}",
+            pitch.step,
+            ",".repeat((4 - pitch.octave).max(0) as usize)
+        )
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a.max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{KeySignature, Quantizer, Take, TakeNote};
+
+    fn take(notes: &[(u8, f64, f64)], key: KeySignature) -> Take {
+        Take {
+            notes: notes
+                .iter()
+                .map(|&(note, start, end)| TakeNote {
+                    note,
+                    channel: 0,
+                    velocity: 80,
+                    start: (start * 500_000.0) as u64,
+                    end: (end * 500_000.0) as u64,
+                })
+                .collect(),
+            key_signature: Some(key),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_note_names_and_lengths() {
+        let speller = PitchSpeller::new(KeySignature::default());
+        assert_eq!(note_name(speller.spell(60)), "C");
+        assert_eq!(note_name(speller.spell(72)), "c");
+        assert_eq!(note_name(speller.spell(84)), "c'");
+        assert_eq!(note_name(speller.spell(40)), "E,,");
+
+        let score = Score::new(&Take::default(), &Quantizer::new(4));
+        assert_eq!(length(&score, 2), "");
+        assert_eq!(length(&score, 6), "3");
+        assert_eq!(length(&score, 1), "/2");
+        assert_eq!(length(&score, 3), "3/2");
+    }
+
+    #[test]
+    fn test_abc_score() {
+        // A minor: beamed eighths with the raised seventh, then a tied quarter
+        let take = take(
+            &[
+                (69, 0.0, 0.5),
+                (68, 0.5, 1.0),
+                (68, 1.0, 1.5),
+                (69, 1.5, 2.0),
+                (64, 3.0, 5.0),
+            ],
+            KeySignature::new(0, true),
+        );
+        let abc = score(&Score::new(&take, &Quantizer::new(4)));
+        assert!(abc.starts_with("X:1\nT:Untitled\nM:4/4\nL:1/8\nQ:1/4=120\nK:Am clef=treble-8\n"));
+        // The sharp carries through the measure
+        assert!(abc.contains("A^G GA z2 E2- | E2 z2 z4 |]"));
+    }
+
+    #[test]
+    fn test_natural_cancels_accidental() {
+        let take = take(
+            &[(66, 0.0, 1.0), (65, 1.0, 2.0)],
+            KeySignature::new(1, false),
+        );
+        let abc = score(&Score::new(&take, &Quantizer::new(4)));
+        assert!(abc.contains("K:G clef=treble-8"));
+        assert!(abc.contains("F2 =F2 z4 |]"));
+    }
+}
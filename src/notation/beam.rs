@@ -0,0 +1,174 @@
+use super::{NotatedEvent, Quantizer, TimeSignature};
+
+/// Beam state of a note at one beam level, as named by MusicXML
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Beam {
+    Begin,
+    Continue,
+    End,
+    /// A short beam pointing to the next note
+    ForwardHook,
+    /// A short beam pointing to the previous note
+    BackwardHook,
+}
+
+impl Beam {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Beam::Begin => "begin",
+            Beam::Continue => "continue",
+            Beam::End => "end",
+            Beam::ForwardHook => "forward hook",
+            Beam::BackwardHook => "backward hook",
+        }
+    }
+}
+
+/// Length of a beamed group in divisions: a beat, or a dotted beat in compound meters
+pub fn beat_length(quantizer: &Quantizer, time_signature: TimeSignature) -> u32 {
+    let whole = quantizer.divisions() * 4;
+    let beat = whole / time_signature.denominator as u32;
+    let compound = time_signature.denominator >= 8
+        && time_signature.numerator > 3
+        && time_signature.numerator.is_multiple_of(3);
+    if compound {
+        beat * 3
+    } else {
+        beat.min(quantizer.divisions()).max(1)
+    }
+}
+
+/// Beams of each event of a measure, by level (1 = eighth-note beam)
+///
+/// Consecutive notes shorter than a quarter within a beat are beamed
+/// together; a sixteenth next to eighths gets a hook on its second beam.
+pub fn beams(
+    events: &[NotatedEvent],
+    quantizer: &Quantizer,
+    time_signature: TimeSignature,
+) -> Vec<Vec<(u8, Beam)>> {
+    let beat = beat_length(quantizer, time_signature);
+    let levels: Vec<u8> = events
+        .iter()
+        .map(|event| {
+            let value = quantizer.note_value(event.duration);
+            match value {
+                Some(value) if !event.is_rest() && value.denominator >= 8 => {
+                    value.denominator.trailing_zeros() as u8 - 2
+                }
+                _ => 0,
+            }
+        })
+        .collect();
+
+    let mut result = vec![Vec::new(); events.len()];
+    let mut i = 0;
+    while i < events.len() {
+        // A group: beamable notes in the same beat
+        let group = events[i].start / beat;
+        let mut end = i;
+        while end < events.len()
+            && levels[end] > 0
+            && events[end].start / beat == group
+            && (events[end].start + events[end].duration).div_ceil(beat) == group + 1
+        {
+            end += 1;
+        }
+        if end - i >= 2 {
+            beam_group(&levels[i..end], &mut result[i..end]);
+        }
+        i = end.max(i + 1);
+    }
+    result
+}
+
+/// Beam one group at every level its notes reach
+fn beam_group(levels: &[u8], beams: &mut [Vec<(u8, Beam)>]) {
+    let deepest = levels.iter().copied().max().unwrap_or(0);
+    for level in 1..=deepest {
+        let mut i = 0;
+        while i < levels.len() {
+            if levels[i] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < levels.len() && levels[i] >= level {
+                i += 1;
+            }
+            if level == 1 || i - start >= 2 {
+                for (j, beam) in beams.iter_mut().enumerate().take(i).skip(start) {
+                    let state = match j {
+                        j if j == start => Beam::Begin,
+                        j if j == i - 1 => Beam::End,
+                        _ => Beam::Continue,
+                    };
+                    beam.push((level, state));
+                }
+            } else {
+                let hook = if start == 0 {
+                    Beam::ForwardHook
+                } else {
+                    Beam::BackwardHook
+                };
+                beams[start].push((level, hook));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(start: u32, duration: u32, rest: bool) -> NotatedEvent {
+        NotatedEvent {
+            notes: if rest { Vec::new() } else { vec![60] },
+            start,
+            duration,
+            tie_start: false,
+            tie_stop: false,
+        }
+    }
+
+    #[test]
+    fn test_eighths_beamed_per_beat() {
+        let quantizer = Quantizer::new(4);
+        let events: Vec<NotatedEvent> = (0..4).map(|i| event(i * 2, 2, false)).collect();
+        let beams = beams(&events, &quantizer, TimeSignature::default());
+        assert_eq!(beams[0], vec![(1, Beam::Begin)]);
+        assert_eq!(beams[1], vec![(1, Beam::End)]);
+        assert_eq!(beams[2], vec![(1, Beam::Begin)]);
+        assert_eq!(beams[3], vec![(1, Beam::End)]);
+    }
+
+    #[test]
+    fn test_sixteenth_hooks_and_rests() {
+        let quantizer = Quantizer::new(4);
+        // Dotted eighth and sixteenth, then an eighth rest and a lone eighth
+        let events = vec![
+            event(0, 3, false),
+            event(3, 1, false),
+            event(4, 2, true),
+            event(6, 2, false),
+        ];
+        let beams = beams(&events, &quantizer, TimeSignature::default());
+        assert_eq!(beams[0], vec![(1, Beam::Begin)]);
+        assert_eq!(beams[1], vec![(1, Beam::End), (2, Beam::BackwardHook)]);
+        assert!(beams[2].is_empty());
+        assert!(beams[3].is_empty());
+    }
+
+    #[test]
+    fn test_compound_meter_groups() {
+        let quantizer = Quantizer::new(4);
+        let six_eight = "6/8".parse().unwrap();
+        assert_eq!(beat_length(&quantizer, six_eight), 6);
+        let events: Vec<NotatedEvent> = (0..3).map(|i| event(i * 2, 2, false)).collect();
+        let beams = beams(&events, &quantizer, six_eight);
+        assert_eq!(
+            beams.iter().map(|b| b[0].1).collect::<Vec<_>>(),
+            vec![Beam::Begin, Beam::Continue, Beam::End]
+        );
+    }
+}
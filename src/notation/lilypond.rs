@@ -0,0 +1,181 @@
+use super::{NotatedEvent, Pitch, PitchSpeller, Score};
+use std::fmt::Write;
+
+/// LilyPond version the output is written for
+const VERSION: &str = "2.24.0";
+
+/// Measures per line of LilyPond source
+const MEASURES_PER_LINE: usize = 4;
+
+/// Render a score as LilyPond source
+///
+/// Pitches are absolute and spelled for the key; LilyPond beams the notes
+/// itself. Guitar parts use the treble clef sounding an octave lower.
+pub fn score(score: &Score) -> String {
+    let speller = PitchSpeller::new(score.key);
+    let quantizer = score.quantizer();
+    let measure_length = quantizer.measure_length(score.time_signature);
+
+    let mut ly = format!("\\version \"{}\"\n\n", VERSION);
+    ly.push_str("\\header {\n");
+    if let Some(title) = &score.title {
+        let _ = writeln!(ly, "  title = \"{}\"", title.replace('"', "\\\""));
+    }
+    ly.push_str("  tagline = ##f\n}\n\n");
+
+    let tonic = speller.spell(60 + score.key.tonic());
+    ly.push_str("\\score {\n  \\new Staff {\n");
+    ly.push_str("    \\clef \"treble_8\"\n");
+    let _ = writeln!(
+        ly,
+        "    \\key {} \\{}",
+        pitch_name(tonic),
+        if score.key.minor { "minor" } else { "major" }
+    );
+    let _ = writeln!(ly, "    \\time {}", score.time_signature);
+    let _ = writeln!(ly, "    \\tempo 4 = {:.0}", score.bpm());
+
+    for line in score.measures.chunks(MEASURES_PER_LINE) {
+        ly.push_str("   ");
+        for measure in line {
+            for event in &measure.events {
+                ly.push(' ');
+                if event.is_rest() && event.duration == measure_length {
+                    let _ = write!(ly, "R{}", measure_duration(score));
+                } else {
+                    ly.push_str(&event_text(score, &speller, event));
+                }
+            }
+            ly.push_str(" |");
+        }
+        ly.push('\n');
+    }
+
+    ly.push_str("  }\n  \\layout { }\n  \\midi { }\n}\n");
+    ly
+}
+
+/// A note, chord or rest with its duration and tie
+fn event_text(score: &Score, speller: &PitchSpeller, event: &NotatedEvent) -> String {
+    let duration = duration(score, event.duration);
+    let mut text = match event.notes.as_slice() {
+        [] => format!("r{}", duration),
+        [note] => format!("{}{}", absolute_pitch(speller.spell(*note)), duration),
+        notes => {
+            let pitches: Vec<String> = notes
+                .iter()
+                .map(|&note| absolute_pitch(speller.spell(note)))
+                .collect();
+            format!("<{}>{}", pitches.join(" "), duration)
+        }
+    };
+    if event.tie_start {
+        text.push('~');
+    }
+    text
+}
+
+/// LilyPond duration of a length in divisions, e.g. "4." for a dotted quarter
+fn duration(score: &Score, length: u32) -> String {
+    match score.quantizer().note_value(length) {
+        Some(value) => format!("{}{}", value.denominator, ".".repeat(value.dots as usize)),
+        // Not a single note value: a multiple of the grid step
+        None => format!("{}*{}", score.divisions * 4, length),
+    }
+}
+
+/// Duration of a whole measure for full-measure rests, e.g. "1" or "2."
+fn measure_duration(score: &Score) -> String {
+    let length = score.quantizer().measure_length(score.time_signature);
+    duration(score, length)
+}
+
+/// Note name without octave, e.g. "fis" or "bes"
+fn pitch_name(pitch: Pitch) -> String {
+    let accidental = if pitch.alter >= 0 { "is" } else { "es" };
+    format!(
+        "{}{}",
+        pitch.step.to_ascii_lowercase(),
+        accidental.repeat(pitch.alter.unsigned_abs() as usize)
+    )
+}
+
+/// Note name with absolute octave marks (c' = middle C)
+fn absolute_pitch(pitch: Pitch) -> String {
+    let marks = pitch.octave - 3;
+    let mark = if marks >= 0 { "'" } else { "," };
+    format!(
+        "{}{}",
+        pitch_name(pitch),
+        mark.repeat(marks.unsigned_abs() as usize)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{KeySignature, Quantizer, Take, TakeNote};
+
+    fn take(notes: &[(u8, f64, f64)], key: KeySignature) -> Take {
+        Take {
+            notes: notes
+                .iter()
+                .map(|&(note, start, end)| TakeNote {
+                    note,
+                    channel: 0,
+                    velocity: 80,
+                    start: (start * 500_000.0) as u64,
+                    end: (end * 500_000.0) as u64,
+                })
+                .collect(),
+            key_signature: Some(key),
+            title: Some("Riff".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pitch_names() {
+        let speller = PitchSpeller::new(KeySignature::new(-2, false));
+        assert_eq!(absolute_pitch(speller.spell(60)), "c'");
+        assert_eq!(absolute_pitch(speller.spell(70)), "bes'");
+        assert_eq!(absolute_pitch(speller.spell(40)), "e,");
+        assert_eq!(absolute_pitch(speller.spell(48)), "c");
+        let speller = PitchSpeller::new(KeySignature::new(3, false));
+        assert_eq!(absolute_pitch(speller.spell(66)), "fis'");
+    }
+
+    #[test]
+    fn test_lilypond_score() {
+        // G major: a dotted quarter, an eighth, a chord tied over the barline
+        let take = take(
+            &[
+                (66, 0.0, 1.5),
+                (67, 1.5, 2.0),
+                (43, 2.0, 6.0),
+                (47, 2.0, 6.0),
+            ],
+            KeySignature::new(1, false),
+        );
+        let ly = score(&Score::new(&take, &Quantizer::new(4)));
+        assert!(ly.contains("title = \"Riff\""));
+        assert!(ly.contains("\\key g \\major"));
+        assert!(ly.contains("\\time 4/4"));
+        assert!(ly.contains("\\tempo 4 = 120"));
+        assert!(ly.contains("fis'4. g'8 <g, b,>2~ | <g, b,>2 r2 |"));
+    }
+
+    #[test]
+    fn test_full_measure_rest() {
+        let take = take(&[(64, 3.0, 4.0)], KeySignature::default());
+        let ly = score(&Score::new(
+            &Take {
+                time_signature: "3/4".parse().unwrap(),
+                ..take
+            },
+            &Quantizer::new(4),
+        ));
+        // Rests in 3/4 follow the beats
+        assert!(ly.contains("R2. | e'4 r4 r4 |"));
+    }
+}
@@ -0,0 +1,576 @@
+pub mod abc;
+pub mod beam;
+pub mod lilypond;
+pub mod musicxml;
+pub mod quantize;
+pub mod spelling;
+pub mod tab;
+
+pub use beam::Beam;
+pub use quantize::{Measure, NotatedEvent, NoteValue, Quantizer};
+pub use spelling::{Pitch, PitchSpeller};
+pub use tab::Tablature;
+
+use anyhow::{Context, Result};
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::guitar::FingeringInference;
+use crate::midi::{TempoChange, RAW_DETECTION_TRACK};
+use crate::theory::{KeyDetector, Mode};
+
+/// Default tempo, 120 BPM in microseconds per quarter note
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// A note of a take, with start and end times in microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TakeNote {
+    pub note: u8,
+    pub channel: u8,
+    pub velocity: u8,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Meter of a take, e.g. 3/4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    /// Note value of a beat (4 = quarter note), a power of two
+    pub denominator: u8,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+impl FromStr for TimeSignature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid time signature '{}' (e.g. 4/4 or 6/8)", s);
+        let (numerator, denominator) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let numerator: u8 = numerator.trim().parse().map_err(|_| invalid())?;
+        let denominator: u8 = denominator.trim().parse().map_err(|_| invalid())?;
+        if numerator == 0 || !denominator.is_power_of_two() || denominator > 32 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+impl std::fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Key signature as a position on the circle of fifths
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeySignature {
+    /// Number of sharps, negative for flats
+    pub fifths: i8,
+    pub minor: bool,
+}
+
+impl KeySignature {
+    pub fn new(fifths: i8, minor: bool) -> Self {
+        Self {
+            fifths: fifths.clamp(-7, 7),
+            minor,
+        }
+    }
+
+    /// Tonic pitch class (0 = C)
+    pub fn tonic(&self) -> u8 {
+        let major = (self.fifths as i32 * 7).rem_euclid(12);
+        let tonic = if self.minor { major + 9 } else { major };
+        (tonic % 12) as u8
+    }
+}
+
+impl FromStr for KeySignature {
+    type Err = anyhow::Error;
+
+    /// Parse a tonic and mode, e.g. "Eb major", "F# minor" or "Am"
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid key '{}' (e.g. \"Eb major\" or \"F# minor\")", s);
+        let s = s.trim();
+        let split = s
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c != '#' && c != 'b')
+            .map_or(s.len(), |(i, _)| i);
+        let (tonic, mode) = s.split_at(split);
+        let minor = match mode.trim().to_lowercase().as_str() {
+            "" | "major" | "maj" => false,
+            "minor" | "min" | "m" => true,
+            _ => return Err(invalid()),
+        };
+
+        // Letters on the circle of fifths; each sharp adds seven fifths
+        let letter = tonic
+            .chars()
+            .next()
+            .ok_or_else(invalid)?
+            .to_ascii_uppercase();
+        let letter_fifths = "FCGDAEB".find(letter).ok_or_else(invalid)? as i8 - 1;
+        let accidentals: i8 = tonic[1..]
+            .chars()
+            .map(|c| if c == '#' { 7 } else { -7 })
+            .sum();
+        let fifths = letter_fifths + accidentals - if minor { 3 } else { 0 };
+        if !(-7..=7).contains(&fifths) {
+            return Err(invalid());
+        }
+        Ok(Self::new(fifths, minor))
+    }
+}
+
+/// The notes of a recording, ready for notation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Take {
+    /// Notes ordered by start time
+    pub notes: Vec<TakeNote>,
+    /// Microseconds per quarter note at the start
+    pub tempo: u32,
+    /// Tempo changes during the take in time order; `tempo` holds until the first
+    pub tempo_changes: Vec<TempoChange>,
+    pub time_signature: TimeSignature,
+    /// Key signature of the recording, if it has one
+    pub key_signature: Option<KeySignature>,
+    /// Title for notation, e.g. the recording's file name
+    pub title: Option<String>,
+}
+
+impl Default for Take {
+    fn default() -> Self {
+        Self {
+            notes: Vec::new(),
+            tempo: DEFAULT_TEMPO,
+            tempo_changes: Vec::new(),
+            time_signature: TimeSignature::default(),
+            key_signature: None,
+            title: None,
+        }
+    }
+}
+
+impl Take {
+    /// Pair note on/off messages (timestamps in microseconds) into notes
+    ///
+    /// Notes still sounding at the last message end there.
+    pub fn from_messages(messages: impl IntoIterator<Item = (u64, u8, MidiMessage)>) -> Self {
+        let mut notes = Vec::new();
+        let mut sounding: HashMap<(u8, u8), (u64, u8)> = HashMap::new();
+        let mut last_timestamp = 0;
+
+        for (timestamp, channel, message) in messages {
+            last_timestamp = last_timestamp.max(timestamp);
+            let (key, velocity) = match message {
+                MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int()),
+                MidiMessage::NoteOff { key, .. } => (key.as_int(), 0),
+                _ => continue,
+            };
+            if let Some((start, on_velocity)) = sounding.remove(&(channel, key)) {
+                notes.push(TakeNote {
+                    note: key,
+                    channel,
+                    velocity: on_velocity,
+                    start,
+                    end: timestamp,
+                });
+            }
+            if velocity > 0 {
+                sounding.insert((channel, key), (timestamp, velocity));
+            }
+        }
+        for ((channel, note), (start, velocity)) in sounding {
+            notes.push(TakeNote {
+                note,
+                channel,
+                velocity,
+                start,
+                end: last_timestamp,
+            });
+        }
+
+        notes.sort_by_key(|n| (n.start, n.note));
+        Self {
+            notes,
+            ..Default::default()
+        }
+    }
+
+    /// Load a take from a Standard MIDI File, merging all tracks
+    ///
+    /// The first time signature in the file is used; tempo changes are
+    /// followed when converting ticks to time.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let smf = Smf::parse(&data).context("Failed to parse MIDI file")?;
+        let Timing::Metrical(ticks_per_beat) = smf.header.timing else {
+            anyhow::bail!("MIDI files with timecode timing are not supported");
+        };
+        let ticks_per_beat = ticks_per_beat.as_int().max(1) as u64;
+
+        let mut events = Vec::new();
+        // The raw detection of a detection-layout recording duplicates the played notes
+        let played = smf.tracks.iter().filter(|track| {
+            !track.iter().any(|event| {
+                matches!(event.kind, TrackEventKind::Meta(MetaMessage::TrackName(name)) if name == RAW_DETECTION_TRACK.as_bytes())
+            })
+        });
+        for track in played {
+            let mut tick = 0u64;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                events.push((tick, event.kind));
+            }
+        }
+        events.sort_by_key(|(tick, _)| *tick);
+
+        let mut tempo_changes: Vec<TempoChange> = Vec::new();
+        let mut time_signature = None;
+        let mut key_signature = None;
+        let mut tempo = DEFAULT_TEMPO as u64;
+        let (mut last_tick, mut micros) = (0u64, 0u64);
+        let mut messages = Vec::new();
+        for (tick, kind) in events {
+            micros += (tick - last_tick) * tempo / ticks_per_beat;
+            last_tick = tick;
+            match kind {
+                TrackEventKind::Midi { channel, message } => {
+                    messages.push((micros, channel.as_int(), message));
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(t)) => {
+                    tempo = t.as_int().max(1) as u64;
+                    // A later change at the same time replaces the earlier one
+                    tempo_changes.retain(|change| change.time < micros);
+                    tempo_changes.push(TempoChange {
+                        time: micros,
+                        tempo: tempo as u32,
+                    });
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, power, _, _)) => {
+                    time_signature.get_or_insert(TimeSignature {
+                        numerator: numerator.max(1),
+                        denominator: 1u8 << power.min(5),
+                    });
+                }
+                TrackEventKind::Meta(MetaMessage::KeySignature(fifths, minor)) => {
+                    key_signature.get_or_insert(KeySignature::new(fifths, minor));
+                }
+                _ => {}
+            }
+        }
+
+        let tempo = match tempo_changes.first() {
+            Some(first) if first.time == 0 => tempo_changes.remove(0).tempo,
+            _ => DEFAULT_TEMPO,
+        };
+        Ok(Self {
+            tempo,
+            tempo_changes,
+            time_signature: time_signature.unwrap_or_default(),
+            key_signature,
+            title: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            ..Self::from_messages(messages)
+        })
+    }
+
+    /// Tempo at the start in beats per minute
+    pub fn bpm(&self) -> f32 {
+        60_000_000.0 / self.tempo as f32
+    }
+
+    /// Quarter notes from the start of the take to a time, following the tempo changes
+    pub fn beats(&self, micros: u64) -> f64 {
+        let (mut beats, mut time, mut tempo) = (0.0, 0, self.tempo);
+        for change in self.tempo_changes.iter().take_while(|c| c.time < micros) {
+            beats += change.time.saturating_sub(time) as f64 / tempo as f64;
+            time = change.time.max(time);
+            tempo = change.tempo.max(1);
+        }
+        beats + micros.saturating_sub(time) as f64 / tempo as f64
+    }
+
+    /// The recorded key signature, or the key estimated from the notes
+    ///
+    /// Notes are weighted by length; C major is used when too few notes
+    /// were played for an estimate.
+    pub fn key(&self) -> KeySignature {
+        if let Some(key) = self.key_signature {
+            return key;
+        }
+        let mut detector = KeyDetector::new(f32::MAX);
+        for note in &self.notes {
+            let beats = (self.beats(note.end) - self.beats(note.start)) as f32;
+            detector.add_note(note.note, beats.max(0.25));
+        }
+        detector
+            .estimate()
+            .map(|key| KeySignature::new(key.sharps(), key.mode == Mode::Minor))
+            .unwrap_or_default()
+    }
+}
+
+/// A take quantized into measures, with its tempo, meter and key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    /// Grid steps per quarter note
+    pub divisions: u32,
+    /// Microseconds per quarter note
+    pub tempo: u32,
+    pub time_signature: TimeSignature,
+    pub key: KeySignature,
+    pub title: Option<String>,
+    pub measures: Vec<Measure>,
+}
+
+impl Score {
+    pub fn new(take: &Take, quantizer: &Quantizer) -> Self {
+        Self {
+            divisions: quantizer.divisions(),
+            tempo: take.tempo,
+            time_signature: take.time_signature,
+            key: take.key(),
+            title: take.title.clone(),
+            measures: quantizer.quantize(take),
+        }
+    }
+
+    /// Tempo in beats per minute
+    pub fn bpm(&self) -> f32 {
+        60_000_000.0 / self.tempo as f32
+    }
+
+    /// Quantizer with the grid the score was quantized to
+    pub fn quantizer(&self) -> Quantizer {
+        Quantizer::new(self.divisions)
+    }
+}
+
+/// Text or file formats a take can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Plain-text guitar tablature
+    AsciiTab,
+    /// MusicXML with a tablature staff, readable by Guitar Pro
+    GuitarPro,
+    /// MusicXML standard notation
+    MusicXml,
+    /// LilyPond source
+    LilyPond,
+    /// ABC notation
+    Abc,
+}
+
+impl ExportFormat {
+    /// File extension for exported files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::AsciiTab => "txt",
+            ExportFormat::GuitarPro => "tab.musicxml",
+            ExportFormat::MusicXml => "musicxml",
+            ExportFormat::LilyPond => "ly",
+            ExportFormat::Abc => "abc",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "ascii-tab" | "tab" => Ok(ExportFormat::AsciiTab),
+            "guitar-pro" | "gp" => Ok(ExportFormat::GuitarPro),
+            "musicxml" | "xml" => Ok(ExportFormat::MusicXml),
+            "lilypond" | "ly" => Ok(ExportFormat::LilyPond),
+            "abc" => Ok(ExportFormat::Abc),
+            _ => anyhow::bail!(
+                "Unknown export format '{}' (use ascii-tab, guitar-pro, musicxml, lilypond or abc)",
+                s
+            ),
+        }
+    }
+}
+
+/// Render a take in an export format
+///
+/// `divisions` is the quantization grid in steps per quarter note; fret
+/// positions use the tuning, capo and highest fret of the configuration.
+pub fn export(
+    take: &Take,
+    format: ExportFormat,
+    config: &Config,
+    divisions: u32,
+) -> Result<String> {
+    let score = Score::new(take, &Quantizer::new(divisions));
+    let tablature = || -> Result<Tablature> {
+        let inference =
+            FingeringInference::new(config.guitar_tuning()?, config.capo, config.max_fret);
+        Ok(Tablature::new(score.clone(), inference))
+    };
+    Ok(match format {
+        ExportFormat::AsciiTab => tablature()?.to_ascii(tab::MEASURES_PER_LINE),
+        ExportFormat::GuitarPro => musicxml::tablature(&tablature()?),
+        ExportFormat::MusicXml => musicxml::score(&score),
+        ExportFormat::LilyPond => lilypond::score(&score),
+        ExportFormat::Abc => abc::score(&score),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::u7;
+
+    fn note_on(key: u8, vel: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(vel),
+        }
+    }
+
+    #[test]
+    fn test_time_signature_parse() {
+        let ts: TimeSignature = "6/8".parse().unwrap();
+        assert_eq!((ts.numerator, ts.denominator), (6, 8));
+        assert_eq!(ts.to_string(), "6/8");
+        assert!("4/3".parse::<TimeSignature>().is_err());
+        assert!("0/4".parse::<TimeSignature>().is_err());
+        assert!("waltz".parse::<TimeSignature>().is_err());
+    }
+
+    #[test]
+    fn test_take_pairs_notes() {
+        let take = Take::from_messages(vec![
+            (0, 0, note_on(60, 80)),
+            (100, 1, note_on(60, 90)),
+            (500, 0, note_on(60, 0)),
+            (700, 1, note_on(64, 70)),
+        ]);
+        assert_eq!(take.notes.len(), 3);
+        assert_eq!((take.notes[0].start, take.notes[0].end), (0, 500));
+        assert_eq!((take.notes[1].channel, take.notes[1].end), (1, 700));
+        // Still sounding at the end of the take
+        assert_eq!((take.notes[2].note, take.notes[2].end), (64, 700));
+    }
+
+    #[test]
+    fn test_take_follows_tempo_changes() {
+        use crate::midi::MidiRecorder;
+
+        // A bar at 120 BPM, then a bar at 60 BPM from two seconds in
+        let mut recorder = MidiRecorder::new();
+        recorder.set_tempo_map(vec![
+            TempoChange::new(0, 120.0),
+            TempoChange::new(2_000_000, 60.0),
+        ]);
+        recorder.start();
+        for (key, start, end) in [
+            (60, 0, 500_000),
+            (62, 2_000_000, 3_000_000),
+            (64, 3_000_000, 4_000_000),
+        ] {
+            recorder.set_time(Some(start));
+            recorder.record_note_on(key, 80);
+            recorder.set_time(Some(end));
+            recorder.record_note_off(key);
+        }
+        recorder.stop();
+        let path = std::env::temp_dir().join("test_take_tempo_changes.mid");
+        recorder.save(&path).unwrap();
+
+        for take in [recorder.take(), Take::load(&path).unwrap()] {
+            assert_eq!(take.bpm(), 120.0);
+            assert_eq!(take.beats(3_000_000), 5.0);
+            let measures = Quantizer::new(4).quantize(&take);
+            let onsets: Vec<(u32, u32, Vec<u8>)> = measures
+                .iter()
+                .flat_map(|m| {
+                    m.events
+                        .iter()
+                        .map(move |e| (m.number, e.start, e.notes.clone()))
+                })
+                .filter(|(_, _, notes)| !notes.is_empty())
+                .collect();
+            // The second bar's quarter notes fall on its first two beats
+            assert_eq!(
+                onsets,
+                vec![(1, 0, vec![60]), (2, 0, vec![62]), (2, 4, vec![64])]
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_key_signature_parse() {
+        let key: KeySignature = "Eb major".parse().unwrap();
+        assert_eq!((key.fifths, key.minor), (-3, false));
+        assert_eq!(key.tonic(), 3);
+        let key: KeySignature = "F# minor".parse().unwrap();
+        assert_eq!((key.fifths, key.minor), (3, true));
+        assert_eq!(key.tonic(), 6);
+        assert_eq!(
+            "Am".parse::<KeySignature>().unwrap(),
+            KeySignature::new(0, true)
+        );
+        assert!("H major".parse::<KeySignature>().is_err());
+        assert!("Fb minor".parse::<KeySignature>().is_err());
+    }
+
+    #[test]
+    fn test_take_estimates_key() {
+        // An E minor arpeggio with its leading tone, repeated
+        let notes = [40, 43, 47, 52, 51, 54, 55, 47]
+            .iter()
+            .cycle()
+            .take(32)
+            .enumerate()
+            .map(|(i, &note)| TakeNote {
+                note,
+                channel: 0,
+                velocity: 80,
+                start: i as u64 * 250_000,
+                end: (i as u64 + 1) * 250_000,
+            })
+            .collect();
+        let take = Take {
+            notes,
+            ..Default::default()
+        };
+        assert_eq!(take.key(), KeySignature::new(1, true));
+
+        let take = Take {
+            key_signature: Some(KeySignature::new(-2, false)),
+            ..take
+        };
+        assert_eq!(take.key().fifths, -2);
+    }
+
+    #[test]
+    fn test_export_format_from_str() {
+        assert_eq!(
+            "Guitar-Pro".parse::<ExportFormat>().unwrap(),
+            ExportFormat::GuitarPro
+        );
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use super::{beam, NotatedEvent, PitchSpeller, Score, Tablature};
+use crate::guitar::TabNote;
+use std::fmt::Write;
+
+const HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+"#;
+
+/// Render a score as MusicXML standard notation
+///
+/// Guitar parts are written on a treble clef sounding an octave lower, with
+/// the key and time signature of the score, beamed notes and ties.
+pub fn score(score: &Score) -> String {
+    write_score(score, None)
+}
+
+/// Render tablature as MusicXML with a single TAB staff
+///
+/// Uses the MusicXML tablature profile (TAB clef, staff tuning and
+/// string/fret technical marks) that Guitar Pro, MuseScore and other
+/// tablature editors import.
+pub fn tablature(tab: &Tablature) -> String {
+    write_score(tab.score(), Some(tab))
+}
+
+fn write_score(score: &Score, tab: Option<&Tablature>) -> String {
+    let quantizer = score.quantizer();
+    let speller = PitchSpeller::new(score.key);
+    let mut xml = String::from(HEADER);
+    xml.push_str("<score-partwise version=\"4.0\">\n");
+    if let Some(title) = &score.title {
+        let _ = writeln!(
+            xml,
+            "  <work><work-title>{}</work-title></work>",
+            escape(title)
+        );
+    }
+    xml.push_str("  <part-list>\n    <score-part id=\"P1\">\n");
+    xml.push_str("      <part-name>Guitar</part-name>\n    </score-part>\n  </part-list>\n");
+    xml.push_str("  <part id=\"P1\">\n");
+
+    let measure_length = quantizer.measure_length(score.time_signature);
+    for (m, measure) in score.measures.iter().enumerate() {
+        let _ = writeln!(xml, "    <measure number=\"{}\">", measure.number);
+        if m == 0 {
+            write_attributes(&mut xml, score, tab);
+        }
+
+        let beams = beam::beams(&measure.events, &quantizer, score.time_signature);
+        for (e, event) in measure.events.iter().enumerate() {
+            let positions = tab.map_or(&[][..], |tab| tab.positions(m, e));
+            let whole_measure = event.is_rest() && event.duration == measure_length;
+            let note = Note {
+                event,
+                positions,
+                beams: &beams[e],
+                whole_measure,
+            };
+            note.write(&mut xml, score, &speller);
+        }
+        xml.push_str("    </measure>\n");
+    }
+
+    xml.push_str("  </part>\n</score-partwise>\n");
+    xml
+}
+
+/// Divisions, key, time, clef and tempo of the first measure
+fn write_attributes(xml: &mut String, score: &Score, tab: Option<&Tablature>) {
+    let _ = writeln!(xml, "      <attributes>");
+    let _ = writeln!(xml, "        <divisions>{}</divisions>", score.divisions);
+    let _ = writeln!(
+        xml,
+        "        <key><fifths>{}</fifths><mode>{}</mode></key>",
+        score.key.fifths,
+        if score.key.minor { "minor" } else { "major" }
+    );
+    let _ = writeln!(
+        xml,
+        "        <time><beats>{}</beats><beat-type>{}</beat-type></time>",
+        score.time_signature.numerator, score.time_signature.denominator
+    );
+
+    match tab {
+        None => {
+            let _ = writeln!(
+                xml,
+                "        <clef><sign>G</sign><line>2</line><clef-octave-change>-1</clef-octave-change></clef>"
+            );
+        }
+        Some(tab) => {
+            let tuning = tab.tuning();
+            let speller = PitchSpeller::new(score.key);
+            let _ = writeln!(xml, "        <clef><sign>TAB</sign><line>5</line></clef>");
+            let _ = writeln!(xml, "        <staff-details>");
+            let _ = writeln!(
+                xml,
+                "          <staff-lines>{}</staff-lines>",
+                tuning.string_count()
+            );
+            // Staff lines are numbered from the bottom, i.e. the lowest string
+            for (line, &open) in tuning.open_notes().iter().rev().enumerate() {
+                let pitch = speller.spell(open);
+                let _ = write!(
+                    xml,
+                    "          <staff-tuning line=\"{}\"><tuning-step>{}</tuning-step>",
+                    line + 1,
+                    pitch.step
+                );
+                if pitch.alter != 0 {
+                    let _ = write!(xml, "<tuning-alter>{}</tuning-alter>", pitch.alter);
+                }
+                let _ = writeln!(
+                    xml,
+                    "<tuning-octave>{}</tuning-octave></staff-tuning>",
+                    pitch.octave
+                );
+            }
+            if tab.capo() > 0 {
+                let _ = writeln!(xml, "          <capo>{}</capo>", tab.capo());
+            }
+            let _ = writeln!(xml, "        </staff-details>");
+        }
+    }
+    let _ = writeln!(xml, "      </attributes>");
+    let _ = writeln!(
+        xml,
+        "      <direction placement=\"above\"><direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>{:.0}</per-minute></metronome></direction-type><sound tempo=\"{:.0}\"/></direction>",
+        score.bpm(),
+        score.bpm()
+    );
+}
+
+/// Escape text for XML content
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// One event of a measure with what is needed to write its `<note>` elements
+struct Note<'a> {
+    event: &'a NotatedEvent,
+    positions: &'a [TabNote],
+    beams: &'a [(u8, beam::Beam)],
+    /// A rest filling the whole measure
+    whole_measure: bool,
+}
+
+impl Note<'_> {
+    fn write(&self, xml: &mut String, score: &Score, speller: &PitchSpeller) {
+        let event = self.event;
+        if event.is_rest() {
+            if self.whole_measure {
+                xml.push_str("      <note>\n        <rest measure=\"yes\"/>\n");
+                let _ = writeln!(xml, "        <duration>{}</duration>", event.duration);
+                xml.push_str("        <voice>1</voice>\n");
+            } else {
+                xml.push_str("      <note>\n        <rest/>\n");
+                let _ = writeln!(xml, "        <duration>{}</duration>", event.duration);
+                xml.push_str("        <voice>1</voice>\n");
+                self.write_type(xml, score);
+            }
+            xml.push_str("      </note>\n");
+            return;
+        }
+
+        for (i, &note) in event.notes.iter().enumerate() {
+            xml.push_str("      <note>\n");
+            if i > 0 {
+                xml.push_str("        <chord/>\n");
+            }
+            let pitch = speller.spell(note);
+            let _ = write!(xml, "        <pitch><step>{}</step>", pitch.step);
+            if pitch.alter != 0 {
+                let _ = write!(xml, "<alter>{}</alter>", pitch.alter);
+            }
+            let _ = writeln!(xml, "<octave>{}</octave></pitch>", pitch.octave);
+            let _ = writeln!(xml, "        <duration>{}</duration>", event.duration);
+            if event.tie_stop {
+                xml.push_str("        <tie type=\"stop\"/>\n");
+            }
+            if event.tie_start {
+                xml.push_str("        <tie type=\"start\"/>\n");
+            }
+            xml.push_str("        <voice>1</voice>\n");
+            self.write_type(xml, score);
+            // Beams belong to the first note of a chord
+            if i == 0 {
+                for (level, beam) in self.beams {
+                    let _ = writeln!(
+                        xml,
+                        "        <beam number=\"{}\">{}</beam>",
+                        level,
+                        beam.as_str()
+                    );
+                }
+            }
+
+            let position = self.positions.iter().find(|p| p.note == note);
+            if position.is_some() || event.tie_start || event.tie_stop {
+                xml.push_str("        <notations>\n");
+                if event.tie_stop {
+                    xml.push_str("          <tied type=\"stop\"/>\n");
+                }
+                if event.tie_start {
+                    xml.push_str("          <tied type=\"start\"/>\n");
+                }
+                if let Some(position) = position {
+                    let _ = writeln!(
+                        xml,
+                        "          <technical><string>{}</string><fret>{}</fret></technical>",
+                        position.string + 1,
+                        position.fret
+                    );
+                }
+                xml.push_str("        </notations>\n");
+            }
+            xml.push_str("      </note>\n");
+        }
+    }
+
+    /// Write `<type>` and `<dot>` elements
+    fn write_type(&self, xml: &mut String, score: &Score) {
+        if let Some(value) = score.quantizer().note_value(self.event.duration) {
+            let _ = writeln!(xml, "        <type>{}</type>", value.name);
+            for _ in 0..value.dots {
+                xml.push_str("        <dot/>\n");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::{FingeringInference, Tuning};
+    use crate::notation::{KeySignature, Quantizer, Take, TakeNote};
+
+    /// A take at 120 BPM from (note, start, end) in quarter notes
+    fn take(notes: &[(u8, f64, f64)]) -> Take {
+        Take {
+            notes: notes
+                .iter()
+                .map(|&(note, start, end)| TakeNote {
+                    note,
+                    channel: 0,
+                    velocity: 80,
+                    start: (start * 500_000.0) as u64,
+                    end: (end * 500_000.0) as u64,
+                })
+                .collect(),
+            key_signature: Some(KeySignature::new(-1, true)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_score_musicxml() {
+        // D minor: a beamed C# and D, a tied half note across the barline
+        let take = take(&[(61, 0.0, 0.5), (62, 0.5, 1.0), (70, 3.0, 5.0)]);
+        let xml = score(&Score::new(&take, &Quantizer::new(4)));
+
+        assert!(xml.contains("<key><fifths>-1</fifths><mode>minor</mode></key>"));
+        assert!(xml.contains("<time><beats>4</beats><beat-type>4</beat-type></time>"));
+        assert!(xml.contains("<clef-octave-change>-1</clef-octave-change>"));
+        // Raised seventh spelled as C#, B flat from the key
+        assert!(xml.contains("<pitch><step>C</step><alter>1</alter><octave>4</octave></pitch>"));
+        assert!(xml.contains("<pitch><step>B</step><alter>-1</alter><octave>4</octave></pitch>"));
+        assert!(xml.contains("<beam number=\"1\">begin</beam>"));
+        assert!(xml.contains("<beam number=\"1\">end</beam>"));
+        assert!(xml.contains("<tied type=\"start\"/>"));
+        assert!(xml.contains("<tied type=\"stop\"/>"));
+        assert!(xml.contains("<type>eighth</type>"));
+        assert_eq!(xml.matches("<measure ").count(), 2);
+    }
+
+    #[test]
+    fn test_whole_measure_rest() {
+        let take = take(&[(62, 4.0, 8.0)]);
+        let xml = score(&Score::new(&take, &Quantizer::new(4)));
+        assert!(xml.contains("<rest measure=\"yes\"/>"));
+        assert!(xml.contains("<type>whole</type>"));
+    }
+
+    #[test]
+    fn test_tablature_musicxml() {
+        let take = take(&[(61, 0.0, 2.0), (64, 0.0, 2.0)]);
+        let tab = Tablature::new(
+            Score::new(&take, &Quantizer::new(4)),
+            FingeringInference::new(Tuning::standard(), 0, 22),
+        );
+        let xml = tablature(&tab);
+
+        assert!(xml.contains("<sign>TAB</sign>"));
+        assert!(xml.contains("<staff-lines>6</staff-lines>"));
+        assert!(xml.contains(
+            "<staff-tuning line=\"1\"><tuning-step>E</tuning-step><tuning-octave>2</tuning-octave></staff-tuning>"
+        ));
+        // C#4 as a half note, with E4 in the same chord on the open top string
+        assert!(xml.contains("<type>half</type>"));
+        assert!(xml.contains("<chord/>"));
+        assert!(xml.contains("<technical><string>1</string><fret>0</fret></technical>"));
+        assert_eq!(xml.matches("<measure ").count(), 1);
+    }
+}
@@ -0,0 +1,316 @@
+use super::{Take, TimeSignature};
+use std::collections::BTreeMap;
+
+/// Default grid: sixteenth notes
+pub const DEFAULT_DIVISIONS: u32 = 4;
+
+/// A note, chord or rest of a measure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotatedEvent {
+    /// Notes sounding together, lowest first; empty for a rest
+    pub notes: Vec<u8>,
+    /// Start within the measure, in divisions
+    pub start: u32,
+    /// Length in divisions
+    pub duration: u32,
+    /// Tied to the next event
+    pub tie_start: bool,
+    /// Tied from the previous event
+    pub tie_stop: bool,
+}
+
+impl NotatedEvent {
+    /// Check if the event is a rest
+    pub fn is_rest(&self) -> bool {
+        self.notes.is_empty()
+    }
+}
+
+/// A measure of notated events, filled from start to end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Measure {
+    /// Measure number, starting at 1
+    pub number: u32,
+    pub events: Vec<NotatedEvent>,
+}
+
+/// A notated length: a note type and a number of dots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteValue {
+    /// Note type as named by MusicXML ("whole", "half", "quarter", "eighth", "16th", ...)
+    pub name: &'static str,
+    /// Fraction of a whole note (1 = whole, 4 = quarter)
+    pub denominator: u32,
+    pub dots: u8,
+}
+
+const NOTE_TYPES: [(&str, u32); 7] = [
+    ("whole", 1),
+    ("half", 2),
+    ("quarter", 4),
+    ("eighth", 8),
+    ("16th", 16),
+    ("32nd", 32),
+    ("64th", 64),
+];
+
+/// Turns microsecond timestamps into measures of notated events
+///
+/// Times are converted to beats with the take's tempo changes. Onsets and releases are snapped to a grid, notes starting together form
+/// a chord that lasts until the next onset or its release, gaps become
+/// rests, and events crossing barlines or with no single note value are
+/// split into tied notes.
+pub struct Quantizer {
+    /// Grid steps per quarter note
+    divisions: u32,
+}
+
+impl Default for Quantizer {
+    fn default() -> Self {
+        Self::new(DEFAULT_DIVISIONS)
+    }
+}
+
+impl Quantizer {
+    /// Create a quantizer with a grid of `divisions` steps per quarter note (a power of two)
+    pub fn new(divisions: u32) -> Self {
+        Self {
+            divisions: divisions.max(1),
+        }
+    }
+
+    /// Grid steps per quarter note
+    pub fn divisions(&self) -> u32 {
+        self.divisions
+    }
+
+    /// Length of a measure in divisions
+    pub fn measure_length(&self, time_signature: TimeSignature) -> u32 {
+        (self.divisions * 4 * time_signature.numerator as u32 / time_signature.denominator as u32)
+            .max(1)
+    }
+
+    /// Note value of a length in divisions, if one exists
+    pub fn note_value(&self, duration: u32) -> Option<NoteValue> {
+        NOTE_TYPES.iter().find_map(|&(name, denominator)| {
+            let whole = self.divisions * 4;
+            if !whole.is_multiple_of(denominator) {
+                return None;
+            }
+            let base = whole / denominator;
+            let dots = match duration {
+                d if d == base => 0,
+                d if base.is_multiple_of(2) && d == base * 3 / 2 => 1,
+                d if base.is_multiple_of(4) && d == base * 7 / 4 => 2,
+                _ => return None,
+            };
+            Some(NoteValue {
+                name,
+                denominator,
+                dots,
+            })
+        })
+    }
+
+    /// Quantize a take into measures
+    pub fn quantize(&self, take: &Take) -> Vec<Measure> {
+        let divisions = self.divisions as f64;
+        let to_grid = |micros: u64| (take.beats(micros) * divisions).round() as u32;
+
+        // Chords by onset: notes and the latest release
+        let mut onsets: BTreeMap<u32, (Vec<u8>, u32)> = BTreeMap::new();
+        for note in &take.notes {
+            let start = to_grid(note.start);
+            let end = to_grid(note.end).max(start + 1);
+            let (notes, release) = onsets.entry(start).or_default();
+            if !notes.contains(&note.note) {
+                notes.push(note.note);
+            }
+            *release = (*release).max(end);
+        }
+
+        // One voice of (start, duration, notes) spans, with rests in the gaps
+        let mut spans = Vec::new();
+        let mut position = 0;
+        let starts: Vec<u32> = onsets.keys().copied().collect();
+        for (i, (&start, (notes, release))) in onsets.iter().enumerate() {
+            if start > position {
+                spans.push((position, start - position, Vec::new()));
+            }
+            let end = starts
+                .get(i + 1)
+                .map_or(*release, |&next| next.min(*release));
+            let mut notes = notes.clone();
+            notes.sort_unstable();
+            spans.push((start, end - start, notes));
+            position = end;
+        }
+
+        let measure_length = self.measure_length(take.time_signature);
+        let measure_count = position.div_ceil(measure_length).max(1);
+        let mut measures: Vec<Measure> = (0..measure_count)
+            .map(|i| Measure {
+                number: i + 1,
+                events: Vec::new(),
+            })
+            .collect();
+        let end = measure_count * measure_length;
+        if end > position {
+            spans.push((position, end - position, Vec::new()));
+        }
+
+        for (start, duration, notes) in spans {
+            let mut pieces = Vec::new();
+            let mut offset = start;
+            while offset < start + duration {
+                let barline = (offset / measure_length + 1) * measure_length;
+                let piece_end = (start + duration).min(barline);
+                for length in self.split(offset % measure_length, piece_end - offset) {
+                    pieces.push((offset, length));
+                    offset += length;
+                }
+            }
+
+            let tied = !notes.is_empty();
+            let count = pieces.len();
+            for (i, (offset, length)) in pieces.into_iter().enumerate() {
+                measures[(offset / measure_length) as usize]
+                    .events
+                    .push(NotatedEvent {
+                        notes: notes.clone(),
+                        start: offset % measure_length,
+                        duration: length,
+                        tie_start: tied && i + 1 < count,
+                        tie_stop: tied && i > 0,
+                    });
+            }
+        }
+        measures
+    }
+
+    /// Split a length within a measure into lengths with note values
+    ///
+    /// Takes the longest note value that fits and starts on its own grid (a
+    /// half note or dotted quarter on a half-note boundary), so notes line up
+    /// with the beats.
+    fn split(&self, start: u32, duration: u32) -> Vec<u32> {
+        let mut lengths = Vec::new();
+        let (mut offset, mut remaining) = (start, duration);
+        while remaining > 0 {
+            let length = (1..=remaining)
+                .rev()
+                .find(|&length| {
+                    self.note_value(length).is_some()
+                        && offset.is_multiple_of(length.next_power_of_two())
+                })
+                .unwrap_or(1);
+            lengths.push(length);
+            offset += length;
+            remaining -= length;
+        }
+        lengths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::TakeNote;
+
+    /// A take at 120 BPM (500ms per quarter note) from (note, start, end) in quarter notes
+    fn take(notes: &[(u8, f64, f64)]) -> Take {
+        Take {
+            notes: notes
+                .iter()
+                .map(|&(note, start, end)| TakeNote {
+                    note,
+                    channel: 0,
+                    velocity: 80,
+                    start: (start * 500_000.0) as u64,
+                    end: (end * 500_000.0) as u64,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn summary(measure: &Measure) -> Vec<(Vec<u8>, u32)> {
+        measure
+            .events
+            .iter()
+            .map(|e| (e.notes.clone(), e.duration))
+            .collect()
+    }
+
+    #[test]
+    fn test_note_values() {
+        let quantizer = Quantizer::new(4);
+        assert_eq!(quantizer.note_value(16).unwrap().name, "whole");
+        assert_eq!(quantizer.note_value(4).unwrap().name, "quarter");
+        let dotted = quantizer.note_value(6).unwrap();
+        assert_eq!((dotted.name, dotted.dots), ("quarter", 1));
+        assert_eq!(quantizer.note_value(1).unwrap().name, "16th");
+        assert!(quantizer.note_value(5).is_none());
+    }
+
+    #[test]
+    fn test_quantize_snaps_to_grid() {
+        // Slightly early and late notes snap to quarter notes
+        let measures = Quantizer::new(4).quantize(&take(&[
+            (60, 0.02, 0.98),
+            (62, 0.97, 2.03),
+            (64, 2.0, 3.0),
+        ]));
+        assert_eq!(measures.len(), 1);
+        assert_eq!(
+            summary(&measures[0]),
+            vec![(vec![60], 4), (vec![62], 4), (vec![64], 4), (vec![], 4)]
+        );
+    }
+
+    #[test]
+    fn test_quantize_chords_and_rests() {
+        let measures =
+            Quantizer::new(4).quantize(&take(&[(48, 1.0, 2.0), (55, 1.0, 2.0), (52, 1.01, 1.9)]));
+        assert_eq!(
+            summary(&measures[0]),
+            vec![(vec![], 4), (vec![48, 52, 55], 4), (vec![], 8)]
+        );
+    }
+
+    #[test]
+    fn test_quantize_ties_across_barline() {
+        // A half note starting on beat 4 is tied into the next measure
+        let measures = Quantizer::new(4).quantize(&take(&[(60, 3.0, 5.0)]));
+        assert_eq!(measures.len(), 2);
+        let last = measures[0].events.last().unwrap();
+        assert_eq!(
+            (last.duration, last.tie_start, last.tie_stop),
+            (4, true, false)
+        );
+        let first = &measures[1].events[0];
+        assert_eq!(
+            (first.duration, first.tie_start, first.tie_stop),
+            (4, false, true)
+        );
+        assert!(measures[1].events[1].is_rest());
+    }
+
+    #[test]
+    fn test_split_into_note_values() {
+        let quantizer = Quantizer::new(4);
+        // Five sixteenths: a quarter tied to a sixteenth
+        assert_eq!(quantizer.split(0, 5), vec![4, 1]);
+        // A dotted half from the start of a measure
+        assert_eq!(quantizer.split(0, 12), vec![12]);
+        // Off the beat: an eighth, then a quarter
+        assert_eq!(quantizer.split(2, 6), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_measure_length() {
+        let quantizer = Quantizer::new(4);
+        assert_eq!(quantizer.measure_length("4/4".parse().unwrap()), 16);
+        assert_eq!(quantizer.measure_length("6/8".parse().unwrap()), 12);
+    }
+}
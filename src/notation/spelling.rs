@@ -0,0 +1,182 @@
+use super::KeySignature;
+
+/// Letter names in order, with their natural pitch classes
+const LETTERS: [(char, u8); 7] = [
+    ('C', 0),
+    ('D', 2),
+    ('E', 4),
+    ('F', 5),
+    ('G', 7),
+    ('A', 9),
+    ('B', 11),
+];
+
+/// Order in which sharps are added to key signatures (flats use the reverse)
+const SHARP_ORDER: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+/// A spelled pitch: letter, alteration and octave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pitch {
+    /// Letter name, 'A' to 'G'
+    pub step: char,
+    /// Semitones of alteration: -1 = flat, 1 = sharp
+    pub alter: i8,
+    /// Octave of the letter, 4 = the octave from middle C
+    pub octave: i32,
+}
+
+impl Pitch {
+    /// Note name with accidental and octave, e.g. "Bb3" or "F#4"
+    pub fn name(&self) -> String {
+        let accidental = match self.alter {
+            a if a > 0 => "#".repeat(a as usize),
+            a if a < 0 => "b".repeat(a.unsigned_abs() as usize),
+            _ => String::new(),
+        };
+        format!("{}{}{}", self.step, accidental, self.octave)
+    }
+}
+
+/// Spells MIDI notes with the letters and accidentals of a key
+///
+/// Notes of the key use its signature. Other notes are spelled natural when
+/// possible, otherwise with sharps in sharp keys and flats in flat keys; in
+/// minor keys the raised sixth and seventh degrees are always sharpened
+/// (G# in A minor, B natural in C minor).
+pub struct PitchSpeller {
+    key: KeySignature,
+    /// Alteration the key signature gives each letter
+    signature: [i8; 7],
+}
+
+impl PitchSpeller {
+    pub fn new(key: KeySignature) -> Self {
+        let mut signature = [0i8; 7];
+        let count = key.fifths.unsigned_abs().min(7) as usize;
+        for i in 0..count {
+            let letter = if key.fifths > 0 {
+                SHARP_ORDER[i]
+            } else {
+                SHARP_ORDER[6 - i]
+            };
+            signature[letter_index(letter)] = key.fifths.signum();
+        }
+        Self { key, signature }
+    }
+
+    pub fn key(&self) -> KeySignature {
+        self.key
+    }
+
+    /// Alteration the key signature gives a letter
+    pub fn signature_alter(&self, step: char) -> i8 {
+        self.signature[letter_index(step)]
+    }
+
+    /// Spell a MIDI note
+    pub fn spell(&self, note: u8) -> Pitch {
+        let pitch_class = note % 12;
+
+        let (letter, alter) = self
+            .diatonic(pitch_class)
+            .or_else(|| self.raised_minor_degree(pitch_class))
+            .or_else(|| self.with_alter(pitch_class, |_| 0))
+            .or_else(|| {
+                if self.key.fifths >= 0 {
+                    self.with_alter(pitch_class, |l| self.signature[l] + 1)
+                } else {
+                    self.with_alter(pitch_class, |l| self.signature[l] - 1)
+                }
+            })
+            .or_else(|| self.with_alter(pitch_class, |_| 1))
+            .unwrap_or((0, 0));
+
+        // The octave belongs to the letter: B#3 sounds as C4, Cb4 as B3
+        let natural = LETTERS[letter].1 as i32;
+        let octave = (note as i32 - alter as i32 - natural) / 12 - 1;
+        Pitch {
+            step: LETTERS[letter].0,
+            alter,
+            octave,
+        }
+    }
+
+    /// Letter and alteration of a pitch class in the key signature
+    fn diatonic(&self, pitch_class: u8) -> Option<(usize, i8)> {
+        self.with_alter(pitch_class, |l| self.signature[l])
+    }
+
+    /// The raised sixth and seventh of a minor key, spelled on their letters
+    fn raised_minor_degree(&self, pitch_class: u8) -> Option<(usize, i8)> {
+        if !self.key.minor {
+            return None;
+        }
+        let tonic = self.key.tonic();
+        let tonic_letter = self.diatonic(tonic)?.0;
+        [(5, 9), (6, 11)].iter().find_map(|&(degree, interval)| {
+            if (tonic + interval) % 12 != pitch_class {
+                return None;
+            }
+            let letter = (tonic_letter + degree) % 7;
+            Some((letter, self.signature[letter] + 1))
+        })
+    }
+
+    /// Find the letter that spells a pitch class with a given alteration
+    fn with_alter(&self, pitch_class: u8, alter: impl Fn(usize) -> i8) -> Option<(usize, i8)> {
+        (0..LETTERS.len()).find_map(|letter| {
+            let alter = alter(letter);
+            let spelled = (LETTERS[letter].1 as i8 + alter).rem_euclid(12) as u8;
+            (spelled == pitch_class).then_some((letter, alter))
+        })
+    }
+}
+
+fn letter_index(step: char) -> usize {
+    LETTERS.iter().position(|&(l, _)| l == step).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spell(key: &str, note: u8) -> String {
+        PitchSpeller::new(key.parse().unwrap()).spell(note).name()
+    }
+
+    #[test]
+    fn test_key_signature_notes() {
+        assert_eq!(spell("C major", 60), "C4");
+        assert_eq!(spell("F major", 70), "Bb4");
+        assert_eq!(spell("E major", 68), "G#4");
+        assert_eq!(spell("Gb major", 71), "Cb5");
+        assert_eq!(spell("F# major", 65), "E#4");
+    }
+
+    #[test]
+    fn test_chromatic_notes() {
+        // Sharps in sharp keys, flats in flat keys
+        assert_eq!(spell("G major", 61), "C#4");
+        assert_eq!(spell("Bb major", 61), "Db4");
+        // A natural is preferred over an enharmonic accidental
+        assert_eq!(spell("G major", 65), "F4");
+        assert_eq!(spell("C major", 63), "D#4");
+    }
+
+    #[test]
+    fn test_minor_raised_degrees() {
+        assert_eq!(spell("A minor", 68), "G#4");
+        assert_eq!(spell("D minor", 61), "C#4");
+        assert_eq!(spell("C minor", 71), "B4");
+        assert_eq!(spell("C minor", 69), "A4");
+        assert_eq!(spell("C minor", 68), "Ab4");
+    }
+
+    #[test]
+    fn test_octave_follows_letter() {
+        // B#3 and Cb5 sound as C4 and B4
+        let speller = PitchSpeller::new("C# major".parse().unwrap());
+        assert_eq!(speller.spell(60).name(), "B#3");
+        assert_eq!(spell("Cb major", 71), "Cb5");
+    }
+}
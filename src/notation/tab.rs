@@ -0,0 +1,226 @@
+use super::{Measure, Score};
+use crate::guitar::{FingeringInference, NoteCue, TabNote, Tuning};
+use crate::pitch::PitchDetector;
+
+/// Measures per line of ASCII tablature
+pub const MEASURES_PER_LINE: usize = 4;
+
+/// A score with fret positions for every note
+pub struct Tablature {
+    tuning: Tuning,
+    capo: u8,
+    score: Score,
+    /// Positions per measure and event; tied notes keep the positions they were struck at
+    positions: Vec<Vec<Vec<TabNote>>>,
+}
+
+impl Tablature {
+    /// Finger each onset of a score in order
+    pub fn new(score: Score, mut inference: FingeringInference) -> Self {
+        let mut previous: Vec<TabNote> = Vec::new();
+        let positions = score
+            .measures
+            .iter()
+            .map(|measure| {
+                measure
+                    .events
+                    .iter()
+                    .map(|event| {
+                        if event.is_rest() {
+                            return Vec::new();
+                        }
+                        if !event.tie_stop {
+                            let cues: Vec<NoteCue> =
+                                event.notes.iter().map(|&n| NoteCue::new(n)).collect();
+                            previous = inference.tab(&cues);
+                        }
+                        previous.clone()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            tuning: inference.tuning().clone(),
+            capo: inference.capo(),
+            score,
+            positions,
+        }
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    pub fn capo(&self) -> u8 {
+        self.capo
+    }
+
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
+
+    pub fn measures(&self) -> &[Measure] {
+        &self.score.measures
+    }
+
+    /// Fret positions of an event (by measure and event index)
+    pub fn positions(&self, measure: usize, event: usize) -> &[TabNote] {
+        self.positions
+            .get(measure)
+            .and_then(|events| events.get(event))
+            .map_or(&[], |positions| positions.as_slice())
+    }
+
+    /// Render as plain-text tablature, one column per grid step
+    ///
+    /// Frets are written where notes are struck; tied notes ring on without
+    /// a new number.
+    pub fn to_ascii(&self, measures_per_line: usize) -> String {
+        let strings: Vec<String> = self
+            .tuning
+            .open_notes()
+            .iter()
+            .map(|&n| PitchDetector::midi_to_note_name(n))
+            .rev()
+            .collect();
+        let mut out = format!("Tuning: {} ({})", self.tuning.name(), strings.join(" "));
+        if self.capo > 0 {
+            out.push_str(&format!(", capo {}", self.capo));
+        }
+        out.push_str(&format!(
+            "\nTempo: {:.0} BPM, time signature {}\n",
+            self.score.bpm(),
+            self.score.time_signature
+        ));
+
+        let wide = self
+            .positions
+            .iter()
+            .flatten()
+            .flatten()
+            .any(|p| p.fret >= 10);
+        let cell = if wide { 3 } else { 2 };
+        let label_width = (0..self.tuning.string_count())
+            .map(|s| self.tuning.string_label(s).len())
+            .max()
+            .unwrap_or(1);
+
+        let indexed: Vec<usize> = (0..self.measures().len()).collect();
+        for line in indexed.chunks(measures_per_line.max(1)) {
+            out.push('\n');
+            for string in 0..self.tuning.string_count() {
+                out.push_str(&format!(
+                    "{:>width$}|",
+                    self.tuning.string_label(string),
+                    width = label_width
+                ));
+                for &m in line {
+                    out.push_str(&self.ascii_measure(m, string, cell));
+                    out.push('|');
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// One string's row of a measure
+    fn ascii_measure(&self, measure: usize, string: usize, cell: usize) -> String {
+        let events = &self.measures()[measure].events;
+        let length = events
+            .iter()
+            .map(|e| e.start + e.duration)
+            .max()
+            .unwrap_or(0);
+        let mut row = vec!['-'; length as usize * cell];
+        for (i, event) in events.iter().enumerate() {
+            if event.tie_stop {
+                continue;
+            }
+            if let Some(position) = self
+                .positions(measure, i)
+                .iter()
+                .find(|p| p.string == string)
+            {
+                let column = event.start as usize * cell;
+                for (j, digit) in position.fret.to_string().chars().enumerate() {
+                    row[column + j] = digit;
+                }
+            }
+        }
+        row.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{Quantizer, Take, TakeNote};
+
+    fn tablature(take: &Take) -> Tablature {
+        Tablature::new(
+            Score::new(take, &Quantizer::new(1)),
+            FingeringInference::new(Tuning::standard(), 0, 22),
+        )
+    }
+
+    fn note(note: u8, start_beats: f64, end_beats: f64) -> TakeNote {
+        TakeNote {
+            note,
+            channel: 0,
+            velocity: 80,
+            start: (start_beats * 500_000.0) as u64,
+            end: (end_beats * 500_000.0) as u64,
+        }
+    }
+
+    #[test]
+    fn test_ascii_tab() {
+        // E minor arpeggio: E2 (open), B2 (A string fret 2), E3 (D string fret 2), G3 (open)
+        let take = Take {
+            notes: vec![
+                note(40, 0.0, 1.0),
+                note(47, 1.0, 2.0),
+                note(52, 2.0, 3.0),
+                note(55, 3.0, 4.0),
+            ],
+            ..Default::default()
+        };
+        let tab = tablature(&take);
+        let ascii = tab.to_ascii(MEASURES_PER_LINE);
+        let rows: Vec<&str> = ascii.lines().skip(3).collect();
+        assert_eq!(
+            rows,
+            vec![
+                "e|--------|",
+                "B|--------|",
+                "G|------0-|",
+                "D|----2---|",
+                "A|--2-----|",
+                "E|0-------|",
+            ]
+        );
+        assert!(ascii.starts_with("Tuning: standard (E2 A2 D3 G3 B3 E4)"));
+    }
+
+    #[test]
+    fn test_tied_notes_keep_position() {
+        // A note held across the barline is struck once
+        let take = Take {
+            notes: vec![note(62, 3.0, 5.0)],
+            ..Default::default()
+        };
+        let tab = tablature(&take);
+        assert_eq!(tab.measures().len(), 2);
+        assert_eq!(tab.positions(0, 1), tab.positions(1, 0));
+        let fret = tab.positions(0, 1)[0].fret.to_string();
+        let ascii = tab.to_ascii(MEASURES_PER_LINE);
+        let digits: usize = ascii
+            .lines()
+            .skip(3)
+            .map(|row| row.matches(|c: char| c.is_ascii_digit()).count())
+            .sum();
+        assert_eq!(digits, fret.len());
+    }
+}
@@ -0,0 +1,320 @@
+use crate::fuzzy::NoteDetection;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File format of a note log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteLogFormat {
+    /// One JSON object per note and line, including the pitch contour
+    #[default]
+    Jsonl,
+    /// One row per note, without the pitch contour
+    Csv,
+}
+
+impl NoteLogFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            NoteLogFormat::Jsonl => "jsonl",
+            NoteLogFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for NoteLogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(NoteLogFormat::Jsonl),
+            "csv" => Ok(NoteLogFormat::Csv),
+            _ => anyhow::bail!("Unknown note log format '{}' (use jsonl or csv)", s),
+        }
+    }
+}
+
+/// Path of the note log written along with a recording, e.g. take.notes.csv
+pub fn note_log_path(path: &Path, format: NoteLogFormat) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.notes.{}", stem, format.extension()))
+}
+
+/// A played note with the measurements it was detected from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggedNote {
+    /// Seconds from the start of the stream
+    pub onset: f64,
+    pub offset: f64,
+    /// Note sent to the MIDI output
+    pub note: u8,
+    /// Note the pitch detector measured, before fuzzy resolution and scale quantization
+    pub detected_note: u8,
+    pub mean_frequency: f32,
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+    /// Mean detection confidence over the note
+    pub confidence: f32,
+    pub velocity: u8,
+    /// Measured frequency over the note as (seconds, Hz) pairs
+    pub contour: Vec<(f64, f32)>,
+}
+
+impl LoggedNote {
+    /// Whether fuzzy resolution or scale quantization changed the detected note
+    pub fn is_corrected(&self) -> bool {
+        self.note != self.detected_note
+    }
+}
+
+/// Sounding note whose measurements are still being collected
+struct OpenNote {
+    note: LoggedNote,
+    confidence_sum: f32,
+}
+
+/// Collects played notes with their frequency and confidence for analysis
+///
+/// Notes are keyed by a voice (a string in per-string mode, 0 otherwise) and
+/// the output note, so unisons on different strings are logged separately.
+/// Times are stream times in microseconds, as used by the processor.
+#[derive(Default)]
+pub struct NoteLog {
+    open: HashMap<(usize, u8), OpenNote>,
+    notes: Vec<LoggedNote>,
+}
+
+impl NoteLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a note; `detection` holds the output note and its first measurement
+    pub fn note_on(
+        &mut self,
+        time: u64,
+        voice: usize,
+        detection: NoteDetection,
+        detected_note: u8,
+        velocity: u8,
+    ) {
+        self.note_off(time, voice, detection.note);
+        let onset = seconds(time);
+        self.open.insert(
+            (voice, detection.note),
+            OpenNote {
+                note: LoggedNote {
+                    onset,
+                    offset: onset,
+                    note: detection.note,
+                    detected_note,
+                    mean_frequency: detection.frequency,
+                    min_frequency: detection.frequency,
+                    max_frequency: detection.frequency,
+                    confidence: detection.confidence,
+                    velocity,
+                    contour: vec![(onset, detection.frequency)],
+                },
+                confidence_sum: detection.confidence,
+            },
+        );
+    }
+
+    /// Add a measurement of a sounding note; notes that are not sounding are ignored
+    pub fn observe(&mut self, time: u64, voice: usize, detection: NoteDetection) {
+        let Some(open) = self.open.get_mut(&(voice, detection.note)) else {
+            return;
+        };
+        let note = &mut open.note;
+        note.min_frequency = note.min_frequency.min(detection.frequency);
+        note.max_frequency = note.max_frequency.max(detection.frequency);
+        note.contour.push((seconds(time), detection.frequency));
+        open.confidence_sum += detection.confidence;
+    }
+
+    /// End a sounding note
+    pub fn note_off(&mut self, time: u64, voice: usize, note: u8) {
+        if let Some(open) = self.open.remove(&(voice, note)) {
+            self.finish(time, open);
+        }
+    }
+
+    /// End all sounding notes
+    pub fn close_all(&mut self, time: u64) {
+        let mut open: Vec<OpenNote> = self.open.drain().map(|(_, open)| open).collect();
+        open.sort_by(|a, b| a.note.onset.total_cmp(&b.note.onset));
+        for open in open {
+            self.finish(time, open);
+        }
+    }
+
+    fn finish(&mut self, time: u64, open: OpenNote) {
+        let OpenNote {
+            mut note,
+            confidence_sum,
+        } = open;
+        let count = note.contour.len() as f32;
+        note.offset = seconds(time).max(note.onset);
+        note.mean_frequency = note.contour.iter().map(|&(_, f)| f).sum::<f32>() / count;
+        note.confidence = confidence_sum / count;
+        self.notes.push(note);
+    }
+
+    /// Finished notes in the order they ended
+    pub fn notes(&self) -> &[LoggedNote] {
+        &self.notes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Forget all notes, finished or sounding
+    pub fn clear(&mut self) {
+        self.open.clear();
+        self.notes.clear();
+    }
+
+    /// Write the finished notes, ordered by onset
+    pub fn save(&self, path: &Path, format: NoteLogFormat) -> Result<()> {
+        let mut notes: Vec<&LoggedNote> = self.notes.iter().collect();
+        notes.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+        let text = match format {
+            NoteLogFormat::Jsonl => to_jsonl(&notes)?,
+            NoteLogFormat::Csv => to_csv(&notes),
+        };
+        std::fs::write(path, text).with_context(|| format!("Failed to write note log {:?}", path))
+    }
+}
+
+fn seconds(time: u64) -> f64 {
+    time as f64 / 1_000_000.0
+}
+
+fn to_jsonl(notes: &[&LoggedNote]) -> Result<String> {
+    let mut text = String::new();
+    for note in notes {
+        text.push_str(&serde_json::to_string(note)?);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+fn to_csv(notes: &[&LoggedNote]) -> String {
+    let mut text = String::from(
+        "onset,offset,note,detected_note,mean_frequency,min_frequency,max_frequency,confidence,velocity\n",
+    );
+    for note in notes {
+        let _ = writeln!(
+            text,
+            "{:.6},{:.6},{},{},{:.3},{:.3},{:.3},{:.4},{}",
+            note.onset,
+            note.offset,
+            note.note,
+            note.detected_note,
+            note.mean_frequency,
+            note.min_frequency,
+            note.max_frequency,
+            note.confidence,
+            note.velocity
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(note: u8, frequency: f32, confidence: f32) -> NoteDetection {
+        NoteDetection {
+            note,
+            frequency,
+            confidence,
+        }
+    }
+
+    #[test]
+    fn test_note_log() {
+        let mut log = NoteLog::new();
+        log.note_on(1_000_000, 0, detection(69, 438.0, 0.4), 68, 100);
+        log.observe(1_020_000, 0, detection(69, 442.0, 0.8));
+        // Measurements of notes that are not sounding are dropped
+        log.observe(1_030_000, 0, detection(70, 466.0, 0.9));
+        log.observe(1_040_000, 1, detection(69, 440.0, 0.9));
+        log.note_off(1_500_000, 0, 69);
+
+        let note = &log.notes()[0];
+        assert_eq!(log.notes().len(), 1);
+        assert_eq!(note.onset, 1.0);
+        assert_eq!(note.offset, 1.5);
+        assert!(note.is_corrected());
+        assert_eq!(note.mean_frequency, 440.0);
+        assert_eq!(note.min_frequency, 438.0);
+        assert_eq!(note.max_frequency, 442.0);
+        assert!((note.confidence - 0.6).abs() < 1e-6);
+        assert_eq!(note.contour, vec![(1.0, 438.0), (1.02, 442.0)]);
+    }
+
+    #[test]
+    fn test_note_log_voices() {
+        let mut log = NoteLog::new();
+        // The same note on two strings
+        log.note_on(0, 0, detection(64, 329.6, 0.9), 64, 80);
+        log.note_on(100_000, 1, detection(64, 329.6, 0.9), 64, 80);
+        log.note_off(200_000, 1, 64);
+        log.close_all(300_000);
+
+        let offsets: Vec<f64> = log.notes().iter().map(|n| n.offset).collect();
+        assert_eq!(offsets, vec![0.2, 0.3]);
+
+        log.clear();
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn test_note_log_files() {
+        let mut log = NoteLog::new();
+        log.note_on(500_000, 0, detection(62, 293.7, 0.5), 62, 90);
+        log.note_on(0, 1, detection(40, 82.4, 0.9), 41, 90);
+        log.close_all(1_000_000);
+
+        let dir = std::env::temp_dir();
+        let csv = note_log_path(&dir.join("test_note_log.mid"), NoteLogFormat::Csv);
+        assert_eq!(csv, dir.join("test_note_log.notes.csv"));
+        log.save(&csv, NoteLogFormat::Csv).unwrap();
+        let text = std::fs::read_to_string(&csv).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("onset,offset,note,detected_note"));
+        // Ordered by onset
+        assert!(lines[1].starts_with("0.000000,1.000000,40,41,82.400"));
+
+        let jsonl = note_log_path(&dir.join("test_note_log.mid"), NoteLogFormat::Jsonl);
+        log.save(&jsonl, NoteLogFormat::Jsonl).unwrap();
+        let notes: Vec<LoggedNote> = std::fs::read_to_string(&jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(notes[0].note, 40);
+        assert_eq!(notes[1].contour, vec![(0.5, 293.7)]);
+
+        std::fs::remove_file(&csv).unwrap();
+        std::fs::remove_file(&jsonl).unwrap();
+    }
+
+    #[test]
+    fn test_note_log_format() {
+        assert_eq!("csv".parse::<NoteLogFormat>().unwrap(), NoteLogFormat::Csv);
+        assert_eq!(
+            "JSONL".parse::<NoteLogFormat>().unwrap(),
+            NoteLogFormat::Jsonl
+        );
+        assert!("xml".parse::<NoteLogFormat>().is_err());
+    }
+}
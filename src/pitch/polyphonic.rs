@@ -17,6 +17,8 @@ pub struct PolyphonicPitchDetector {
     buffer_size: usize,
     fft_planner: FftPlanner<f32>,
     min_peak_magnitude: f32,
+    /// Magnitude spectrum of the most recent analysis frame
+    last_magnitudes: Vec<f32>,
 }
 
 impl PolyphonicPitchDetector {
@@ -26,6 +28,7 @@ impl PolyphonicPitchDetector {
             buffer_size,
             fft_planner: FftPlanner::new(),
             min_peak_magnitude,
+            last_magnitudes: Vec::new(),
         }
     }
 
@@ -68,7 +71,8 @@ impl PolyphonicPitchDetector {
         let mut candidates: Vec<PitchCandidate> = peaks
             .into_iter()
             .filter_map(|(bin, magnitude)| {
-                let frequency = bin as f32 * self.sample_rate / self.buffer_size as f32;
+                let bin = bin as f32 + Self::peak_offset(&magnitudes, bin);
+                let frequency = bin * self.sample_rate / self.buffer_size as f32;
 
                 // Filter to guitar frequency range
                 if (MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
@@ -93,6 +97,8 @@ impl PolyphonicPitchDetector {
         // Limit to maximum 6 simultaneous notes (reasonable for guitar chords)
         candidates.truncate(6);
 
+        self.last_magnitudes = magnitudes;
+
         if !candidates.is_empty() {
             debug!("Detected {} simultaneous pitches", candidates.len());
             for candidate in &candidates {
@@ -127,6 +133,66 @@ impl PolyphonicPitchDetector {
         peaks
     }
 
+    /// Fractional bin offset of a spectral peak (-0.5 to +0.5)
+    ///
+    /// Fits a parabola through the log magnitudes around the peak, which gives
+    /// sub-bin frequency accuracy for a Hamming-windowed sinusoid.
+    fn peak_offset(magnitudes: &[f32], bin: usize) -> f32 {
+        if bin == 0 || bin + 1 >= magnitudes.len() {
+            return 0.0;
+        }
+
+        let a = magnitudes[bin - 1].max(f32::MIN_POSITIVE).ln();
+        let b = magnitudes[bin].max(f32::MIN_POSITIVE).ln();
+        let c = magnitudes[bin + 1].max(f32::MIN_POSITIVE).ln();
+
+        let denom = a - 2.0 * b + c;
+        if denom.abs() < f32::EPSILON {
+            return 0.0;
+        }
+        (0.5 * (a - c) / denom).clamp(-0.5, 0.5)
+    }
+
+    /// Normalize a candidate magnitude to 0.0-1.0, where 1.0 is a full-scale sine
+    pub fn normalized_magnitude(&self, magnitude: f32) -> f32 {
+        // A unit sine through a Hamming window peaks at 0.54 * N / 2
+        let full_scale = 0.54 * self.buffer_size as f32 / 2.0;
+        (magnitude / full_scale).clamp(0.0, 1.0)
+    }
+
+    /// Estimate the brightness of a note in the last analysed frame (0.0-1.0)
+    ///
+    /// Ratio of the energy in the 2nd-6th harmonics to the total energy of the
+    /// note's harmonic series. Dull, fundamental-heavy tones are near 0.0.
+    pub fn harmonic_brightness(&self, frequency: f32) -> f32 {
+        if self.last_magnitudes.is_empty() || frequency <= 0.0 {
+            return 0.0;
+        }
+
+        let bin_width = self.sample_rate / self.buffer_size as f32;
+        let harmonic_peak = |harmonic: usize| -> f32 {
+            let center = (frequency * harmonic as f32 / bin_width).round() as usize;
+            if center >= self.last_magnitudes.len() {
+                return 0.0;
+            }
+            let start = center.saturating_sub(1);
+            let end = (center + 1).min(self.last_magnitudes.len() - 1);
+            self.last_magnitudes[start..=end]
+                .iter()
+                .copied()
+                .fold(0.0, f32::max)
+        };
+
+        let fundamental = harmonic_peak(1);
+        let overtones: f32 = (2..=6).map(harmonic_peak).sum();
+        let total = fundamental + overtones;
+        if total <= 0.0 {
+            0.0
+        } else {
+            overtones / total
+        }
+    }
+
     /// Remove harmonic duplicates, keeping only fundamental frequencies
     fn remove_harmonics(&self, mut candidates: Vec<PitchCandidate>) -> Vec<PitchCandidate> {
         if candidates.len() <= 1 {
@@ -278,6 +344,50 @@ mod tests {
         assert!(filtered.iter().any(|c| (c.frequency - 330.0).abs() < 1.0));
     }
 
+    #[test]
+    fn test_interpolated_frequency_accuracy() {
+        let sample_rate = 44100;
+        let mut detector = PolyphonicPitchDetector::new(sample_rate, 2048, 0.1);
+
+        // 445 Hz sits between FFT bins (bin width ~21.5 Hz)
+        let frequency = 445.0;
+        let samples: Vec<f32> = (0..2048)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / 44100.0).sin())
+            .collect();
+
+        let pitches = detector.detect_pitches(&samples);
+        assert!(!pitches.is_empty());
+        assert_relative_eq!(pitches[0].frequency, frequency, epsilon = 2.0);
+    }
+
+    #[test]
+    fn test_normalized_magnitude_and_brightness() {
+        let sample_rate = 44100;
+        let mut detector = PolyphonicPitchDetector::new(sample_rate, 2048, 0.1);
+
+        let frequency = 220.0;
+        let pure: Vec<f32> = (0..2048)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / 44100.0).sin())
+            .collect();
+        let pitches = detector.detect_pitches(&pure);
+        let level = detector.normalized_magnitude(pitches[0].magnitude);
+        assert!(level > 0.5 && level <= 1.0);
+        let pure_brightness = detector.harmonic_brightness(frequency);
+
+        // Add strong overtones: the same note should read brighter
+        let bright: Vec<f32> = (0..2048)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                (1..=4)
+                    .map(|h| (2.0 * std::f32::consts::PI * frequency * h as f32 * t).sin() * 0.5)
+                    .sum::<f32>()
+            })
+            .collect();
+        detector.detect_pitches(&bright);
+        let rich_brightness = detector.harmonic_brightness(frequency);
+        assert!(rich_brightness > pure_brightness);
+    }
+
     #[test]
     fn test_frequency_to_midi() {
         // A4 = 440 Hz = MIDI note 69
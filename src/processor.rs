@@ -1,35 +1,109 @@
 use anyhow::Result;
-use crossbeam_channel::{bounded, Receiver};
-use log::{debug, info};
-use std::collections::HashSet;
-use std::time::{Duration, Instant};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError};
+use log::{debug, info, warn};
+use midly::{num::u7, MidiMessage};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::MutexGuard;
+use std::time::Duration;
 use tokio::sync::broadcast;
 
-use crate::audio::AudioInput;
-use crate::config::Config;
+use crate::audio::{audio_capture_path, AudioCapture, AudioInput, SampleClock};
+use crate::config::{Config, StringAssignmentMode};
 use crate::fuzzy::{FuzzyNoteResolver, NoteDetection};
-use crate::midi::{MidiOutputHandler, MidiRecorder};
-use crate::pitch::polyphonic::PolyphonicPitchDetector;
+use crate::guitar::{FingeringInference, NoteCue, StringAssigner, Tuning};
+use crate::lifecycle::ProcessorHandle;
+use crate::midi::{
+    journal_path, take_path, Autosave, Cleanup, ControllerThinning, MidiOutputHandler,
+    MidiRecorder, NoteExpression, NoteMapper, SharedRecorder, TrackLayout, ZoneRoute, ZoneRouter,
+    RAW_DETECTION_TRACK,
+};
+use crate::notelog::{note_log_path, NoteLog};
+use crate::pitch::polyphonic::{PitchCandidate, PolyphonicPitchDetector};
 use crate::pitch::PitchDetector;
+use crate::rhythm::{BeatTracker, OnsetDetector, Tempo};
+use crate::theory::{pitch_class_name, Chord, Key, KeyDetector, Mode, Scale};
 use crate::web::MonitoringEvent;
 
+/// Detection state of one string of a hexaphonic input
+#[derive(Debug, Default)]
+struct StringVoice {
+    note: Option<u8>,
+    /// Stream time the note started at
+    start_time: Option<u64>,
+}
+
 pub struct StreamProcessor {
     config: Config,
     audio_input: AudioInput,
     pitch_detector: PitchDetector,
     polyphonic_detector: Option<PolyphonicPitchDetector>,
     midi_output: MidiOutputHandler,
-    midi_recorder: Option<MidiRecorder>,
+    /// Recorder shared with the MIDI outputs, which record everything they send
+    midi_recorder: Option<SharedRecorder>,
+    /// Recorder track of each zone in the zone track layout
+    zone_tracks: Vec<usize>,
+    /// Recorder track of the raw pitch detection in the detection track layout
+    raw_track: Option<usize>,
+    /// Mapped note of the raw detection recorded to `raw_track`
+    raw_note: Option<u8>,
+    note_mapper: NoteMapper,
+    zone_router: ZoneRouter,
+    /// Outputs for zone ports other than the main port (output index - 1)
+    zone_outputs: Vec<MidiOutputHandler>,
+    /// Zone routes of sounding notes, keyed by mapped note
+    zone_notes: HashMap<u8, Vec<ZoneRoute>>,
     fuzzy_resolver: Option<FuzzyNoteResolver>,
     current_note: Option<u8>,
     active_notes: HashSet<u8>,
-    note_start_time: Option<Instant>,
+    /// Stream time the current note started at
+    note_start_time: Option<u64>,
+    tuning: Tuning,
+    string_assigner: Option<StringAssigner>,
+    string_voices: Vec<StringVoice>,
+    current_chord: Option<Chord>,
+    fingering: Option<FingeringInference>,
+    key_detector: Option<KeyDetector>,
+    beat_tracker: Option<BeatTracker>,
+    onset_detector: OnsetDetector,
+    /// Sample position of the input stream
+    clock: SampleClock,
+    /// Stream time of the audio being processed, in microseconds
+    stream_time: u64,
+    current_key: Option<Key>,
+    /// Stream time of the last note fed to the key detector, in microseconds
+    last_key_update: u64,
+    /// Configured scale for quantization (None = use the detected key)
+    scale: Option<Scale>,
     web_event_tx: Option<broadcast::Sender<MonitoringEvent>>,
+    /// File the recording is saved and autosaved to
+    record_path: PathBuf,
+    /// Saves the recording every `record_autosave_interval` seconds while running
+    autosave: Option<Autosave>,
+    /// Input audio written alongside the recording
+    audio_capture: Option<AudioCapture>,
+    /// Played notes with their measurements, written alongside the recording
+    note_log: Option<NoteLog>,
+    handle: ProcessorHandle,
+    /// Set once stopped, so stopping again does not save the recording twice
+    stopped: bool,
 }
 
 impl StreamProcessor {
+    /// Create a processor for the default input device
     pub fn new(config: Config) -> Result<Self> {
-        let audio_input = AudioInput::new()?;
+        Self::with_input(config, AudioInput::new()?)
+    }
+
+    /// Create a processor that converts a WAV file offline
+    ///
+    /// Nothing is sent to MIDI ports, so enable recording to keep the
+    /// result. Processing ends at the end of the file.
+    pub fn from_file(config: Config, path: &Path) -> Result<Self> {
+        Self::with_input(config, AudioInput::open(path)?)
+    }
+
+    fn with_input(config: Config, audio_input: AudioInput) -> Result<Self> {
         let sample_rate = audio_input.sample_rate();
 
         let pitch_detector =
@@ -47,8 +121,61 @@ impl StreamProcessor {
             None
         };
 
+        let tuning = config.guitar_tuning()?;
+
+        // Per-string channel mode: infer strings from the tuning, or read one string per input channel
+        let mut string_assigner = None;
+        let mut string_voices = Vec::new();
+        if config.string_channels_enabled {
+            match config.string_assignment {
+                StringAssignmentMode::Tuning => {
+                    info!(
+                        "Per-string MIDI channels enabled ({} tuning)",
+                        tuning.name()
+                    );
+                    string_assigner = Some(StringAssigner::new(
+                        tuning.clone(),
+                        config.capo,
+                        config.max_fret,
+                    ));
+                }
+                StringAssignmentMode::InputChannel => {
+                    let channels = audio_input.channels() as usize;
+                    if channels < tuning.string_count() {
+                        warn!(
+                            "Input has {} channels but the tuning has {} strings",
+                            channels,
+                            tuning.string_count()
+                        );
+                    }
+                    info!(
+                        "Per-string MIDI channels enabled from {} input channels",
+                        channels
+                    );
+                    string_voices = (0..tuning.string_count().min(channels))
+                        .map(|_| StringVoice::default())
+                        .collect();
+                }
+            }
+        }
+
         let mut midi_output = MidiOutputHandler::new()?;
-        midi_output.connect(config.midi_port.as_deref())?;
+        if config.mpe_enabled {
+            midi_output.enable_mpe(config.mpe_member_channels, config.mpe_pitch_bend_range);
+        }
+        if audio_input.is_live() {
+            midi_output.connect(config.midi_port.as_deref())?;
+        } else {
+            midi_output.record_only()?;
+        }
+
+        if config.string_channels_enabled {
+            let range = config.pitch_bend_range.round().clamp(1.0, 96.0) as u8;
+            for string in 0..tuning.string_count() {
+                midi_output
+                    .set_pitch_bend_range(config.string_channel_base + string as u8, range)?;
+            }
+        }
 
         // Initialize fuzzy note resolver if enabled (only for monophonic mode)
         let fuzzy_resolver = if config.fuzzy_enabled && !config.polyphonic_enabled {
@@ -62,10 +189,160 @@ impl StreamProcessor {
             None
         };
 
+        let note_mapper = NoteMapper::from_config(&config);
+        if !note_mapper.is_identity() {
+            info!(
+                "Note mapping: transpose {:+} semitones, range {}-{}, {} remapped notes",
+                note_mapper.transpose(),
+                config.note_range_low,
+                config.note_range_high,
+                config.note_remap.len()
+            );
+        }
+
+        let zone_router = ZoneRouter::new(
+            &config.zones,
+            note_mapper.channel(),
+            config.midi_port.as_deref(),
+        );
+        let mut zone_outputs = Vec::new();
+        for port in zone_router.ports() {
+            let mut output = MidiOutputHandler::new()?;
+            if audio_input.is_live() {
+                output.connect(Some(port))?;
+            } else {
+                output.record_only()?;
+            }
+            zone_outputs.push(output);
+        }
+        for zone in &config.zones {
+            info!(
+                "Zone {}: notes {}-{}, channel {}, port {}, transpose {:+}",
+                zone.name,
+                zone.note_low,
+                zone.note_high,
+                zone.channel.unwrap_or(note_mapper.channel()) + 1,
+                zone.port.as_deref().unwrap_or("main"),
+                zone.transpose
+            );
+        }
+
+        // Per-string mode already knows the strings, so fingering is only inferred otherwise
+        let fingering = (config.tab_enabled && !config.string_channels_enabled)
+            .then(|| FingeringInference::new(tuning.clone(), config.capo, config.max_fret));
+
+        let key_detector = config
+            .key_detection_enabled
+            .then(|| KeyDetector::new(config.key_half_life));
+
+        let beat_tracker = config
+            .beat_tracking_enabled
+            .then(|| BeatTracker::new(config.beat_tracking_min_bpm, config.beat_tracking_max_bpm));
+
+        let scale = config.scale.as_deref().map(Scale::parse).transpose()?;
+        if config.scale_quantize_enabled {
+            match &scale {
+                Some(scale) => info!("Scale quantization to {}", scale.name()),
+                None => info!("Scale quantization to the detected key"),
+            }
+        }
+
         // Initialize MIDI recorder if enabled
+        let mut zone_tracks = Vec::new();
+        let mut raw_track = None;
+        let record_path = PathBuf::from(config.record_output.clone().unwrap_or_else(|| {
+            let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+            format!("recording_{}.mid", timestamp)
+        }));
         let midi_recorder = if config.record_enabled {
             info!("MIDI recording enabled");
-            Some(MidiRecorder::new())
+            let recorder = MidiRecorder::shared();
+            {
+                let mut recorder = recorder.lock().unwrap();
+                if config.record_thinning_enabled {
+                    let thinning = ControllerThinning::new(
+                        Duration::from_secs_f32(config.record_thinning_interval),
+                        config.record_thinning_delta,
+                    );
+                    recorder.set_thinning(Some(thinning));
+                }
+                recorder.set_bpm(config.record_bpm);
+                recorder.set_time_signature(config.record_time_signature()?);
+                recorder.set_ticks_per_beat(config.record_ppq);
+                if !config.record_tempo_changes.is_empty() {
+                    let changes: Vec<(u32, f32)> = config
+                        .record_tempo_changes
+                        .iter()
+                        .map(|change| (change.bar, change.bpm))
+                        .collect();
+                    recorder.set_bar_tempos(&changes);
+                }
+                if config.record_journal_enabled {
+                    let journal = journal_path(&record_path);
+                    info!("Journaling the recording to {}", journal.display());
+                    recorder.set_journal(Some(journal));
+                }
+                if config.record_cleanup_enabled {
+                    recorder.set_cleanup(Some(Cleanup::from_config(&config)));
+                }
+                recorder.set_take_storage(config.record_takes);
+                recorder.set_punch(config.record_punch());
+                if let Some(overdub) = &config.record_overdub {
+                    if Path::new(overdub) == record_path {
+                        anyhow::bail!("Cannot overdub onto the recording output {}", overdub);
+                    }
+                    // Adopts the tempo map and time signature of the take
+                    recorder.overdub(overdub)?;
+                }
+                if !config.record_armed {
+                    recorder.disarm();
+                }
+                recorder.set_layout(config.record_tracks);
+                match config.record_tracks {
+                    TrackLayout::Single => {}
+                    TrackLayout::Channel => {
+                        if config.string_channels_enabled {
+                            for string in 0..tuning.string_count() {
+                                let open = tuning.open_note(string).unwrap_or(0);
+                                recorder.set_channel_name(
+                                    config.string_channel_base + string as u8,
+                                    &format!(
+                                        "String {} ({})",
+                                        string + 1,
+                                        PitchDetector::midi_to_note_name(open)
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                    TrackLayout::Zone => {
+                        if config.zones.is_empty() {
+                            warn!("Zone track layout without zones, recording a single track");
+                        }
+                        zone_tracks = config
+                            .zones
+                            .iter()
+                            .enumerate()
+                            .map(|(index, zone)| match zone.name.as_str() {
+                                "" => recorder.add_track(&format!("Zone {}", index + 1)),
+                                name => recorder.add_track(name),
+                            })
+                            .collect();
+                    }
+                    TrackLayout::Detection => {
+                        if fuzzy_resolver.is_none() {
+                            warn!("Detection track layout without fuzzy resolution, both tracks will match");
+                        }
+                        recorder.set_track_name(0, "Fuzzy-resolved");
+                        raw_track = Some(recorder.add_track(RAW_DETECTION_TRACK));
+                    }
+                }
+            }
+            midi_output.set_recorder(recorder.clone());
+            for output in &mut zone_outputs {
+                output.set_recorder(recorder.clone());
+            }
+            Some(recorder)
         } else {
             None
         };
@@ -75,6 +352,14 @@ impl StreamProcessor {
             sample_rate
         );
 
+        let clock = SampleClock::new(sample_rate, audio_input.channels());
+        let note_log =
+            (config.record_enabled && config.record_note_log.is_some()).then(NoteLog::new);
+        let handle = ProcessorHandle::default();
+        if config.record_armed {
+            handle.arm();
+        }
+
         Ok(Self {
             config,
             audio_input,
@@ -82,31 +367,92 @@ impl StreamProcessor {
             polyphonic_detector,
             midi_output,
             midi_recorder,
+            zone_tracks,
+            raw_track,
+            raw_note: None,
+            note_mapper,
+            zone_router,
+            zone_outputs,
+            zone_notes: HashMap::new(),
             fuzzy_resolver,
             current_note: None,
             active_notes: HashSet::new(),
             note_start_time: None,
+            tuning,
+            string_assigner,
+            string_voices,
+            current_chord: None,
+            fingering,
+            key_detector,
+            beat_tracker,
+            onset_detector: OnsetDetector::new(),
+            clock,
+            stream_time: 0,
+            current_key: None,
+            last_key_update: 0,
+            scale,
             web_event_tx: None,
+            record_path,
+            autosave: None,
+            audio_capture: None,
+            note_log,
+            handle,
+            stopped: false,
         })
     }
 
+    /// Lock the recorder, if recording is enabled
+    fn recorder(&self) -> Option<MutexGuard<'_, MidiRecorder>> {
+        self.midi_recorder
+            .as_ref()
+            .and_then(|recorder| recorder.lock().ok())
+    }
+
+    /// Get a handle to shut down, pause or resume the processor
+    pub fn handle(&self) -> ProcessorHandle {
+        self.handle.clone()
+    }
+
     /// Set the web event sender for broadcasting monitoring events
     pub fn set_web_event_sender(&mut self, tx: broadcast::Sender<MonitoringEvent>) {
         self.web_event_tx = Some(tx);
     }
 
+    /// Process audio until shut down through the processor's handle
+    ///
+    /// The recording is saved by `stop`, which is also called on drop.
     pub fn start(&mut self) -> Result<()> {
         info!("Starting real-time audio processing...");
+        self.stopped = false;
 
         // Start MIDI recording if enabled
-        if let Some(recorder) = &mut self.midi_recorder {
+        if let Some(mut recorder) = self.recorder() {
             recorder.start();
         }
+        self.clock.reset();
+        self.stream_time = 0;
+        self.last_key_update = 0;
+        if let Some(log) = &mut self.note_log {
+            log.clear();
+        }
+        let interval = self.config.record_autosave_interval;
+        if let Some(recorder) = self.midi_recorder.clone().filter(|_| interval > 0.0) {
+            let interval = Duration::from_secs_f32(interval);
+            let autosave = Autosave::start(recorder, self.record_path.clone(), interval)?;
+            self.autosave = Some(autosave);
+        }
+        let first_take = self
+            .recorder()
+            .filter(|recorder| recorder.is_armed())
+            .map(|recorder| recorder.take_number());
+        if let Some(take) = first_take {
+            self.begin_audio_capture(take)?;
+        }
 
         // Broadcast recording status to web UI
         if let Some(tx) = &self.web_event_tx {
             let _ = tx.send(MonitoringEvent::RecordingStatus {
-                recording: self.midi_recorder.is_some(),
+                recording: self.recorder().is_some_and(|recorder| recorder.is_armed()),
             });
         }
 
@@ -121,23 +467,154 @@ impl StreamProcessor {
     fn process_audio_stream(&mut self, rx: Receiver<Vec<f32>>) -> Result<()> {
         let mut buffer = Vec::new();
 
-        loop {
+        // Hexaphonic input is processed as interleaved frames, one sample per string
+        let frame_width = if self.string_voices.is_empty() {
+            1
+        } else {
+            self.audio_input.channels() as usize
+        };
+        let chunk_size = self.config.buffer_size * frame_width;
+
+        let mut paused = false;
+        while !self.handle.is_shutdown() {
+            if self.handle.is_paused() != paused {
+                paused = !paused;
+                buffer.clear();
+                self.set_paused(paused)?;
+            }
+            self.sync_armed()?;
+
             match rx.recv_timeout(Duration::from_millis(100)) {
                 Ok(samples) => {
-                    buffer.extend_from_slice(&samples);
+                    // Dropped samples count too, so time goes on while paused
+                    self.clock.advance(samples.len());
+                    if let Some(capture) = &self.audio_capture {
+                        capture.write(&samples);
+                    }
+                    if !paused {
+                        buffer.extend_from_slice(&samples);
+                    }
 
                     // Process buffer when we have enough samples
-                    while buffer.len() >= self.config.buffer_size {
-                        let chunk: Vec<f32> = buffer.drain(..self.config.buffer_size).collect();
-                        self.process_chunk(&chunk)?;
+                    while buffer.len() >= chunk_size {
+                        let chunk: Vec<f32> = buffer.drain(..chunk_size).collect();
+                        let chunk_end = self.clock.samples() - buffer.len() as u64;
+                        let time = self.clock.window_time(chunk_end, chunk_size);
+                        self.set_stream_time(time);
+                        if let Some(strength) = self.onset_detector.process(time, &chunk) {
+                            self.observe_onset(strength);
+                        }
+                        if frame_width > 1 {
+                            self.process_strings(&chunk, frame_width)?;
+                            let notes: Vec<u8> =
+                                self.string_voices.iter().filter_map(|v| v.note).collect();
+                            self.update_chord(&notes);
+                        } else {
+                            self.process_chunk(&chunk)?;
+                        }
+                        self.advance_beats();
                     }
                 }
-                Err(_) => {
+                Err(RecvTimeoutError::Timeout) => {
                     // Timeout - continue processing
                     continue;
                 }
+                Err(RecvTimeoutError::Disconnected) => {
+                    info!("End of input");
+                    return Ok(());
+                }
             }
         }
+
+        info!("Shutdown requested");
+        Ok(())
+    }
+
+    /// Arm or disarm the recorder as requested through the handle
+    ///
+    /// A take starts at the next sample received, which is also the first
+    /// sample of the take's audio capture.
+    fn sync_armed(&mut self) -> Result<()> {
+        let armed = self.handle.is_armed();
+        let start = self.clock.time_at(self.clock.samples());
+        let Some(mut recorder) = self.recorder() else {
+            return Ok(());
+        };
+        if recorder.is_armed() == armed {
+            return Ok(());
+        }
+        if armed {
+            recorder.arm_at(start);
+        } else {
+            recorder.disarm();
+        }
+        let take = recorder.take_number();
+        drop(recorder);
+
+        let message = if armed {
+            self.begin_audio_capture(take)?;
+            format!("Recording take {}", take)
+        } else {
+            self.finish_audio_capture()?;
+            format!("Take {} finished", take)
+        };
+        self.broadcast(MonitoringEvent::RecordingStatus { recording: armed });
+        self.broadcast(MonitoringEvent::Status { message });
+        Ok(())
+    }
+
+    /// Capture the input audio of a take, next to the take's MIDI file
+    ///
+    /// The capture starts at the next sample received, where the take starts.
+    /// A file input is already on disk, so only live input is captured.
+    fn begin_audio_capture(&mut self, take: usize) -> Result<()> {
+        self.finish_audio_capture()?;
+        if self.midi_recorder.is_none()
+            || !self.config.record_audio_enabled
+            || !self.audio_input.is_live()
+        {
+            return Ok(());
+        }
+        let path = audio_capture_path(&take_path(&self.record_path, take));
+        let capture = AudioCapture::create(
+            &path,
+            self.audio_input.sample_rate(),
+            self.audio_input.channels(),
+        )?;
+        info!("Capturing input audio to {}", path.display());
+        self.audio_capture = Some(capture);
+        Ok(())
+    }
+
+    /// Close the audio capture of the current take, if any
+    fn finish_audio_capture(&mut self) -> Result<()> {
+        let Some(capture) = self.audio_capture.take() else {
+            return Ok(());
+        };
+        let path = capture.path().to_path_buf();
+        let frames = capture.finish()?;
+        info!(
+            "Input audio saved to: {} ({:.1}s)",
+            path.display(),
+            frames as f32 / self.audio_input.sample_rate() as f32
+        );
+        Ok(())
+    }
+
+    /// Release all sounding notes when pausing; resuming starts from a clean slate
+    fn set_paused(&mut self, paused: bool) -> Result<()> {
+        let message = if paused {
+            self.release_notes()?;
+            "Processing paused"
+        } else {
+            self.onset_detector.reset();
+            "Processing resumed"
+        };
+        info!("{}", message);
+        self.broadcast(MonitoringEvent::Status {
+            message: message.to_string(),
+        });
+        Ok(())
     }
 
     fn process_chunk(&mut self, samples: &[f32]) -> Result<()> {
@@ -171,50 +648,69 @@ impl StreamProcessor {
             .collect();
 
         for &note in &notes_to_turn_off {
-            self.midi_output.note_off(note)?;
-            if let Some(recorder) = &mut self.midi_recorder {
-                recorder.record_note_off(note);
-            }
-
-            // Broadcast note off event
-            if let Some(tx) = &self.web_event_tx {
-                let note_name = PolyphonicPitchDetector::midi_to_note_name(note);
-                let _ = tx.send(MonitoringEvent::NoteOff { note, note_name });
-            }
-
+            self.output_note_off(note)?;
             self.active_notes.remove(&note);
             debug!("Note off (polyphonic): {}", note);
         }
 
-        // Turn on new notes
-        let notes_to_turn_on: Vec<u8> = detected_notes
+        // Turn on new notes, lowest first so chords spread across the strings in order
+        let mut notes_to_turn_on: Vec<u8> = detected_notes
             .difference(&self.active_notes)
             .copied()
             .collect();
+        notes_to_turn_on.sort_unstable();
+
+        if !notes_to_turn_on.is_empty() {
+            let cues: Vec<NoteCue> = notes_to_turn_on
+                .iter()
+                .map(|&note| NoteCue {
+                    note,
+                    brightness: candidates
+                        .iter()
+                        .find(|c| c.midi_note == note)
+                        .filter(|_| self.config.fingering_timbre_cues)
+                        .map(|c| poly_detector.harmonic_brightness(c.frequency)),
+                })
+                .collect();
+            self.update_tab(&cues);
+        }
 
         for &note in &notes_to_turn_on {
-            self.midi_output.note_on(note, self.config.velocity)?;
-            if let Some(recorder) = &mut self.midi_recorder {
-                recorder.record_note_on(note, self.config.velocity);
+            if let Some(candidate) = candidates.iter().find(|c| c.midi_note == note) {
+                let expression = self.candidate_expression(poly_detector, candidate);
+                let detection = NoteDetection {
+                    note,
+                    frequency: candidate.frequency,
+                    confidence: candidate.magnitude,
+                };
+                self.output_note_on(detection, note, self.config.velocity, expression)?;
+                self.log_note_on(0, note, detection);
             }
 
             self.active_notes.insert(note);
+            debug!("Note on (polyphonic): {}", note);
+        }
 
-            // Broadcast note on event
-            if let Some(tx) = &self.web_event_tx {
-                let note_name = PolyphonicPitchDetector::midi_to_note_name(note);
-                if let Some(candidate) = candidates.iter().find(|c| c.midi_note == note) {
-                    let _ = tx.send(MonitoringEvent::NoteOn {
-                        note,
-                        note_name: note_name.clone(),
-                        frequency: candidate.frequency,
-                        velocity: self.config.velocity,
-                        confidence: candidate.magnitude,
-                    });
-                }
+        for candidate in &candidates {
+            if !notes_to_turn_on.contains(&candidate.midi_note) {
+                let detection = NoteDetection {
+                    note: candidate.midi_note,
+                    frequency: candidate.frequency,
+                    confidence: candidate.magnitude,
+                };
+                self.log_observe(0, candidate.midi_note, detection);
             }
+        }
 
-            debug!("Note on (polyphonic): {}", note);
+        // Update per-note expression of notes that keep sounding
+        if self.midi_output.is_mpe_enabled() || self.string_assigner.is_some() {
+            for candidate in &candidates {
+                if notes_to_turn_on.contains(&candidate.midi_note) {
+                    continue;
+                }
+                let expression = self.candidate_expression(poly_detector, candidate);
+                self.output_note_expression(candidate.midi_note, expression)?;
+            }
         }
 
         // Log active notes if changed
@@ -225,17 +721,34 @@ impl StreamProcessor {
                 .map(|&n| PolyphonicPitchDetector::midi_to_note_name(n))
                 .collect();
             info!("Active notes: {}", note_names.join(", "));
+
+            let notes: Vec<u8> = self.active_notes.iter().copied().collect();
+            self.update_chord(&notes);
         }
 
         Ok(())
     }
 
+    /// Derive per-note expression from a polyphonic pitch candidate
+    fn candidate_expression(
+        &self,
+        poly_detector: &PolyphonicPitchDetector,
+        candidate: &PitchCandidate,
+    ) -> NoteExpression {
+        NoteExpression {
+            bend: self.note_bend(candidate.frequency, candidate.midi_note),
+            pressure: poly_detector.normalized_magnitude(candidate.magnitude),
+            timbre: poly_detector.harmonic_brightness(candidate.frequency),
+        }
+    }
+
     fn process_monophonic(&mut self, samples: &[f32]) -> Result<()> {
         // Detect pitch with confidence
         if let Some((frequency, confidence)) =
             self.pitch_detector.detect_pitch_with_confidence(samples)
         {
             let detected_note = PitchDetector::frequency_to_midi(frequency);
+            self.record_raw(Some(detected_note));
 
             // Create note detection
             let detection = NoteDetection {
@@ -251,6 +764,9 @@ impl StreamProcessor {
                 detection
             };
 
+            // Bends follow the played note, so a snapped note keeps its vibrato
+            let played_note = resolved_detection.note;
+            let resolved_detection = self.quantize(resolved_detection);
             let note = resolved_detection.note;
             let note_name = PitchDetector::midi_to_note_name(note);
 
@@ -258,41 +774,25 @@ impl StreamProcessor {
             if Some(note) != self.current_note {
                 // Turn off previous note if it exists
                 if let Some(prev_note) = self.current_note {
-                    self.midi_output.note_off(prev_note)?;
-                    if let Some(recorder) = &mut self.midi_recorder {
-                        recorder.record_note_off(prev_note);
-                    }
-
-                    // Broadcast note off event
-                    if let Some(tx) = &self.web_event_tx {
-                        let prev_note_name = PitchDetector::midi_to_note_name(prev_note);
-                        let _ = tx.send(MonitoringEvent::NoteOff {
-                            note: prev_note,
-                            note_name: prev_note_name,
-                        });
-                    }
-
+                    self.output_note_off(prev_note)?;
                     debug!("Note changed from {} to {}", prev_note, note_name);
                 }
 
                 // Start new note
-                self.midi_output.note_on(note, self.config.velocity)?;
-                if let Some(recorder) = &mut self.midi_recorder {
-                    recorder.record_note_on(note, self.config.velocity);
-                }
+                self.update_tab(&[NoteCue::new(played_note)]);
+                let expression = NoteExpression {
+                    bend: self.note_bend(frequency, played_note),
+                    ..Default::default()
+                };
+                self.output_note_on(
+                    resolved_detection,
+                    played_note,
+                    self.config.velocity,
+                    expression,
+                )?;
+                self.log_note_on(0, note, detection);
                 self.current_note = Some(note);
-                self.note_start_time = Some(Instant::now());
-
-                // Broadcast note on event
-                if let Some(tx) = &self.web_event_tx {
-                    let _ = tx.send(MonitoringEvent::NoteOn {
-                        note,
-                        note_name: note_name.clone(),
-                        frequency,
-                        velocity: self.config.velocity,
-                        confidence,
-                    });
-                }
+                self.note_start_time = Some(self.stream_time);
 
                 if confidence < self.config.fuzzy_threshold && self.config.fuzzy_enabled {
                     // For fuzzy-resolved notes, show the expected frequency of the resolved note
@@ -304,42 +804,26 @@ impl StreamProcessor {
                 } else {
                     info!("Playing note: {} ({:.2} Hz)", note_name, frequency);
                 }
+            } else {
+                self.log_observe(0, note, detection);
             }
 
             // Apply pitch bend if enabled and we have an active note
             if self.config.pitch_bend_enabled && self.current_note.is_some() {
-                let bend = PitchDetector::calculate_pitch_bend(
-                    frequency,
-                    note,
-                    self.config.pitch_bend_range,
-                );
-                self.midi_output.pitch_bend(bend)?;
-
-                // Broadcast pitch bend event
-                if let Some(tx) = &self.web_event_tx {
-                    let _ = tx.send(MonitoringEvent::PitchBend {
-                        note,
-                        bend_value: bend,
-                    });
-                }
+                let expression = NoteExpression {
+                    bend: self.note_bend(frequency, played_note),
+                    ..Default::default()
+                };
+                self.output_note_expression(note, expression)?;
             }
         } else {
+            self.record_raw(None);
             // No pitch detected - turn off current note if minimum duration met
             if let Some(note) = self.current_note {
                 if let Some(start_time) = self.note_start_time {
-                    let duration = start_time.elapsed().as_secs_f32();
+                    let duration = self.seconds_since(start_time);
                     if duration >= self.config.min_note_duration {
-                        self.midi_output.note_off(note)?;
-                        if let Some(recorder) = &mut self.midi_recorder {
-                            recorder.record_note_off(note);
-                        }
-
-                        // Broadcast note off event
-                        if let Some(tx) = &self.web_event_tx {
-                            let note_name = PitchDetector::midi_to_note_name(note);
-                            let _ = tx.send(MonitoringEvent::NoteOff { note, note_name });
-                        }
-
+                        self.output_note_off(note)?;
                         debug!("Note off after {:.2}s", duration);
                         self.current_note = None;
                         self.note_start_time = None;
@@ -351,31 +835,614 @@ impl StreamProcessor {
         Ok(())
     }
 
+    /// Process interleaved hexaphonic input: each input channel is one string
+    fn process_strings(&mut self, frames: &[f32], channels: usize) -> Result<()> {
+        for string in 0..self.string_voices.len() {
+            let samples: Vec<f32> = frames
+                .iter()
+                .skip(string)
+                .step_by(channels)
+                .copied()
+                .collect();
+            let channel = self.string_channel(string);
+            let open_note = self.tuning.open_note(string).unwrap_or(0);
+
+            let Some((frequency, confidence)) =
+                self.pitch_detector.detect_pitch_with_confidence(&samples)
+            else {
+                // Silence on this string - release its note once the minimum duration is met
+                let voice = &self.string_voices[string];
+                if let (Some(note), Some(start_time)) = (voice.note, voice.start_time) {
+                    if self.seconds_since(start_time) >= self.config.min_note_duration {
+                        self.string_note_off(string, note)?;
+                    }
+                }
+                continue;
+            };
+
+            let note = PitchDetector::frequency_to_midi(frequency);
+            let bend = self.note_bend(frequency, note);
+            let detection = NoteDetection {
+                note,
+                frequency,
+                confidence,
+            };
+            self.log_observe(string, note, detection);
+
+            if self.string_voices[string].note != Some(note) {
+                if let Some(prev_note) = self.string_voices[string].note {
+                    self.string_note_off(string, prev_note)?;
+                }
+                self.string_voices[string] = StringVoice {
+                    note: Some(note),
+                    start_time: Some(self.stream_time),
+                };
+
+                let Some(mapped) = self.note_mapper.map(note) else {
+                    continue;
+                };
+                self.midi_output.pitch_bend_channel(channel, bend)?;
+                self.midi_output
+                    .note_on_channel(channel, mapped, self.config.velocity)?;
+
+                self.broadcast(MonitoringEvent::StringAssignment {
+                    note: mapped,
+                    string,
+                    fret: note.saturating_sub(open_note),
+                    channel,
+                });
+                self.broadcast(MonitoringEvent::NoteOn {
+                    note: mapped,
+                    note_name: PitchDetector::midi_to_note_name(mapped),
+                    frequency,
+                    velocity: self.config.velocity,
+                    confidence,
+                });
+                self.observe_key(mapped, confidence);
+                self.observe_onset(self.config.velocity as f32 / 127.0);
+                self.log_note_on(string, note, detection);
+                debug!("String {} note on: {}", string + 1, mapped);
+            } else if self.config.pitch_bend_enabled {
+                let Some(mapped) = self.note_mapper.map(note) else {
+                    continue;
+                };
+                self.midi_output.pitch_bend_channel(channel, bend)?;
+                self.broadcast(MonitoringEvent::PitchBend {
+                    note: mapped,
+                    bend_value: bend,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Release the note sounding on a string of a hexaphonic input
+    fn string_note_off(&mut self, string: usize, note: u8) -> Result<()> {
+        self.string_voices[string] = StringVoice::default();
+        self.log_note_off(string, note);
+        let Some(mapped) = self.note_mapper.map(note) else {
+            return Ok(());
+        };
+
+        let channel = self.string_channel(string);
+        self.midi_output.note_off_channel(channel, mapped)?;
+
+        self.broadcast(MonitoringEvent::NoteOff {
+            note: mapped,
+            note_name: PitchDetector::midi_to_note_name(mapped),
+        });
+        debug!("String {} note off: {}", string + 1, mapped);
+        Ok(())
+    }
+
+    /// Send a detected note to the MIDI output, the recorder and the web UI
+    ///
+    /// The note is passed through the note mapper first, so all three see the
+    /// same mapped note; filtered-out notes are dropped. In per-string mode the
+    /// note is assigned to a string and sent on that string's channel; in MPE
+    /// mode it gets its own member channel. The expression's bend is applied
+    /// before the note starts in both modes.
+    ///
+    /// `played_note` is the note before scale quantization. It feeds the key
+    /// detector, so notes snapped to the detected key do not reinforce it.
+    fn output_note_on(
+        &mut self,
+        detection: NoteDetection,
+        played_note: u8,
+        velocity: u8,
+        expression: NoteExpression,
+    ) -> Result<()> {
+        let Some(note) = self.note_mapper.map(detection.note) else {
+            debug!("Note {} filtered out by note mapping", detection.note);
+            return Ok(());
+        };
+
+        if let Some(assigner) = &mut self.string_assigner {
+            // Strings are assigned from the played note, not the mapped one
+            let Some(position) = assigner.assign(detection.note) else {
+                debug!("No free string for note {}", detection.note);
+                return Ok(());
+            };
+            let channel = self.string_channel(position.string);
+            self.broadcast(MonitoringEvent::StringAssignment {
+                note,
+                string: position.string,
+                fret: position.fret,
+                channel,
+            });
+            self.midi_output
+                .pitch_bend_channel(channel, expression.bend)?;
+            self.midi_output.note_on_channel(channel, note, velocity)?;
+        } else if self.midi_output.is_mpe_enabled() {
+            self.midi_output.mpe_note_on(note, velocity, expression)?;
+        } else {
+            let routes = self.zone_router.route(note);
+            for route in &routes {
+                self.route_handler(route)
+                    .note_on_channel(route.channel, route.note, velocity)?;
+            }
+            self.zone_notes.insert(note, routes);
+        }
+
+        self.broadcast(MonitoringEvent::NoteOn {
+            note,
+            note_name: PitchDetector::midi_to_note_name(note),
+            frequency: detection.frequency,
+            velocity,
+            confidence: detection.confidence,
+        });
+        if let Some(played) = self.note_mapper.map(played_note) {
+            self.observe_key(played, detection.confidence);
+        }
+        self.observe_onset(velocity as f32 / 127.0);
+        Ok(())
+    }
+
+    /// Release a detected note on the MIDI output, the recorder and the web UI
+    fn output_note_off(&mut self, detected_note: u8) -> Result<()> {
+        let Some(note) = self.note_mapper.map(detected_note) else {
+            return Ok(());
+        };
+        self.log_note_off(0, detected_note);
+
+        if let Some(assigner) = &mut self.string_assigner {
+            let Some(position) = assigner.release(detected_note) else {
+                return Ok(());
+            };
+            let channel = self.string_channel(position.string);
+            self.midi_output.note_off_channel(channel, note)?;
+        } else if self.midi_output.is_mpe_enabled() {
+            self.midi_output.note_off(note)?;
+        } else {
+            let routes = self.zone_notes.remove(&note).unwrap_or_default();
+            for route in &routes {
+                self.route_handler(route)
+                    .note_off_channel(route.channel, route.note)?;
+            }
+        }
+
+        self.broadcast(MonitoringEvent::NoteOff {
+            note,
+            note_name: PitchDetector::midi_to_note_name(note),
+        });
+        Ok(())
+    }
+
+    /// Update the expression of a sounding detected note
+    ///
+    /// Per-string and MPE modes bend only the note's own channel; otherwise
+    /// the bend applies to the whole output channel.
+    fn output_note_expression(
+        &mut self,
+        detected_note: u8,
+        expression: NoteExpression,
+    ) -> Result<()> {
+        let Some(note) = self.note_mapper.map(detected_note) else {
+            return Ok(());
+        };
+
+        let string_position = self
+            .string_assigner
+            .as_ref()
+            .and_then(|assigner| assigner.position_of(detected_note));
+
+        if let Some(position) = string_position {
+            let channel = self.string_channel(position.string);
+            self.midi_output
+                .pitch_bend_channel(channel, expression.bend)?;
+        } else if self.midi_output.is_mpe_enabled() {
+            self.midi_output.note_expression(note, expression)?;
+        } else {
+            let routes = self.zone_notes.get(&note).cloned().unwrap_or_default();
+            for route in routes {
+                self.route_handler(&route)
+                    .pitch_bend_channel(route.channel, expression.bend)?;
+            }
+        }
+
+        self.broadcast(MonitoringEvent::PitchBend {
+            note,
+            bend_value: expression.bend,
+        });
+        Ok(())
+    }
+
+    /// Snap a resolved detection onto the active scale if scale quantization is enabled
+    ///
+    /// The active scale is the configured one, or the detected key's scale.
+    /// Until a key has been detected notes pass through unchanged.
+    fn quantize(&self, detection: NoteDetection) -> NoteDetection {
+        if !self.config.scale_quantize_enabled {
+            return detection;
+        }
+        let scale = match (&self.scale, &self.current_key) {
+            (Some(scale), _) => scale.clone(),
+            (None, Some(key)) => Scale::from_key(key),
+            (None, None) => return detection,
+        };
+        let note = scale.snap(detection.note, self.config.scale_snap);
+        if note != detection.note {
+            debug!(
+                "Snapped note {} to {} ({})",
+                detection.note,
+                note,
+                scale.name()
+            );
+        }
+        NoteDetection { note, ..detection }
+    }
+
+    /// Feed a played (mapped) note to the key detector and report key changes
+    ///
+    /// A new key is reported to the web UI and written to the recording as a
+    /// key signature once its confidence reaches the configured threshold.
+    fn observe_key(&mut self, note: u8, confidence: f32) {
+        let elapsed = self.seconds_since(self.last_key_update);
+        let Some(detector) = &mut self.key_detector else {
+            return;
+        };
+        detector.decay(elapsed);
+        self.last_key_update = self.stream_time;
+        detector.add_note(note, confidence.clamp(0.0, 1.0));
+
+        let Some(key) = detector.estimate() else {
+            return;
+        };
+        if key.confidence < self.config.key_confidence_threshold {
+            return;
+        }
+        if self
+            .current_key
+            .is_some_and(|current| current.same_key(&key))
+        {
+            self.current_key = Some(key);
+            return;
+        }
+
+        info!("Key: {} (confidence {:.2})", key.name(), key.confidence);
+        if let Some(mut recorder) = self.recorder() {
+            recorder.record_key_signature(key.sharps(), key.mode == Mode::Minor);
+        }
+        self.broadcast(MonitoringEvent::Key {
+            name: key.name(),
+            tonic: pitch_class_name(key.tonic).to_string(),
+            mode: match key.mode {
+                Mode::Major => "major".to_string(),
+                Mode::Minor => "minor".to_string(),
+            },
+            confidence: key.confidence,
+            sharps: key.sharps(),
+        });
+        self.current_key = Some(key);
+    }
+
+    /// Stamp everything from now on with the stream time of the audio being processed
+    ///
+    /// The recorder shares the stamp, so recorded events sit where the sound
+    /// was in the input rather than where it was processed.
+    fn set_stream_time(&mut self, time: u64) {
+        self.stream_time = time;
+        if let Some(mut recorder) = self.recorder() {
+            recorder.set_time(Some(time));
+        }
+    }
+
+    /// Seconds of stream time since an earlier stream time
+    fn seconds_since(&self, time: u64) -> f32 {
+        self.stream_time.saturating_sub(time) as f32 / 1_000_000.0
+    }
+
+    /// Feed a note attack (from the audio or a note on) to the beat tracker
+    fn observe_onset(&mut self, strength: f32) {
+        let time = self.stream_time;
+        if let Some(tracker) = &mut self.beat_tracker {
+            tracker.add_onset(time, strength);
+        }
+    }
+
+    /// Pass the beats up to now and report the tempo to the web UI on each beat
+    fn advance_beats(&mut self) {
+        let time = self.stream_time;
+        let Some(tracker) = &mut self.beat_tracker else {
+            return;
+        };
+        if tracker.advance(time) == 0 {
+            return;
+        }
+        let Some(tempo) = tracker.tempo() else {
+            return;
+        };
+        let beat = tracker.beats().len();
+        debug!("Beat {}: {:.1} BPM", beat, tempo.bpm);
+        self.broadcast(MonitoringEvent::Tempo {
+            bpm: tempo.bpm,
+            confidence: tempo.confidence,
+            beat,
+        });
+    }
+
+    /// The current tempo estimate, if beat tracking is enabled and has found a beat
+    pub fn current_tempo(&self) -> Option<Tempo> {
+        self.beat_tracker.as_ref().and_then(BeatTracker::tempo)
+    }
+
+    /// The current estimated key, if key detection is enabled and confident
+    pub fn current_key(&self) -> Option<Key> {
+        self.current_key
+    }
+
+    /// Infer the fretboard positions of an onset and send them to the web UI
+    fn update_tab(&mut self, cues: &[NoteCue]) {
+        let Some(fingering) = &mut self.fingering else {
+            return;
+        };
+        let notes = fingering.tab(cues);
+        if !notes.is_empty() {
+            self.broadcast(MonitoringEvent::Tab { notes });
+        }
+    }
+
+    /// Recognize the chord formed by the sounding (detected) notes
+    ///
+    /// When the chord changes it is sent to the web UI and written to the
+    /// recording as a marker. The chord is named from the mapped notes so that
+    /// it matches the MIDI output.
+    fn update_chord(&mut self, detected_notes: &[u8]) {
+        let notes: Vec<u8> = detected_notes
+            .iter()
+            .filter_map(|&note| self.note_mapper.map(note))
+            .collect();
+        let Some(chord) = Chord::from_notes(&notes) else {
+            return;
+        };
+        if self.current_chord.as_ref() == Some(&chord) {
+            return;
+        }
+
+        let name = chord.name();
+        info!("Chord: {}", name);
+        if let Some(mut recorder) = self.recorder() {
+            recorder.record_marker(&name);
+        }
+        self.broadcast(MonitoringEvent::Chord {
+            name,
+            root: chord.root_name().to_string(),
+            quality: format!("{:?}", chord.quality),
+            extensions: chord.extensions.iter().map(|e| e.to_string()).collect(),
+            bass: pitch_class_name(chord.bass).to_string(),
+            inversion: chord.inversion,
+            notes,
+        });
+        self.current_chord = Some(chord);
+    }
+
+    /// Pitch bend for a detected frequency relative to its note, using the active bend range
+    fn note_bend(&self, frequency: f32, note: u8) -> f32 {
+        if !self.config.pitch_bend_enabled {
+            return 0.0;
+        }
+        let range = if self.midi_output.is_mpe_enabled() {
+            self.config.mpe_pitch_bend_range as f32
+        } else {
+            self.config.pitch_bend_range
+        };
+        PitchDetector::calculate_pitch_bend(frequency, note, range)
+    }
+
+    /// MIDI output for a zone output index (0 = main port)
+    fn output_handler(&mut self, output: usize) -> &mut MidiOutputHandler {
+        match output {
+            0 => &mut self.midi_output,
+            n => &mut self.zone_outputs[n - 1],
+        }
+    }
+
+    /// Output handler of a zone route, recording to the zone's track if it has one
+    fn route_handler(&mut self, route: &ZoneRoute) -> &mut MidiOutputHandler {
+        let track = route
+            .zone
+            .and_then(|zone| self.zone_tracks.get(zone))
+            .copied()
+            .unwrap_or(0);
+        if let Some(mut recorder) = self.recorder() {
+            recorder.set_track(track);
+        }
+        self.output_handler(route.output)
+    }
+
+    /// Record the detected note before fuzzy resolution to the raw detection track
+    fn record_raw(&mut self, note: Option<u8>) {
+        let Some(track) = self.raw_track else {
+            return;
+        };
+        let note = note.and_then(|note| self.note_mapper.map(note));
+        if note == self.raw_note {
+            return;
+        }
+
+        let channel = self.note_mapper.channel();
+        if let Some(mut recorder) = self.recorder() {
+            if let Some(previous) = self.raw_note {
+                let message = MidiMessage::NoteOff {
+                    key: u7::new(previous),
+                    vel: u7::new(0),
+                };
+                recorder.record_message_on_track(track, channel, message);
+            }
+            if let Some(note) = note {
+                let message = MidiMessage::NoteOn {
+                    key: u7::new(note),
+                    vel: u7::new(self.config.velocity),
+                };
+                recorder.record_message_on_track(track, channel, message);
+            }
+        }
+        self.raw_note = note;
+    }
+
+    /// Start logging a played note with the measurement it was detected from
+    ///
+    /// `note` is the note played before mapping and `detection` the pitch
+    /// detector's measurement; both are logged as mapped notes.
+    fn log_note_on(&mut self, voice: usize, note: u8, detection: NoteDetection) {
+        let (time, velocity) = (self.stream_time, self.config.velocity);
+        let detected_note = self
+            .note_mapper
+            .map(detection.note)
+            .unwrap_or(detection.note);
+        let (Some(log), Some(note)) = (&mut self.note_log, self.note_mapper.map(note)) else {
+            return;
+        };
+        let detection = NoteDetection { note, ..detection };
+        log.note_on(time, voice, detection, detected_note, velocity);
+    }
+
+    /// Add a measurement to a logged note that keeps sounding
+    fn log_observe(&mut self, voice: usize, note: u8, detection: NoteDetection) {
+        let time = self.stream_time;
+        let (Some(log), Some(note)) = (&mut self.note_log, self.note_mapper.map(note)) else {
+            return;
+        };
+        log.observe(time, voice, NoteDetection { note, ..detection });
+    }
+
+    /// End a logged note
+    fn log_note_off(&mut self, voice: usize, note: u8) {
+        let time = self.stream_time;
+        if let (Some(log), Some(note)) = (&mut self.note_log, self.note_mapper.map(note)) {
+            log.note_off(time, voice, note);
+        }
+    }
+
+    /// MIDI channel of a string in per-string mode
+    fn string_channel(&self, string: usize) -> u8 {
+        self.config.string_channel_base + string as u8
+    }
+
+    /// Send an event to the web UI if it is connected
+    fn broadcast(&self, event: MonitoringEvent) {
+        if let Some(tx) = &self.web_event_tx {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Silence the outputs, then save the recording
     pub fn stop(&mut self) -> Result<()> {
+        if self.stopped {
+            return Ok(());
+        }
         info!("Stopping stream processor...");
-
-        // Turn off all active notes
-        self.midi_output.all_notes_off()?;
-        self.active_notes.clear();
+        self.release_notes()?;
+        // The final save replaces the autosaves
+        if let Some(autosave) = self.autosave.take() {
+            autosave.stop();
+        }
 
         // Save MIDI recording if enabled
-        if let Some(recorder) = &mut self.midi_recorder {
+        let tempo_map = self
+            .beat_tracker
+            .as_ref()
+            .filter(|tracker| self.config.record_tempo_tracking && tracker.beats().len() >= 2)
+            .map(|tracker| tracker.tempo_map(self.config.record_bpm));
+        if let Some(mut recorder) = self.recorder() {
             recorder.stop();
+            // The tracked beats count from the start of the session, which only
+            // the first take shares; an overdub keeps the tempo map of its take
+            let tempo_map = tempo_map.filter(|_| {
+                self.config.record_armed && recorder.take_number() == 1 && !recorder.is_overdub()
+            });
+            if let Some(tempo_map) = tempo_map {
+                info!(
+                    "Writing tracked tempo map ({} tempo changes)",
+                    tempo_map.len() - 1
+                );
+                recorder.set_tempo_map(tempo_map);
+            }
             if recorder.event_count() > 0 {
-                let default_path;
-                let output_path = if let Some(ref path) = self.config.record_output {
-                    path.as_str()
-                } else {
-                    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-                    default_path = format!("recording_{}.mid", timestamp);
-                    &default_path
-                };
-                recorder.save(output_path)?;
-                info!("MIDI recording saved to: {}", output_path);
+                recorder.save(&self.record_path)?;
+                info!("MIDI recording saved to: {}", self.record_path.display());
             } else {
                 info!("No MIDI events recorded");
             }
+            recorder.close_journal(true);
+        }
+        if let (Some(log), Some(format)) = (&self.note_log, self.config.record_note_log) {
+            if !log.is_empty() {
+                let path = note_log_path(&self.record_path, format);
+                log.save(&path, format)?;
+                info!(
+                    "Note log saved to: {} ({} notes)",
+                    path.display(),
+                    log.notes().len()
+                );
+            }
+        }
+        self.finish_audio_capture()?;
+        if self.midi_recorder.is_some() {
+            self.broadcast(MonitoringEvent::RecordingStatus { recording: false });
+        }
+        self.stopped = true;
+
+        Ok(())
+    }
+
+    /// Release all sounding notes and reset the detection state
+    ///
+    /// Besides note offs, every output gets all sound off and all notes off
+    /// on the channels it used, so nothing keeps ringing on the receiver.
+    fn release_notes(&mut self) -> Result<()> {
+        // Turn off all active notes, zone notes first so they end on their own tracks
+        self.record_raw(None);
+        let zone_notes: Vec<ZoneRoute> = self.zone_notes.drain().flat_map(|(_, r)| r).collect();
+        for route in &zone_notes {
+            self.route_handler(route)
+                .note_off_channel(route.channel, route.note)?;
+        }
+        if let Some(mut recorder) = self.recorder() {
+            recorder.set_track(0);
+        }
+        self.midi_output.silence()?;
+        for output in &mut self.zone_outputs {
+            output.silence()?;
+        }
+        self.zone_notes.clear();
+        self.current_chord = None;
+        if let Some(fingering) = &mut self.fingering {
+            fingering.reset();
+        }
+        self.active_notes.clear();
+        self.current_note = None;
+        self.note_start_time = None;
+        if let Some(log) = &mut self.note_log {
+            log.close_all(self.stream_time);
+        }
+        if let Some(assigner) = &mut self.string_assigner {
+            assigner.reset();
         }
+        self.string_voices
+            .iter_mut()
+            .for_each(|voice| *voice = StringVoice::default());
+        self.onset_detector.reset();
 
         Ok(())
     }
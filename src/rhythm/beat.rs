@@ -0,0 +1,454 @@
+use std::collections::VecDeque;
+
+use crate::midi::TempoChange;
+
+/// Onsets older than this are forgotten, in microseconds
+const WINDOW: u64 = 8_000_000;
+
+/// Onsets closer together than this are merged, e.g. an audio onset and its note on
+const MERGE_INTERVAL: u64 = 50_000;
+
+/// Onsets needed before a tempo is estimated
+const MIN_ONSETS: usize = 4;
+
+/// Timing spread of played onsets around the beat grid, in microseconds
+const TIMING_SPREAD: f64 = 25_000.0;
+
+/// Tempo the estimate leans towards when choosing between double and half time
+const PREFERRED_BPM: f64 = 120.0;
+
+/// Width of the preferred tempo range in octaves
+const PREFERENCE_OCTAVES: f64 = 1.0;
+
+/// Step between candidate tempos in BPM
+const BPM_STEP: f64 = 0.5;
+
+/// Intervals of up to this many beats support a tempo
+const MAX_MULTIPLE: u32 = 4;
+
+/// Steps of the beat phase search per beat
+const PHASE_STEPS: usize = 48;
+
+/// How far each beat moves towards the onset nearest to it, as a fraction of the error
+const PHASE_GAIN: f64 = 0.7;
+
+/// Age at which an onset interval counts half towards the tempo, in microseconds
+const TEMPO_HALF_LIFE: f64 = 3_000_000.0;
+
+/// Fastest tempo written to tempo maps, as microseconds per quarter note (400 BPM)
+const MIN_TEMPO: f64 = 150_000.0;
+
+const MICROSECONDS_PER_MINUTE: f64 = 60_000_000.0;
+
+/// A note attack with its time in microseconds and strength (0.0 to 1.0)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onset {
+    pub time: u64,
+    pub strength: f32,
+}
+
+/// An estimated tempo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tempo {
+    pub bpm: f32,
+    /// Share of recent onsets that fall on the beat grid (0.0 to 1.0)
+    pub confidence: f32,
+}
+
+/// Estimates tempo and beat positions from a stream of onsets
+///
+/// The tempo is the beat period best supported by the intervals between
+/// recent onsets (an interval of one to four beats supports it), leaning
+/// towards 120 BPM to choose between double and half time. Each beat is
+/// predicted one period after the previous one and pulled towards the
+/// nearest onset, so the beat grid follows a performance that speeds up or
+/// slows down. A beat is placed a quarter period after its predicted time,
+/// once the onsets around it are known.
+#[derive(Debug, Clone)]
+pub struct BeatTracker {
+    min_bpm: f64,
+    max_bpm: f64,
+    onsets: VecDeque<Onset>,
+    /// Beat period in microseconds
+    period: Option<f64>,
+    /// Time of a beat of the best-fitting grid through the recent onsets
+    phase: f64,
+    confidence: f32,
+    next_beat: Option<f64>,
+    beats: Vec<u64>,
+}
+
+impl BeatTracker {
+    pub fn new(min_bpm: f32, max_bpm: f32) -> Self {
+        Self {
+            min_bpm: min_bpm.max(1.0) as f64,
+            max_bpm: max_bpm.max(min_bpm + 1.0) as f64,
+            onsets: VecDeque::new(),
+            period: None,
+            phase: 0.0,
+            confidence: 0.0,
+            next_beat: None,
+            beats: Vec::new(),
+        }
+    }
+
+    /// Track beats through onsets after the fact, e.g. the note ons of a recording
+    pub fn track(onsets: impl IntoIterator<Item = Onset>, min_bpm: f32, max_bpm: f32) -> Self {
+        let mut tracker = Self::new(min_bpm, max_bpm);
+        let mut last = 0;
+        for onset in onsets {
+            tracker.advance(onset.time);
+            tracker.add_onset(onset.time, onset.strength);
+            last = onset.time;
+        }
+        // Place the beat at the last onset
+        if let Some(period) = tracker.period {
+            tracker.advance(last + (period / 4.0).ceil() as u64);
+        }
+        tracker
+    }
+
+    /// Add an onset at a time in microseconds; onsets must arrive in time order
+    pub fn add_onset(&mut self, time: u64, strength: f32) {
+        match self.onsets.back_mut() {
+            Some(last) if time.saturating_sub(last.time) < MERGE_INTERVAL => {
+                last.strength = last.strength.max(strength);
+            }
+            _ => self.onsets.push_back(Onset { time, strength }),
+        }
+        while self
+            .onsets
+            .front()
+            .is_some_and(|onset| onset.time + WINDOW < time)
+        {
+            self.onsets.pop_front();
+        }
+
+        if self.onsets.len() >= MIN_ONSETS {
+            self.estimate();
+        }
+    }
+
+    /// Place the beats up to a quarter period before a time; returns how many were placed
+    ///
+    /// Tracking stops after a window without onsets and restarts with the
+    /// next ones.
+    pub fn advance(&mut self, now: u64) -> usize {
+        let Some(period) = self.period else {
+            return 0;
+        };
+        if self
+            .onsets
+            .back()
+            .is_none_or(|onset| onset.time + WINDOW < now)
+        {
+            self.onsets.clear();
+            self.period = None;
+            self.next_beat = None;
+            return 0;
+        }
+
+        let mut count = 0;
+        while let Some(next) = self
+            .next_beat
+            .filter(|&next| next + period / 4.0 <= now as f64)
+        {
+            let beat = self.aligned(next, period);
+            self.beats.push(beat.round() as u64);
+            self.next_beat = Some(beat + period);
+            count += 1;
+        }
+        count
+    }
+
+    /// The current tempo estimate, if enough onsets were seen
+    pub fn tempo(&self) -> Option<Tempo> {
+        self.period.map(|period| Tempo {
+            bpm: (MICROSECONDS_PER_MINUTE / period) as f32,
+            confidence: self.confidence,
+        })
+    }
+
+    /// Times of the beats passed so far, in microseconds
+    pub fn beats(&self) -> &[u64] {
+        &self.beats
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.min_bpm as f32, self.max_bpm as f32);
+    }
+
+    /// A tempo map with one quarter note per tracked beat
+    ///
+    /// The time before the first beat is a whole number of beats when it is
+    /// long enough, so the first beat falls on the grid. A gap of several
+    /// beats without onsets keeps the tempo before it. Without at least two
+    /// beats the map holds only `fallback_bpm`.
+    pub fn tempo_map(&self, fallback_bpm: f32) -> Vec<TempoChange> {
+        let beats = &self.beats;
+        if beats.len() < 2 {
+            return vec![TempoChange::new(0, fallback_bpm)];
+        }
+
+        let mut period = (beats[1] - beats[0]) as f64;
+        let lead_in = beats[0] as f64;
+        let lead_beats = (lead_in / period).round();
+        let first = if lead_beats >= 1.0 && lead_in / lead_beats >= MIN_TEMPO {
+            lead_in / lead_beats
+        } else {
+            period
+        };
+        let mut map = vec![tempo_change(0, first)];
+
+        for pair in beats.windows(2) {
+            let interval = (pair[1] - pair[0]) as f64;
+            let count = (interval / period).round().max(1.0);
+            period = interval / count;
+            let change = tempo_change(pair[0], period);
+            if map.last().is_some_and(|last| last.tempo != change.tempo) {
+                map.push(change);
+            }
+        }
+        map
+    }
+
+    /// Re-estimate the period and phase from the recent onsets
+    fn estimate(&mut self) {
+        let Some(period) = self.best_period() else {
+            return;
+        };
+        self.period = Some(period);
+        self.phase = self.best_phase(period);
+        self.confidence = self.grid_agreement(period);
+
+        if self.next_beat.is_none() {
+            // Start on the grid at the oldest onset, so the first beats are emitted at once
+            let first = self.onsets.front().map_or(0.0, |onset| onset.time as f64);
+            let start = first - period / 4.0;
+            let steps = ((start - self.phase) / period).ceil();
+            self.next_beat = Some(self.phase + steps * period);
+        }
+    }
+
+    /// Move a predicted beat towards the strongest nearby onset within a quarter period
+    fn aligned(&self, predicted: f64, period: f64) -> f64 {
+        let nearest = self
+            .onsets
+            .iter()
+            .map(|onset| (onset.time as f64 - predicted, onset.strength as f64))
+            .filter(|(error, _)| error.abs() <= period / 4.0)
+            .max_by(|a, b| {
+                let score =
+                    |(error, strength): (f64, f64)| strength * gaussian(error, period / 8.0);
+                score(*a).total_cmp(&score(*b))
+            });
+        match nearest {
+            Some((error, _)) => predicted + error * PHASE_GAIN,
+            None => predicted,
+        }
+    }
+
+    /// The beat period best supported by the onset intervals
+    fn best_period(&self) -> Option<f64> {
+        let bins = ((self.max_bpm - self.min_bpm) / BPM_STEP) as usize + 1;
+        let mut scores = vec![0.0f64; bins];
+        let longest = MAX_MULTIPLE as f64 * MICROSECONDS_PER_MINUTE / self.min_bpm;
+        let reach = 3.0 * TIMING_SPREAD;
+
+        let onsets: Vec<Onset> = self.onsets.iter().copied().collect();
+        let newest = onsets.last().map_or(0, |onset| onset.time);
+        for (i, a) in onsets.iter().enumerate() {
+            for b in &onsets[i + 1..] {
+                let interval = (b.time - a.time) as f64;
+                if interval > longest + reach {
+                    break;
+                }
+                let age = (newest - b.time) as f64;
+                let weight = (a.strength * b.strength) as f64 * 0.5f64.powf(age / TEMPO_HALF_LIFE);
+                for multiple in 1..=MAX_MULTIPLE {
+                    let k = multiple as f64;
+                    // Candidate periods within reach of interval / k
+                    let fastest = MICROSECONDS_PER_MINUTE * k / (interval + reach);
+                    let slowest = MICROSECONDS_PER_MINUTE * k / (interval - reach).max(1.0);
+                    let low = ((fastest - self.min_bpm) / BPM_STEP).ceil().max(0.0) as usize;
+                    let high =
+                        (((slowest - self.min_bpm) / BPM_STEP).floor()).min(bins as f64 - 1.0);
+                    if high < 0.0 {
+                        continue;
+                    }
+                    for (bin, score) in scores
+                        .iter_mut()
+                        .enumerate()
+                        .take(high as usize + 1)
+                        .skip(low)
+                    {
+                        let period = MICROSECONDS_PER_MINUTE / self.bpm_of(bin);
+                        let error = interval - k * period;
+                        *score += weight / k * gaussian(error, TIMING_SPREAD);
+                    }
+                }
+            }
+        }
+
+        let (bin, score) = scores
+            .iter()
+            .enumerate()
+            .map(|(bin, &score)| (bin, score * self.preference(self.bpm_of(bin))))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        (score > 0.0).then(|| MICROSECONDS_PER_MINUTE / self.bpm_of(bin))
+    }
+
+    fn bpm_of(&self, bin: usize) -> f64 {
+        self.min_bpm + bin as f64 * BPM_STEP
+    }
+
+    /// Weight of a candidate tempo: near 120 BPM, and near the current estimate
+    fn preference(&self, bpm: f64) -> f64 {
+        let octaves = (bpm / PREFERRED_BPM).log2() / PREFERENCE_OCTAVES;
+        let prior = (-0.5 * octaves * octaves).exp();
+        let continuity = self.period.map_or(0.0, |period| {
+            let current = MICROSECONDS_PER_MINUTE / period;
+            gaussian((bpm / current).log2(), 0.05)
+        });
+        prior * (1.0 + continuity)
+    }
+
+    /// Time of a beat of the grid that best fits the onsets, recent ones weighing more
+    fn best_phase(&self, period: f64) -> f64 {
+        let newest = self.onsets.back().map_or(0, |onset| onset.time);
+        (0..PHASE_STEPS)
+            .map(|step| step as f64 * period / PHASE_STEPS as f64)
+            .map(|phase| {
+                let score: f64 = self
+                    .onsets
+                    .iter()
+                    .map(|onset| {
+                        let age = (newest - onset.time) as f64 / WINDOW as f64;
+                        let recency = 1.0 - 0.5 * age;
+                        let offset = grid_offset(onset.time as f64, phase, period);
+                        onset.strength as f64 * recency * gaussian(offset, TIMING_SPREAD)
+                    })
+                    .sum();
+                (phase, score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0.0, |(phase, _)| phase)
+    }
+
+    /// Share of the onset strength within an eighth of a beat of the grid
+    fn grid_agreement(&self, period: f64) -> f32 {
+        let total: f32 = self.onsets.iter().map(|onset| onset.strength).sum();
+        let on_grid: f32 = self
+            .onsets
+            .iter()
+            .filter(|onset| {
+                grid_offset(onset.time as f64, self.phase, period).abs() <= period / 8.0
+            })
+            .map(|onset| onset.strength)
+            .sum();
+        if total > 0.0 {
+            on_grid / total
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Signed distance of a time from the nearest beat of a grid
+fn grid_offset(time: f64, phase: f64, period: f64) -> f64 {
+    let offset = (time - phase).rem_euclid(period);
+    if offset > period / 2.0 {
+        offset - period
+    } else {
+        offset
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-0.5 * (x / sigma).powi(2)).exp()
+}
+
+fn tempo_change(time: u64, period: f64) -> TempoChange {
+    TempoChange {
+        time,
+        tempo: period.round().clamp(MIN_TEMPO, 0xFF_FFFF as f64) as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Onsets on every beat at a tempo, from a start time
+    fn steady(bpm: f64, start: u64, beats: usize) -> Vec<Onset> {
+        let period = MICROSECONDS_PER_MINUTE / bpm;
+        (0..beats)
+            .map(|i| Onset {
+                time: start + (i as f64 * period) as u64,
+                strength: 1.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_steady_tempo() {
+        let tracker = BeatTracker::track(steady(100.0, 1_000_000, 16), 60.0, 200.0);
+        let tempo = tracker.tempo().unwrap();
+        assert!((tempo.bpm - 100.0).abs() < 1.0, "bpm {}", tempo.bpm);
+        assert!(tempo.confidence > 0.9);
+        // Beats land on the onsets
+        let last = *tracker.beats().last().unwrap();
+        assert!((last as i64 - 1_000_000 - 15 * 600_000).abs() < 20_000);
+    }
+
+    #[test]
+    fn test_offbeat_eighths_keep_the_beat() {
+        // Quarter notes with an eighth note between every other pair
+        let mut onsets = steady(90.0, 0, 16);
+        onsets.extend(
+            steady(90.0, 333_333, 8)
+                .into_iter()
+                .step_by(2)
+                .map(|o| Onset { strength: 0.5, ..o }),
+        );
+        onsets.sort_by_key(|o| o.time);
+        let tempo = BeatTracker::track(onsets, 60.0, 200.0).tempo().unwrap();
+        assert!((tempo.bpm - 90.0).abs() < 1.0, "bpm {}", tempo.bpm);
+    }
+
+    #[test]
+    fn test_follows_tempo_change() {
+        let mut onsets = steady(100.0, 0, 12);
+        let start = onsets.last().unwrap().time + 500_000;
+        onsets.extend(steady(120.0, start, 24));
+        let tempo = BeatTracker::track(onsets, 60.0, 200.0).tempo().unwrap();
+        assert!((tempo.bpm - 120.0).abs() < 1.5, "bpm {}", tempo.bpm);
+    }
+
+    #[test]
+    fn test_tempo_map_follows_beats() {
+        let mut tracker = BeatTracker::new(60.0, 200.0);
+        tracker.beats = vec![1_000_000, 1_500_000, 2_000_000, 2_600_000, 3_200_000];
+        let map = tracker.tempo_map(120.0);
+        // Two beats of lead-in at 120 BPM, then a change to 100 BPM
+        assert_eq!(
+            map[0],
+            TempoChange {
+                time: 0,
+                tempo: 500_000
+            }
+        );
+        assert_eq!(map.last().unwrap().tempo, 600_000);
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(BeatTracker::new(60.0, 200.0).tempo_map(96.0).len(), 1);
+    }
+
+    #[test]
+    fn test_stops_after_silence() {
+        let mut tracker = BeatTracker::track(steady(120.0, 0, 8), 60.0, 200.0);
+        assert!(tracker.tempo().is_some());
+        tracker.advance(20_000_000);
+        assert!(tracker.tempo().is_none());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod beat;
+pub mod onset;
+
+pub use beat::{BeatTracker, Onset, Tempo};
+pub use onset::OnsetDetector;
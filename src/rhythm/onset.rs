@@ -0,0 +1,100 @@
+/// Rise of the level over its recent average that counts as an onset, in dB
+const ONSET_RISE_DB: f32 = 6.0;
+
+/// Level below which chunks are treated as silence, in dB
+const SILENCE_DB: f32 = -50.0;
+
+/// Weight of the previous level in the running average
+const LEVEL_SMOOTHING: f32 = 0.5;
+
+/// Rise in dB that gives full onset strength
+const FULL_STRENGTH_RISE_DB: f32 = 24.0;
+
+/// Minimum time between onsets in microseconds
+const MIN_ONSET_INTERVAL: u64 = 50_000;
+
+/// Detects note attacks in audio from sudden rises in level
+///
+/// Works on the same chunks as pitch detection, so the time resolution is
+/// one chunk. Attacks are also detected when the pitch detector cannot
+/// name the note, e.g. for muted strums.
+#[derive(Debug, Clone)]
+pub struct OnsetDetector {
+    /// Running average level in dB
+    level_db: f32,
+    last_onset: Option<u64>,
+}
+
+impl Default for OnsetDetector {
+    fn default() -> Self {
+        Self {
+            level_db: SILENCE_DB,
+            last_onset: None,
+        }
+    }
+}
+
+impl OnsetDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a chunk of samples starting at `time` (microseconds)
+    ///
+    /// Returns the onset strength (0.0 to 1.0) if the chunk starts a note.
+    pub fn process(&mut self, time: u64, samples: &[f32]) -> Option<f32> {
+        if samples.is_empty() {
+            return None;
+        }
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        let level_db = 20.0 * rms.max(1e-6).log10();
+        let rise = level_db - self.level_db;
+        self.level_db = self.level_db * LEVEL_SMOOTHING + level_db * (1.0 - LEVEL_SMOOTHING);
+
+        let rested = self
+            .last_onset
+            .is_none_or(|last| time.saturating_sub(last) >= MIN_ONSET_INTERVAL);
+        if level_db > SILENCE_DB && rise >= ONSET_RISE_DB && rested {
+            self.last_onset = Some(time);
+            Some((rise / FULL_STRENGTH_RISE_DB).clamp(0.25, 1.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(amplitude: f32) -> Vec<f32> {
+        (0..1024)
+            .map(|i| amplitude * (i as f32 * 0.1).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_attacks() {
+        let mut detector = OnsetDetector::new();
+        assert_eq!(detector.process(0, &chunk(0.0)), None);
+        let strength = detector.process(20_000, &chunk(0.5)).unwrap();
+        assert!(strength > 0.5);
+        // A sustained note is not a new onset
+        assert_eq!(detector.process(40_000, &chunk(0.5)), None);
+        assert_eq!(detector.process(60_000, &chunk(0.45)), None);
+    }
+
+    #[test]
+    fn test_minimum_interval() {
+        let mut detector = OnsetDetector::new();
+        assert!(detector.process(0, &chunk(0.5)).is_some());
+        detector.process(10_000, &chunk(0.0));
+        assert!(detector.process(20_000, &chunk(0.5)).is_none());
+        detector.process(40_000, &chunk(0.0));
+        assert!(detector.process(100_000, &chunk(0.5)).is_some());
+    }
+}
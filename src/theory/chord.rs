@@ -0,0 +1,326 @@
+use super::pitch_class_name;
+
+/// Chord quality: the triad or seventh chord the chord is built on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Power,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+    MinorMajor7,
+    Major6,
+    Minor6,
+    Dominant7Sus4,
+}
+
+/// Qualities with their intervals above the root, simplest first
+const TEMPLATES: &[(ChordQuality, &[u8])] = &[
+    (ChordQuality::Major, &[0, 4, 7]),
+    (ChordQuality::Minor, &[0, 3, 7]),
+    (ChordQuality::Power, &[0, 7]),
+    (ChordQuality::Sus4, &[0, 5, 7]),
+    (ChordQuality::Sus2, &[0, 2, 7]),
+    (ChordQuality::Diminished, &[0, 3, 6]),
+    (ChordQuality::Augmented, &[0, 4, 8]),
+    (ChordQuality::Dominant7, &[0, 4, 7, 10]),
+    (ChordQuality::Major7, &[0, 4, 7, 11]),
+    (ChordQuality::Minor7, &[0, 3, 7, 10]),
+    (ChordQuality::Major6, &[0, 4, 7, 9]),
+    (ChordQuality::Minor6, &[0, 3, 7, 9]),
+    (ChordQuality::HalfDiminished7, &[0, 3, 6, 10]),
+    (ChordQuality::Diminished7, &[0, 3, 6, 9]),
+    (ChordQuality::MinorMajor7, &[0, 3, 7, 11]),
+    (ChordQuality::Dominant7Sus4, &[0, 5, 7, 10]),
+];
+
+/// Extension names by interval above the root
+const EXTENSIONS: &[(u8, &str)] = &[
+    (1, "b9"),
+    (2, "9"),
+    (3, "#9"),
+    (5, "11"),
+    (6, "#11"),
+    (8, "b13"),
+    (9, "13"),
+];
+
+impl ChordQuality {
+    /// Chord symbol suffix, e.g. "m7" for a minor seventh
+    pub fn suffix(self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Sus2 => "sus2",
+            ChordQuality::Sus4 => "sus4",
+            ChordQuality::Power => "5",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Diminished7 => "dim7",
+            ChordQuality::MinorMajor7 => "m(maj7)",
+            ChordQuality::Major6 => "6",
+            ChordQuality::Minor6 => "m6",
+            ChordQuality::Dominant7Sus4 => "7sus4",
+        }
+    }
+
+    /// Intervals above the root
+    pub fn intervals(self) -> &'static [u8] {
+        TEMPLATES
+            .iter()
+            .find(|(quality, _)| *quality == self)
+            .map(|(_, intervals)| *intervals)
+            .unwrap_or(&[0])
+    }
+
+    /// Check if the quality includes a seventh
+    fn has_seventh(self) -> bool {
+        self.intervals().iter().any(|&i| i == 10 || i == 11) || self == ChordQuality::Diminished7
+    }
+}
+
+/// A recognized chord
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord {
+    /// Root pitch class (0 = C)
+    pub root: u8,
+    pub quality: ChordQuality,
+    /// Tensions above the chord, e.g. "9" or "#11"
+    pub extensions: Vec<&'static str>,
+    /// Bass pitch class
+    pub bass: u8,
+    /// 0 = root position, 1 = first inversion, ...; 0 also for slash chords over a non-chord bass
+    pub inversion: u8,
+}
+
+impl Chord {
+    /// Recognize the chord formed by a set of MIDI notes; the lowest note is the bass
+    pub fn from_notes(notes: &[u8]) -> Option<Self> {
+        let bass = notes.iter().min()? % 12;
+        let mut pitch_classes = [false; 12];
+        for &note in notes {
+            pitch_classes[(note % 12) as usize] = true;
+        }
+        Self::from_pitch_classes(&pitch_classes, Some(bass))
+    }
+
+    /// Recognize a chord from a chroma vector (energy per pitch class, 0 = C)
+    ///
+    /// Pitch classes with at least a third of the strongest class's energy count
+    /// as chord tones. Without a bass the chord is assumed to be in root position.
+    pub fn from_chroma(chroma: &[f32; 12], bass: Option<u8>) -> Option<Self> {
+        let max = chroma.iter().cloned().fold(0.0f32, f32::max);
+        if max <= 0.0 {
+            return None;
+        }
+        let pitch_classes = chroma.map(|energy| energy >= max / 3.0);
+        Self::from_pitch_classes(&pitch_classes, bass.map(|b| b % 12))
+    }
+
+    /// Find the best-scoring root and quality for a set of pitch classes
+    fn from_pitch_classes(pitch_classes: &[bool; 12], bass: Option<u8>) -> Option<Self> {
+        let mut best: Option<(f32, Chord)> = None;
+
+        for root in (0..12u8).filter(|&pc| pitch_classes[pc as usize]) {
+            let present: Vec<u8> = (0..12u8)
+                .filter(|&i| pitch_classes[((root + i) % 12) as usize])
+                .collect();
+
+            for &(quality, intervals) in TEMPLATES {
+                let Some((score, extensions)) = Self::score(quality, intervals, &present) else {
+                    continue;
+                };
+                let bass = bass.unwrap_or(root);
+                let score = score + if bass == root { 0.3 } else { 0.0 };
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_score, _)| score > *best_score)
+                {
+                    let inversion = Self::inversion(quality, (bass + 12 - root) % 12);
+                    best = Some((
+                        score,
+                        Chord {
+                            root,
+                            quality,
+                            extensions,
+                            bass,
+                            inversion,
+                        },
+                    ));
+                }
+            }
+        }
+
+        best.map(|(_, chord)| chord)
+    }
+
+    /// Score a quality against the intervals present above a root
+    ///
+    /// Every template interval must be present, except that the fifth may be
+    /// omitted from seventh chords with a third (it then scores nothing). Other
+    /// present intervals must be valid tensions and cost a little each, so
+    /// simpler chords win ties.
+    fn score(
+        quality: ChordQuality,
+        intervals: &[u8],
+        present: &[u8],
+    ) -> Option<(f32, Vec<&'static str>)> {
+        let fifth_optional = quality.has_seventh() && present.iter().any(|&i| i == 3 || i == 4);
+        let mut score = 0.0;
+        for &interval in intervals {
+            if present.contains(&interval) {
+                score += 1.0;
+            } else if interval == 7 && fifth_optional {
+                continue;
+            } else {
+                return None;
+            }
+        }
+
+        let has_major_third = intervals.contains(&4);
+        let mut extensions = Vec::new();
+        for &interval in present.iter().filter(|i| !intervals.contains(i)) {
+            let (_, name) = EXTENSIONS.iter().find(|(i, _)| *i == interval)?;
+            // A minor third over a major third is a #9; otherwise it's a different chord
+            if interval == 3 && !has_major_third {
+                return None;
+            }
+            extensions.push(*name);
+            score -= 0.6;
+        }
+        Some((score, extensions))
+    }
+
+    /// Inversion implied by the bass interval above the root
+    fn inversion(quality: ChordQuality, bass_interval: u8) -> u8 {
+        quality
+            .intervals()
+            .iter()
+            .position(|&i| i == bass_interval)
+            .unwrap_or(0) as u8
+    }
+
+    /// Root note name
+    pub fn root_name(&self) -> &'static str {
+        pitch_class_name(self.root)
+    }
+
+    /// Chord symbol, e.g. "C", "Am7", "Cmaj9", "G7(b9)/B"
+    pub fn name(&self) -> String {
+        let mut name = self.root_name().to_string();
+
+        let naturals: Vec<&str> = self
+            .extensions
+            .iter()
+            .copied()
+            .filter(|e| ["9", "11", "13"].contains(e))
+            .collect();
+        let altered: Vec<&str> = self
+            .extensions
+            .iter()
+            .copied()
+            .filter(|e| !naturals.contains(e))
+            .collect();
+
+        let stacked = matches!(
+            self.quality,
+            ChordQuality::Dominant7 | ChordQuality::Major7 | ChordQuality::Minor7
+        );
+        if stacked && !naturals.is_empty() {
+            // Natural tensions stack on the seventh: C7 + 9 = C9, Cmaj7 + 9 + 13 = Cmaj13
+            let highest = naturals.last().copied().unwrap_or("9");
+            name.push_str(&self.quality.suffix().replace('7', highest));
+            if !altered.is_empty() {
+                name.push_str(&format!("({})", altered.join(",")));
+            }
+        } else {
+            name.push_str(self.quality.suffix());
+            if self.quality.has_seventh() {
+                if !self.extensions.is_empty() {
+                    name.push_str(&format!("({})", self.extensions.join(",")));
+                }
+            } else {
+                for extension in &self.extensions {
+                    name.push_str("add");
+                    name.push_str(extension);
+                }
+            }
+        }
+
+        if self.bass != self.root {
+            name.push('/');
+            name.push_str(pitch_class_name(self.bass));
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(notes: &[u8]) -> Option<String> {
+        Chord::from_notes(notes).map(|chord| chord.name())
+    }
+
+    #[test]
+    fn test_triads() {
+        assert_eq!(name(&[48, 52, 55]).as_deref(), Some("C"));
+        assert_eq!(name(&[45, 48, 52]).as_deref(), Some("Am"));
+        assert_eq!(name(&[47, 50, 53]).as_deref(), Some("Bdim"));
+        assert_eq!(name(&[40, 47, 52]).as_deref(), Some("E5"));
+        // Open E major: E2 B2 E3 G#3 B3 E4
+        assert_eq!(name(&[40, 47, 52, 56, 59, 64]).as_deref(), Some("E"));
+    }
+
+    #[test]
+    fn test_sevenths_and_extensions() {
+        assert_eq!(name(&[48, 52, 55, 58]).as_deref(), Some("C7"));
+        assert_eq!(name(&[48, 52, 55, 59]).as_deref(), Some("Cmaj7"));
+        assert_eq!(name(&[40, 43, 47, 50]).as_deref(), Some("Em7"));
+        assert_eq!(name(&[48, 52, 55, 58, 62]).as_deref(), Some("C9"));
+        assert_eq!(name(&[48, 52, 55, 62]).as_deref(), Some("Cadd9"));
+        // Rootless fifth is fine in a seventh chord
+        assert_eq!(name(&[48, 52, 58]).as_deref(), Some("C7"));
+    }
+
+    #[test]
+    fn test_inversions() {
+        let chord = Chord::from_notes(&[40, 43, 48]).unwrap();
+        assert_eq!(chord.name(), "C/E");
+        assert_eq!(chord.inversion, 1);
+        assert_eq!(Chord::from_notes(&[43, 48, 52]).unwrap().inversion, 2);
+        // The same notes with G in the bass read as G6 rather than Em7
+        assert_eq!(name(&[43, 47, 50, 52]).as_deref(), Some("G6"));
+    }
+
+    #[test]
+    fn test_unrecognized() {
+        assert_eq!(name(&[]), None);
+        assert_eq!(name(&[60]), None);
+        assert_eq!(name(&[60, 61]), None);
+    }
+
+    #[test]
+    fn test_from_chroma() {
+        let mut chroma = [0.0; 12];
+        chroma[9] = 1.0; // A
+        chroma[0] = 0.8; // C
+        chroma[4] = 0.6; // E
+        chroma[5] = 0.1; // leakage
+        let chord = Chord::from_chroma(&chroma, None).unwrap();
+        assert_eq!(chord.name(), "Am");
+        assert_eq!(chord.quality, ChordQuality::Minor);
+    }
+}
@@ -0,0 +1,216 @@
+use super::pitch_class_name;
+
+/// Krumhansl-Kessler major key profile, starting at the tonic
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+
+/// Krumhansl-Kessler minor key profile, starting at the tonic
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Minimum accumulated weight before a key is estimated
+const MIN_WEIGHT: f32 = 4.0;
+
+/// Major or minor mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+/// An estimated key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Key {
+    /// Tonic pitch class (0 = C)
+    pub tonic: u8,
+    pub mode: Mode,
+    /// Correlation of the note histogram with the key profile (0.0 to 1.0)
+    pub confidence: f32,
+}
+
+impl Key {
+    pub fn new(tonic: u8, mode: Mode) -> Self {
+        Self {
+            tonic: tonic % 12,
+            mode,
+            confidence: 1.0,
+        }
+    }
+
+    /// Key name, e.g. "A minor"
+    pub fn name(&self) -> String {
+        let mode = match self.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        };
+        format!("{} {}", pitch_class_name(self.tonic), mode)
+    }
+
+    /// Check if two keys have the same tonic and mode, ignoring confidence
+    pub fn same_key(&self, other: &Key) -> bool {
+        self.tonic == other.tonic && self.mode == other.mode
+    }
+
+    /// Number of sharps (positive) or flats (negative) in the key signature
+    pub fn sharps(&self) -> i8 {
+        let major_tonic = match self.mode {
+            Mode::Major => self.tonic,
+            Mode::Minor => (self.tonic + 3) % 12,
+        };
+        // Position on the circle of fifths, preferring flats from Db onwards
+        let fifths = (major_tonic as i8 * 7) % 12;
+        if fifths > 6 {
+            fifths - 12
+        } else {
+            fifths
+        }
+    }
+}
+
+/// Estimates the key from played notes with the Krumhansl-Schmuckler algorithm
+///
+/// Notes are accumulated in a pitch-class histogram that decays over time, so
+/// the estimate follows modulations. The histogram is correlated with the
+/// major and minor profiles rotated to each of the 12 tonics.
+pub struct KeyDetector {
+    histogram: [f32; 12],
+    /// Seconds for a note's weight to halve
+    half_life: f32,
+}
+
+impl KeyDetector {
+    pub fn new(half_life: f32) -> Self {
+        Self {
+            histogram: [0.0; 12],
+            half_life: half_life.max(0.1),
+        }
+    }
+
+    /// Add a note with a weight (e.g. its confidence or duration)
+    pub fn add_note(&mut self, note: u8, weight: f32) {
+        self.histogram[(note % 12) as usize] += weight.max(0.0);
+    }
+
+    /// Decay the histogram by the time elapsed since the last call
+    pub fn decay(&mut self, seconds: f32) {
+        let factor = 0.5f32.powf(seconds.max(0.0) / self.half_life);
+        self.histogram.iter_mut().for_each(|w| *w *= factor);
+    }
+
+    /// Current pitch-class histogram (0 = C)
+    pub fn histogram(&self) -> &[f32; 12] {
+        &self.histogram
+    }
+
+    /// Estimate the key, or `None` until enough notes have been played
+    pub fn estimate(&self) -> Option<Key> {
+        if self.histogram.iter().sum::<f32>() < MIN_WEIGHT {
+            return None;
+        }
+
+        let mut best: Option<Key> = None;
+        for tonic in 0..12u8 {
+            for (mode, profile) in [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)] {
+                let rotated: [f32; 12] =
+                    std::array::from_fn(|pc| profile[(pc + 12 - tonic as usize) % 12]);
+                let correlation = pearson(&self.histogram, &rotated);
+                if best.is_none_or(|key| correlation > key.confidence) {
+                    best = Some(Key {
+                        tonic,
+                        mode,
+                        confidence: correlation,
+                    });
+                }
+            }
+        }
+
+        best.map(|key| Key {
+            confidence: key.confidence.clamp(0.0, 1.0),
+            ..key
+        })
+    }
+
+    /// Forget all played notes
+    pub fn reset(&mut self) {
+        self.histogram = [0.0; 12];
+    }
+}
+
+/// Pearson correlation coefficient of two vectors
+fn pearson(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a <= 0.0 || var_b <= 0.0 {
+        return 0.0;
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(notes: &[u8]) -> Option<Key> {
+        let mut detector = KeyDetector::new(30.0);
+        for &note in notes {
+            detector.add_note(note, 1.0);
+        }
+        detector.estimate()
+    }
+
+    #[test]
+    fn test_needs_enough_notes() {
+        assert_eq!(detect(&[60, 64]), None);
+    }
+
+    #[test]
+    fn test_c_major_scale() {
+        let key = detect(&[60, 62, 64, 65, 67, 69, 71, 72, 67, 64, 60]).unwrap();
+        assert!(key.same_key(&Key::new(0, Mode::Major)));
+        assert!(key.confidence > 0.7);
+    }
+
+    #[test]
+    fn test_a_minor_melody() {
+        // A minor pentatonic lick ending on A
+        let key = detect(&[57, 60, 62, 64, 67, 69, 57, 64, 57, 60, 57]).unwrap();
+        assert!(key.same_key(&Key::new(9, Mode::Minor)));
+    }
+
+    #[test]
+    fn test_decay_follows_modulation() {
+        let mut detector = KeyDetector::new(2.0);
+        for note in [60, 62, 64, 65, 67, 69, 71, 60] {
+            detector.add_note(note, 1.0);
+        }
+        detector.decay(20.0);
+        // E major
+        for note in [64, 66, 68, 69, 71, 73, 75, 64, 71, 68] {
+            detector.add_note(note, 1.0);
+        }
+        assert!(detector
+            .estimate()
+            .unwrap()
+            .same_key(&Key::new(4, Mode::Major)));
+    }
+
+    #[test]
+    fn test_key_signature() {
+        assert_eq!(Key::new(0, Mode::Major).sharps(), 0);
+        assert_eq!(Key::new(4, Mode::Major).sharps(), 4);
+        assert_eq!(Key::new(5, Mode::Major).sharps(), -1);
+        assert_eq!(Key::new(9, Mode::Minor).sharps(), 0);
+        assert_eq!(Key::new(2, Mode::Minor).sharps(), -1);
+        assert_eq!(Key::new(1, Mode::Major).sharps(), -5);
+        assert_eq!(Key::new(6, Mode::Major).sharps(), 6);
+        assert_eq!(Key::new(9, Mode::Minor).name(), "A minor");
+    }
+}
@@ -0,0 +1,16 @@
+pub mod chord;
+pub mod key;
+pub mod scale;
+
+pub use chord::{Chord, ChordQuality};
+pub use key::{Key, KeyDetector, Mode};
+pub use scale::{Scale, SnapDirection};
+
+const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Name of a pitch class (0 = C), using sharps
+pub fn pitch_class_name(pitch_class: u8) -> &'static str {
+    PITCH_CLASS_NAMES[(pitch_class % 12) as usize]
+}
@@ -0,0 +1,214 @@
+use super::{pitch_class_name, Key, Mode};
+use crate::guitar::parse_note_name;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Named scales accepted by `Scale::parse`, as intervals above the tonic
+const PRESET_SCALES: &[(&str, &[u8])] = &[
+    ("major", &[0, 2, 4, 5, 7, 9, 11]),
+    ("ionian", &[0, 2, 4, 5, 7, 9, 11]),
+    ("dorian", &[0, 2, 3, 5, 7, 9, 10]),
+    ("phrygian", &[0, 1, 3, 5, 7, 8, 10]),
+    ("lydian", &[0, 2, 4, 6, 7, 9, 11]),
+    ("mixolydian", &[0, 2, 4, 5, 7, 9, 10]),
+    ("minor", &[0, 2, 3, 5, 7, 8, 10]),
+    ("aeolian", &[0, 2, 3, 5, 7, 8, 10]),
+    ("locrian", &[0, 1, 3, 5, 6, 8, 10]),
+    ("harmonic-minor", &[0, 2, 3, 5, 7, 8, 11]),
+    ("melodic-minor", &[0, 2, 3, 5, 7, 9, 11]),
+    ("major-pentatonic", &[0, 2, 4, 7, 9]),
+    ("minor-pentatonic", &[0, 3, 5, 7, 10]),
+    ("blues", &[0, 3, 5, 6, 7, 10]),
+    ("chromatic", &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+];
+
+/// Which way notes outside the scale are moved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapDirection {
+    /// To the closest scale degree (upwards when both are equally close)
+    #[default]
+    Nearest,
+    Up,
+    Down,
+}
+
+impl FromStr for SnapDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "nearest" => Ok(SnapDirection::Nearest),
+            "up" => Ok(SnapDirection::Up),
+            "down" => Ok(SnapDirection::Down),
+            _ => anyhow::bail!("Unknown snap direction '{}' (use nearest, up or down)", s),
+        }
+    }
+}
+
+/// A scale: a tonic and the pitch classes above it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scale {
+    name: String,
+    tonic: u8,
+    /// Scale membership by interval above the tonic
+    degrees: [bool; 12],
+}
+
+impl Scale {
+    /// Create a scale from a tonic pitch class and intervals above it
+    pub fn new(name: &str, tonic: u8, intervals: &[u8]) -> Self {
+        let mut degrees = [false; 12];
+        degrees[0] = true;
+        for &interval in intervals {
+            degrees[(interval % 12) as usize] = true;
+        }
+        Self {
+            name: name.to_string(),
+            tonic: tonic % 12,
+            degrees,
+        }
+    }
+
+    /// Parse a tonic and scale name (e.g. "A minor-pentatonic") or intervals (e.g. "D 0,2,3,7,9")
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let spec = spec.trim();
+        let (tonic_name, rest) = spec.split_once(char::is_whitespace).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid scale '{}'. Use a tonic and a scale, e.g. \"A minor\" or \"D 0,2,3,7,9\"",
+                spec
+            )
+        })?;
+        // Any octave will do, only the pitch class is used
+        let tonic = parse_note_name(&format!("{}4", tonic_name))
+            .ok_or_else(|| anyhow::anyhow!("Invalid scale tonic '{}'", tonic_name))?
+            % 12;
+
+        let kind = rest.trim().to_lowercase();
+        if let Some((name, intervals)) = PRESET_SCALES.iter().find(|(name, _)| *name == kind) {
+            return Ok(Self::new(name, tonic, intervals));
+        }
+
+        let intervals = kind
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<u8>().ok().filter(|&i| i < 12))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|intervals| !intervals.is_empty())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown scale '{}'. Use a preset ({}) or intervals 0-11 such as \"0,2,3,7,9\"",
+                    rest.trim(),
+                    Self::preset_names().join(", ")
+                )
+            })?;
+        Ok(Self::new("custom", tonic, &intervals))
+    }
+
+    /// The major or natural minor scale of a key
+    pub fn from_key(key: &Key) -> Self {
+        let name = match key.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        };
+        Self::parse(&format!("{} {}", pitch_class_name(key.tonic), name))
+            .expect("major and minor are presets")
+    }
+
+    /// Names of the built-in scales
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESET_SCALES.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Scale name, e.g. "A minor-pentatonic"
+    pub fn name(&self) -> String {
+        format!("{} {}", pitch_class_name(self.tonic), self.name)
+    }
+
+    /// Tonic pitch class (0 = C)
+    pub fn tonic(&self) -> u8 {
+        self.tonic
+    }
+
+    /// Check if a MIDI note is in the scale
+    pub fn contains(&self, note: u8) -> bool {
+        self.degrees[((note + 12 - self.tonic) % 12) as usize]
+    }
+
+    /// Move a note onto the scale
+    pub fn snap(&self, note: u8, direction: SnapDirection) -> u8 {
+        let up = (0..12)
+            .map(|i| note.saturating_add(i))
+            .find(|&n| self.contains(n));
+        let down = (0..12)
+            .map(|i| note.saturating_sub(i))
+            .find(|&n| self.contains(n));
+
+        let snapped = match direction {
+            SnapDirection::Up => up.or(down),
+            SnapDirection::Down => down.or(up),
+            SnapDirection::Nearest => match (up, down) {
+                (Some(up), Some(down)) if note - down < up - note => Some(down),
+                (Some(up), _) => Some(up),
+                (None, down) => down,
+            },
+        };
+        snapped.unwrap_or(note).min(127)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_presets() {
+        let scale = Scale::parse("A minor-pentatonic").unwrap();
+        assert_eq!(scale.name(), "A minor-pentatonic");
+        assert!(scale.contains(57));
+        assert!(scale.contains(60));
+        assert!(!scale.contains(59));
+        assert!(Scale::parse("Bb Dorian").unwrap().contains(61));
+        assert!(Scale::parse("H major").is_err());
+        assert!(Scale::parse("C nonsense").is_err());
+        assert!(Scale::parse("major").is_err());
+    }
+
+    #[test]
+    fn test_parse_custom() {
+        let scale = Scale::parse("D 0,3,7").unwrap();
+        assert!(scale.contains(62));
+        assert!(scale.contains(65));
+        assert!(!scale.contains(64));
+        assert!(Scale::parse("D 0,12").is_err());
+    }
+
+    #[test]
+    fn test_snap_directions() {
+        let scale = Scale::parse("C major").unwrap();
+        assert_eq!(scale.snap(60, SnapDirection::Nearest), 60);
+        assert_eq!(scale.snap(61, SnapDirection::Up), 62);
+        assert_eq!(scale.snap(61, SnapDirection::Down), 60);
+        // Equally close: upwards
+        assert_eq!(scale.snap(61, SnapDirection::Nearest), 62);
+
+        let blues = Scale::parse("A blues").unwrap();
+        // B is a whole tone above A and a semitone below C
+        assert_eq!(blues.snap(59, SnapDirection::Nearest), 60);
+        assert_eq!(blues.snap(59, SnapDirection::Down), 57);
+    }
+
+    #[test]
+    fn test_from_key() {
+        let scale = Scale::from_key(&Key::new(9, Mode::Minor));
+        assert_eq!(scale.name(), "A minor");
+        assert!(scale.contains(67));
+        assert!(!scale.contains(68));
+    }
+
+    #[test]
+    fn test_snap_direction_from_str() {
+        assert_eq!("Up".parse::<SnapDirection>().unwrap(), SnapDirection::Up);
+        assert!("sideways".parse::<SnapDirection>().is_err());
+    }
+}
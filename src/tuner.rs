@@ -0,0 +1,341 @@
+use anyhow::Result;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use log::info;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::audio::AudioInput;
+use crate::config::Config;
+use crate::guitar::Tuning;
+use crate::lifecycle::ProcessorHandle;
+use crate::pitch::PitchDetector;
+use crate::web::MonitoringEvent;
+
+/// Offset in cents within which a note counts as in tune
+pub const IN_TUNE_CENTS: f32 = 3.0;
+
+/// Number of recent readings used for the stability estimate
+const STABILITY_WINDOW: usize = 8;
+
+/// Spread of recent readings (in cents) at which stability reaches zero
+const UNSTABLE_CENTS: f32 = 10.0;
+
+/// A single tuner reading
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunerReading {
+    pub frequency: f32,
+    /// Note the reading is measured against (nearest note, or the target string's note)
+    pub note: u8,
+    pub note_name: String,
+    pub target_frequency: f32,
+    /// Offset from the note in cents, positive = sharp
+    pub cents: f32,
+    /// 0.0 (wobbling) to 1.0 (steady), from the spread of recent readings
+    pub stability: f32,
+    /// String being tuned in guided mode (0 = highest-pitched string)
+    pub string: Option<usize>,
+}
+
+impl TunerReading {
+    /// Check if the reading is within `IN_TUNE_CENTS` of the note
+    pub fn in_tune(&self) -> bool {
+        self.cents.abs() <= IN_TUNE_CENTS
+    }
+
+    /// Text needle for terminals, e.g. `[-----|--*--]` (±50 cents full scale)
+    pub fn meter(&self, width: usize) -> String {
+        let width = width.max(3) | 1;
+        let center = width / 2;
+        let offset = (self.cents.clamp(-50.0, 50.0) / 50.0 * center as f32).round() as isize;
+        let needle = (center as isize + offset) as usize;
+        let bar: String = (0..width)
+            .map(|i| match i {
+                i if i == needle => '*',
+                i if i == center => '|',
+                _ => '-',
+            })
+            .collect();
+        format!("[{}]", bar)
+    }
+}
+
+/// Turns detected frequencies into tuner readings
+///
+/// In chromatic mode readings are against the nearest note. In guided mode
+/// they are against an open string of a tuning: a selected string, or the
+/// string closest to the played pitch.
+pub struct Tuner {
+    tuning: Option<Tuning>,
+    target_string: Option<usize>,
+    recent_cents: VecDeque<f32>,
+    last_note: Option<u8>,
+}
+
+impl Tuner {
+    /// Create a chromatic tuner
+    pub fn chromatic() -> Self {
+        Self {
+            tuning: None,
+            target_string: None,
+            recent_cents: VecDeque::with_capacity(STABILITY_WINDOW),
+            last_note: None,
+        }
+    }
+
+    /// Create a tuner guiding through the open strings of a tuning
+    pub fn guided(tuning: Tuning) -> Self {
+        Self {
+            tuning: Some(tuning),
+            ..Self::chromatic()
+        }
+    }
+
+    /// Select the string to tune (0 = highest-pitched string), or `None` to follow the played pitch
+    pub fn set_target_string(&mut self, string: Option<usize>) {
+        self.target_string = string;
+        self.reset();
+    }
+
+    /// Take a reading of a detected frequency
+    pub fn update(&mut self, frequency: f32) -> TunerReading {
+        let (note, string) = self.target(frequency);
+        let target_frequency = PitchDetector::midi_to_frequency(note);
+        let cents = 1200.0 * (frequency / target_frequency).log2();
+
+        if self.last_note != Some(note) {
+            self.recent_cents.clear();
+            self.last_note = Some(note);
+        }
+        if self.recent_cents.len() == STABILITY_WINDOW {
+            self.recent_cents.pop_front();
+        }
+        self.recent_cents.push_back(cents);
+
+        TunerReading {
+            frequency,
+            note,
+            note_name: PitchDetector::midi_to_note_name(note),
+            target_frequency,
+            cents,
+            stability: self.stability(),
+            string,
+        }
+    }
+
+    /// Forget recent readings, e.g. when the string stops sounding
+    pub fn reset(&mut self) {
+        self.recent_cents.clear();
+        self.last_note = None;
+    }
+
+    /// Note (and string in guided mode) a frequency is measured against
+    fn target(&self, frequency: f32) -> (u8, Option<usize>) {
+        let Some(tuning) = &self.tuning else {
+            return (PitchDetector::frequency_to_midi(frequency), None);
+        };
+
+        let string = self.target_string.or_else(|| {
+            let played = 69.0 + 12.0 * (frequency / 440.0).log2();
+            (0..tuning.string_count()).min_by(|&a, &b| {
+                let distance = |s| (tuning.open_note(s).unwrap_or(0) as f32 - played).abs();
+                distance(a).total_cmp(&distance(b))
+            })
+        });
+        match string.and_then(|s| tuning.open_note(s).map(|note| (note, s))) {
+            Some((note, string)) => (note, Some(string)),
+            None => (PitchDetector::frequency_to_midi(frequency), None),
+        }
+    }
+
+    /// Stability from the standard deviation of recent readings
+    fn stability(&self) -> f32 {
+        if self.recent_cents.len() < 2 {
+            return 0.0;
+        }
+        let count = self.recent_cents.len() as f32;
+        let mean = self.recent_cents.iter().sum::<f32>() / count;
+        let variance = self
+            .recent_cents
+            .iter()
+            .map(|c| (c - mean).powi(2))
+            .sum::<f32>()
+            / count;
+        (1.0 - variance.sqrt() / UNSTABLE_CENTS).clamp(0.0, 1.0)
+    }
+}
+
+/// Runs the tuner on live audio input, streaming readings and web events
+pub struct TunerProcessor {
+    audio_input: AudioInput,
+    pitch_detector: PitchDetector,
+    tuner: Tuner,
+    buffer_size: usize,
+    handle: ProcessorHandle,
+    reading_tx: Option<Sender<TunerReading>>,
+    web_event_tx: Option<broadcast::Sender<MonitoringEvent>>,
+}
+
+impl TunerProcessor {
+    pub fn new(config: &Config, tuner: Tuner) -> Result<Self> {
+        let audio_input = AudioInput::new()?;
+        let pitch_detector = PitchDetector::new(
+            audio_input.sample_rate(),
+            config.buffer_size,
+            config.pitch_threshold,
+        );
+        Ok(Self {
+            audio_input,
+            pitch_detector,
+            tuner,
+            buffer_size: config.buffer_size,
+            handle: ProcessorHandle::default(),
+            reading_tx: None,
+            web_event_tx: None,
+        })
+    }
+
+    /// Get a handle to shut down the tuner
+    pub fn handle(&self) -> ProcessorHandle {
+        self.handle.clone()
+    }
+
+    /// Set the sender readings are streamed to, e.g. for a terminal display
+    pub fn set_reading_sender(&mut self, tx: Sender<TunerReading>) {
+        self.reading_tx = Some(tx);
+    }
+
+    /// Set the web event sender for broadcasting tuner readings
+    pub fn set_web_event_sender(&mut self, tx: broadcast::Sender<MonitoringEvent>) {
+        self.web_event_tx = Some(tx);
+    }
+
+    /// Process audio until shut down through the tuner's handle or the input ends
+    pub fn start(&mut self) -> Result<()> {
+        info!("Starting tuner...");
+        let (tx, rx) = bounded(10);
+        let _stream = self.audio_input.start_stream(tx)?;
+        self.process_audio_stream(rx)
+    }
+
+    /// Analyze overlapping windows so readings arrive several times per buffer
+    fn process_audio_stream(&mut self, rx: Receiver<Vec<f32>>) -> Result<()> {
+        let hop = (self.buffer_size / 4).max(1);
+        let mut window: VecDeque<f32> = VecDeque::with_capacity(self.buffer_size + hop);
+        let mut pending = 0;
+
+        while !self.handle.is_shutdown() {
+            let samples = match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(samples) => samples,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    info!("End of input");
+                    return Ok(());
+                }
+            };
+            for sample in samples {
+                window.push_back(sample);
+                if window.len() > self.buffer_size {
+                    window.pop_front();
+                }
+                pending += 1;
+                if pending >= hop && window.len() == self.buffer_size {
+                    pending = 0;
+                    let chunk: Vec<f32> = window.iter().copied().collect();
+                    self.process_window(&chunk);
+                }
+            }
+        }
+
+        info!("Shutdown requested");
+        Ok(())
+    }
+
+    fn process_window(&mut self, samples: &[f32]) {
+        let Some((frequency, _)) = self.pitch_detector.detect_pitch_with_confidence(samples) else {
+            self.tuner.reset();
+            return;
+        };
+        let reading = self.tuner.update(frequency);
+
+        if let Some(tx) = &self.web_event_tx {
+            let _ = tx.send(MonitoringEvent::Tuner {
+                in_tune: reading.in_tune(),
+                frequency: reading.frequency,
+                note: reading.note,
+                note_name: reading.note_name.clone(),
+                target_frequency: reading.target_frequency,
+                cents: reading.cents,
+                stability: reading.stability,
+                string: reading.string,
+            });
+        }
+        if let Some(tx) = &self.reading_tx {
+            let _ = tx.send(reading);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chromatic_reading() {
+        let mut tuner = Tuner::chromatic();
+        let reading = tuner.update(440.0);
+        assert_eq!(reading.note, 69);
+        assert_eq!(reading.note_name, "A4");
+        assert!(reading.cents.abs() < 0.01);
+        assert!(reading.in_tune());
+
+        // 10 cents sharp of A4
+        let reading = tuner.update(440.0 * 2f32.powf(10.0 / 1200.0));
+        assert!((reading.cents - 10.0).abs() < 0.01);
+        assert!(!reading.in_tune());
+    }
+
+    #[test]
+    fn test_guided_nearest_string() {
+        let mut tuner = Tuner::guided(Tuning::standard());
+        // Low E string tuned a semitone flat still reads against E2
+        let reading = tuner.update(PitchDetector::midi_to_frequency(39));
+        assert_eq!(reading.string, Some(5));
+        assert_eq!(reading.note, 40);
+        assert!((reading.cents + 100.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_guided_selected_string() {
+        let mut tuner = Tuner::guided(Tuning::parse("drop-d").unwrap());
+        tuner.set_target_string(Some(5));
+        let reading = tuner.update(PitchDetector::midi_to_frequency(40));
+        assert_eq!(reading.note, 38);
+        assert!((reading.cents - 200.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_stability() {
+        let mut tuner = Tuner::chromatic();
+        for _ in 0..STABILITY_WINDOW {
+            tuner.update(440.0);
+        }
+        assert!(tuner.update(440.0).stability > 0.99);
+
+        let mut tuner = Tuner::chromatic();
+        let mut reading = tuner.update(440.0);
+        for i in 0..STABILITY_WINDOW {
+            let cents = if i % 2 == 0 { 20.0 } else { -20.0 };
+            reading = tuner.update(440.0 * 2f32.powf(cents / 1200.0));
+        }
+        assert!(reading.stability < 0.1);
+    }
+
+    #[test]
+    fn test_meter() {
+        let mut tuner = Tuner::chromatic();
+        assert_eq!(tuner.update(440.0).meter(11), "[-----*-----]");
+        let reading = tuner.update(440.0 * 2f32.powf(40.0 / 1200.0));
+        assert_eq!(reading.meter(11), "[-----|---*-]");
+    }
+}
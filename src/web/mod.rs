@@ -1,3 +1,3 @@
 pub mod server;
 
-pub use server::{MonitoringEvent, WebServer};
+pub use server::{MonitoringEvent, ShutdownHandle, WebServer};
@@ -9,7 +9,9 @@ use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, watch};
+
+use crate::guitar::TabNote;
 
 /// Events that can be sent to the web UI for monitoring
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +28,63 @@ pub enum MonitoringEvent {
     NoteOff { note: u8, note_name: String },
     /// Pitch bend was applied
     PitchBend { note: u8, bend_value: f32 },
+    /// A note was assigned to a guitar string in per-string channel mode
+    StringAssignment {
+        note: u8,
+        /// String index, 0 = highest-pitched string
+        string: usize,
+        fret: u8,
+        /// MIDI channel (0-based)
+        channel: u8,
+    },
+    /// A chord was recognized from the sounding notes
+    Chord {
+        /// Chord symbol, e.g. "Am7/G"
+        name: String,
+        root: String,
+        quality: String,
+        extensions: Vec<String>,
+        bass: String,
+        /// 0 = root position, 1 = first inversion, ...
+        inversion: u8,
+        notes: Vec<u8>,
+    },
+    /// Inferred string/fret positions of the notes of an onset
+    Tab { notes: Vec<TabNote> },
+    /// The estimated key changed
+    Key {
+        /// Key name, e.g. "A minor"
+        name: String,
+        tonic: String,
+        /// "major" or "minor"
+        mode: String,
+        confidence: f32,
+        /// Sharps (positive) or flats (negative) in the key signature
+        sharps: i8,
+    },
+    /// The tempo estimate at a tracked beat
+    Tempo {
+        bpm: f32,
+        /// Share of recent note attacks on the beat grid (0.0 to 1.0)
+        confidence: f32,
+        /// Number of beats tracked so far
+        beat: usize,
+    },
+    /// A tuner reading
+    Tuner {
+        frequency: f32,
+        /// Note being tuned to (nearest note, or the target string's note)
+        note: u8,
+        note_name: String,
+        target_frequency: f32,
+        /// Offset in cents, positive = sharp
+        cents: f32,
+        /// 0.0 (wobbling) to 1.0 (steady)
+        stability: f32,
+        /// String being tuned in guided mode, 0 = highest-pitched string
+        string: Option<usize>,
+        in_tune: bool,
+    },
     /// System status update
     Status { message: String },
     /// Recording started/stopped
@@ -36,13 +95,45 @@ pub enum MonitoringEvent {
 pub struct WebServer {
     event_tx: broadcast::Sender<MonitoringEvent>,
     port: u16,
+    shutdown_tx: Arc<watch::Sender<bool>>,
+}
+
+/// Stops a running `WebServer`
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    /// Close the open WebSocket connections and stop accepting new ones
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// State shared by the request handlers
+#[derive(Clone)]
+struct ServerState {
+    event_tx: broadcast::Sender<MonitoringEvent>,
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+/// Resolve once a shutdown has been requested
+async fn shutdown_requested(mut shutdown_rx: watch::Receiver<bool>) {
+    // An error means the handle is gone, so no shutdown can come any more
+    if shutdown_rx.wait_for(|&shutdown| shutdown).await.is_err() {
+        std::future::pending::<()>().await;
+    }
 }
 
 impl WebServer {
     /// Create a new web server
     pub fn new(port: u16) -> Self {
         let (event_tx, _) = broadcast::channel(100);
-        Self { event_tx, port }
+        let (shutdown_tx, _) = watch::channel(false);
+        Self {
+            event_tx,
+            port,
+            shutdown_tx: Arc::new(shutdown_tx),
+        }
     }
 
     /// Get a sender for broadcasting monitoring events
@@ -50,20 +141,34 @@ impl WebServer {
         self.event_tx.clone()
     }
 
+    /// Get a handle that shuts the server down
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown_tx.clone())
+    }
+
     /// Start the web server (runs in the background)
+    ///
+    /// Returns once shut down through a `ShutdownHandle`.
     pub async fn start(self) -> Result<()> {
         let addr = SocketAddr::from(([127, 0, 0, 1], self.port));
         info!("Starting web server on http://{}", addr);
 
         let app = Router::new()
             .route("/", get(index_handler))
+            .route("/tuner", get(tuner_handler))
             .route("/ws", get(ws_handler))
-            .with_state(Arc::new(self.event_tx));
+            .with_state(ServerState {
+                event_tx: self.event_tx,
+                shutdown_rx: self.shutdown_tx.subscribe(),
+            });
 
         let listener = tokio::net::TcpListener::bind(&addr).await?;
         info!("Web UI available at http://{}", addr);
 
-        axum::serve(listener, app).await?;
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_requested(self.shutdown_tx.subscribe()))
+            .await?;
+        info!("Web server stopped");
 
         Ok(())
     }
@@ -74,16 +179,21 @@ async fn index_handler() -> impl IntoResponse {
     Html(include_str!("../../static/index.html"))
 }
 
+/// Serve the tuner page
+async fn tuner_handler() -> impl IntoResponse {
+    Html(include_str!("../../static/tuner.html"))
+}
+
 /// WebSocket handler for real-time monitoring
 async fn ws_handler(
     ws: WebSocketUpgrade,
-    axum::extract::State(event_tx): axum::extract::State<Arc<broadcast::Sender<MonitoringEvent>>>,
+    axum::extract::State(state): axum::extract::State<ServerState>,
 ) -> Response {
-    ws.on_upgrade(|socket| handle_socket(socket, event_tx))
+    ws.on_upgrade(|socket| handle_socket(socket, state))
 }
 
 /// Handle WebSocket connection
-async fn handle_socket(mut socket: WebSocket, event_tx: Arc<broadcast::Sender<MonitoringEvent>>) {
+async fn handle_socket(mut socket: WebSocket, state: ServerState) {
     debug!("WebSocket connection established");
 
     // Send initial status
@@ -95,10 +205,24 @@ async fn handle_socket(mut socket: WebSocket, event_tx: Arc<broadcast::Sender<Mo
     }
 
     // Subscribe to events
-    let mut rx = event_tx.subscribe();
+    let mut rx = state.event_tx.subscribe();
+    let shutdown = shutdown_requested(state.shutdown_rx);
+    tokio::pin!(shutdown);
 
-    // Forward events to the WebSocket
-    while let Ok(event) = rx.recv().await {
+    // Forward events to the WebSocket until the server shuts down
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut shutdown => {
+                let _ = socket.send(Message::Close(None)).await;
+                debug!("WebSocket connection closed for shutdown");
+                break;
+            }
+        };
         if let Ok(json) = serde_json::to_string(&event) {
             if socket.send(Message::Text(json)).await.is_err() {
                 debug!("WebSocket connection closed");
@@ -118,6 +242,18 @@ mod tests {
         assert!(server.port == 8080);
     }
 
+    #[tokio::test]
+    async fn test_shutdown_handle() {
+        let server = WebServer::new(0);
+        let handle = server.shutdown_handle();
+        let shutdown = shutdown_requested(server.shutdown_tx.subscribe());
+
+        handle.clone().shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(1), shutdown)
+            .await
+            .expect("shutdown was not signalled");
+    }
+
     #[test]
     fn test_event_serialization() {
         let event = MonitoringEvent::NoteOn {
@@ -1,4 +1,5 @@
 use hound::{WavSpec, WavWriter};
+use instrument_to_midi::audio::Pluck;
 use std::f32::consts::PI;
 use std::path::PathBuf;
 
@@ -33,7 +34,7 @@ pub fn generate_test_audio(
     path
 }
 
-/// Generate a guitar-like plucked note using Karplus-Strong algorithm
+/// Generate a guitar-like plucked note with the crate's Karplus-Strong string
 #[allow(dead_code)]
 pub fn generate_guitar_note(
     frequency: f32,
@@ -49,30 +50,15 @@ pub fn generate_guitar_note(
     };
 
     let num_samples = (sample_rate as f32 * duration_secs) as usize;
-    let period = (sample_rate as f32 / frequency) as usize;
     let amplitude = i16::MAX as f32 * 0.5;
 
     let path = PathBuf::from(filename);
     let mut writer = WavWriter::create(&path, spec).unwrap();
 
-    // Initialize delay line with noise
-    let mut delay_line: Vec<f32> = (0..period)
-        .map(|_| (rand::random::<f32>() - 0.5) * 2.0)
-        .collect();
-
-    let decay = 0.996; // Decay factor for realistic sound
-    let mut index = 0;
-
+    let mut string = Pluck::new(frequency as f64, sample_rate as f64, rand::random());
     for _ in 0..num_samples {
-        let current = delay_line[index];
-        let next = delay_line[(index + 1) % period];
-        let new_sample = (current + next) * 0.5 * decay;
-
-        delay_line[index] = new_sample;
-        let sample_int = (new_sample * amplitude) as i16;
+        let sample_int = (string.next(1.0) * amplitude) as i16;
         writer.write_sample(sample_int).unwrap();
-
-        index = (index + 1) % period;
     }
 
     writer.finalize().unwrap();
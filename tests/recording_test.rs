@@ -83,3 +83,297 @@ fn test_midi_file_creation() {
     // Clean up
     fs::remove_file(&test_file).unwrap();
 }
+
+#[test]
+fn test_saved_recording_exports_tablature() {
+    use instrument_to_midi::config::Config;
+    use instrument_to_midi::midi::MidiRecorder;
+    use instrument_to_midi::notation::{self, ExportFormat, Take};
+
+    let mut recorder = MidiRecorder::new();
+    recorder.start();
+    recorder.record_note_on(40, 80);
+    thread::sleep(Duration::from_millis(250));
+    recorder.record_note_off(40);
+    recorder.record_note_on(45, 80);
+    thread::sleep(Duration::from_millis(250));
+    recorder.record_note_off(45);
+    recorder.stop();
+
+    let test_file = env::temp_dir().join("test_export_tab.mid");
+    recorder.save(&test_file).unwrap();
+
+    // The saved file loads back into the same notes
+    let take = Take::load(&test_file).unwrap();
+    assert_eq!(take.notes.len(), 2);
+    assert_eq!(take.bpm(), 120.0);
+    assert_eq!(
+        take.notes.iter().map(|n| n.note).collect::<Vec<_>>(),
+        vec![40, 45]
+    );
+
+    let config = Config::default();
+    let ascii = notation::export(&take, ExportFormat::AsciiTab, &config, 4).unwrap();
+    let low_e = ascii.lines().find(|line| line.starts_with("E|")).unwrap();
+    assert!(low_e.starts_with("E|0"));
+    assert!(ascii.lines().any(|line| line.starts_with("A|----0")));
+
+    let xml = notation::export(&take, ExportFormat::GuitarPro, &config, 4).unwrap();
+    assert!(xml.contains("<technical><string>5</string><fret>0</fret></technical>"));
+
+    fs::remove_file(&test_file).unwrap();
+}
+
+#[test]
+fn test_saved_recording_exports_notation() {
+    use instrument_to_midi::config::Config;
+    use instrument_to_midi::midi::MidiRecorder;
+    use instrument_to_midi::notation::{self, ExportFormat, Take};
+
+    let mut recorder = MidiRecorder::new();
+    recorder.start();
+    recorder.record_key_signature(2, false);
+    recorder.record_note_on(66, 80);
+    thread::sleep(Duration::from_millis(250));
+    recorder.record_note_off(66);
+    recorder.stop();
+
+    let test_file = env::temp_dir().join("test_export_notation.mid");
+    recorder.save(&test_file).unwrap();
+
+    let take = Take::load(&test_file).unwrap();
+    assert_eq!(take.key_signature.map(|k| k.fifths), Some(2));
+
+    let xml = notation::export(&take, ExportFormat::MusicXml, &Config::default(), 4).unwrap();
+    assert!(xml.contains("<fifths>2</fifths>"));
+    assert!(xml.contains("<pitch><step>F</step><alter>1</alter><octave>4</octave></pitch>"));
+
+    fs::remove_file(&test_file).unwrap();
+}
+
+#[test]
+fn test_multi_track_recording_loads_played_notes() {
+    use instrument_to_midi::midi::{MidiRecorder, TrackLayout, RAW_DETECTION_TRACK};
+    use instrument_to_midi::notation::Take;
+
+    let mut recorder = MidiRecorder::new();
+    recorder.set_layout(TrackLayout::Detection);
+    let raw = recorder.add_track(RAW_DETECTION_TRACK);
+    recorder.start();
+    recorder.record_key_signature(-1, false);
+    recorder.set_track(raw);
+    recorder.record_note_on(61, 80);
+    recorder.set_track(0);
+    recorder.record_note_on(62, 80);
+    thread::sleep(Duration::from_millis(100));
+    recorder.record_note_off(62);
+    recorder.set_track(raw);
+    recorder.record_note_off(61);
+    recorder.stop();
+
+    let test_file = env::temp_dir().join("test_multi_track.mid");
+    recorder.save(&test_file).unwrap();
+
+    // The conductor track's key signature applies, the raw detection is skipped
+    let take = Take::load(&test_file).unwrap();
+    assert_eq!(take.key_signature.map(|k| k.fifths), Some(-1));
+    assert_eq!(take.notes.len(), 1);
+    assert_eq!(take.notes[0].note, 62);
+    assert!(take.notes[0].end > take.notes[0].start);
+
+    fs::remove_file(&test_file).unwrap();
+}
+
+#[test]
+fn test_clean_saved_recording() {
+    use instrument_to_midi::config::Config;
+    use instrument_to_midi::midi::{Cleanup, MidiRecorder};
+    use instrument_to_midi::notation::Take;
+
+    // A note broken into two fragments by a dropout, then a blip
+    let mut recorder = MidiRecorder::new();
+    recorder.start();
+    recorder.record_note_on(64, 80);
+    thread::sleep(Duration::from_millis(60));
+    recorder.record_note_off(64);
+    recorder.record_note_on(64, 70);
+    thread::sleep(Duration::from_millis(60));
+    recorder.record_note_off(64);
+    recorder.record_note_on(65, 80);
+    recorder.record_note_off(65);
+    recorder.stop();
+
+    let test_file = env::temp_dir().join("test_clean_input.mid");
+    let clean_file = env::temp_dir().join("test_clean_output.mid");
+    recorder.save(&test_file).unwrap();
+
+    let config = Config {
+        cleanup_grid: 16,
+        ..Default::default()
+    };
+    let notes = Cleanup::from_config(&config)
+        .apply_file(&test_file, &clean_file)
+        .unwrap();
+    assert_eq!(notes, 1);
+
+    let take = Take::load(&clean_file).unwrap();
+    assert_eq!(take.notes.len(), 1);
+    assert_eq!(take.notes[0].note, 64);
+    assert_eq!(take.notes[0].start, 0);
+    assert!(take.notes[0].end >= 100_000);
+    // The input is left unchanged
+    assert_eq!(Take::load(&test_file).unwrap().notes.len(), 3);
+
+    fs::remove_file(&test_file).unwrap();
+    fs::remove_file(&clean_file).unwrap();
+}
+
+#[test]
+fn test_recover_interrupted_recording() {
+    use instrument_to_midi::midi::{journal_path, MidiRecorder};
+    use instrument_to_midi::notation::Take;
+
+    let test_file = env::temp_dir().join("test_interrupted.mid");
+    let journal = journal_path(&test_file);
+
+    // A session that is killed before it saves
+    {
+        let mut recorder = MidiRecorder::new();
+        recorder.set_journal(Some(journal.clone()));
+        recorder.start();
+        recorder.record_note_on(64, 80);
+        thread::sleep(Duration::from_millis(20));
+        recorder.record_note_off(64);
+        recorder.record_note_on(67, 80);
+        // Killed once the journal writer has caught up
+        recorder.sync_journal();
+        std::mem::forget(recorder);
+    }
+
+    let recorder = MidiRecorder::recover(&journal).unwrap();
+    assert_eq!(recorder.event_count(), 3);
+    recorder.save(&test_file).unwrap();
+
+    // The note still sounding when the session ended is closed
+    let take = Take::load(&test_file).unwrap();
+    let notes: Vec<u8> = take.notes.iter().map(|note| note.note).collect();
+    assert_eq!(notes, vec![64, 67]);
+
+    fs::remove_file(&test_file).unwrap();
+    fs::remove_file(&journal).unwrap();
+}
+
+#[test]
+fn test_recover_takes() {
+    use instrument_to_midi::midi::{journal_path, MidiRecorder, TakeStorage};
+    use midly::{MetaMessage, Smf, TrackEventKind};
+
+    let test_file = env::temp_dir().join("test_recover_takes.mid");
+    let journal = journal_path(&test_file);
+
+    // Two takes kept as tracks, then the session is killed
+    {
+        let mut recorder = MidiRecorder::new();
+        recorder.set_journal(Some(journal.clone()));
+        recorder.set_take_storage(TakeStorage::Tracks);
+        recorder.start();
+        recorder.record_note_on(64, 80);
+        recorder.record_note_off(64);
+        recorder.disarm();
+        recorder.arm();
+        recorder.record_note_on(67, 80);
+        recorder.record_note_off(67);
+        // Killed once the journal writer has caught up
+        recorder.sync_journal();
+        std::mem::forget(recorder);
+    }
+
+    let recorder = MidiRecorder::recover(&journal).unwrap();
+    assert_eq!(recorder.take_number(), 2);
+    recorder.save(&test_file).unwrap();
+
+    let data = fs::read(&test_file).unwrap();
+    let smf = Smf::parse(&data).unwrap();
+    let names: Vec<&[u8]> = smf
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            track.iter().find_map(|event| match event.kind {
+                TrackEventKind::Meta(MetaMessage::TrackName(name)) => Some(name),
+                _ => None,
+            })
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![&b"Conductor"[..], b"Guitar (Take 1)", b"Guitar (Take 2)"]
+    );
+
+    fs::remove_file(&test_file).unwrap();
+    fs::remove_file(&journal).unwrap();
+}
+
+#[test]
+fn test_audio_capture_lines_up_with_each_take() {
+    use instrument_to_midi::audio::{audio_capture_path, AudioCapture, SampleClock};
+    use instrument_to_midi::midi::{take_path, MidiRecorder, TakeStorage};
+    use instrument_to_midi::notation::Take;
+
+    const SAMPLE_RATE: u32 = 48_000;
+    fn feed(clock: &mut SampleClock, capture: &AudioCapture, samples: &[f32]) {
+        capture.write(samples);
+        clock.advance(samples.len());
+    }
+
+    let test_file = env::temp_dir().join("test_take_audio.mid");
+    let mut clock = SampleClock::new(SAMPLE_RATE, 1);
+    let mut recorder = MidiRecorder::new();
+    recorder.set_take_storage(TakeStorage::Files);
+    recorder.disarm();
+    recorder.start();
+
+    // Half a second disarmed, then two takes with a click and a note at the click
+    let silence = |seconds: f32| vec![0.0f32; (SAMPLE_RATE as f32 * seconds) as usize];
+    clock.advance(silence(0.5).len());
+    for (key, lead_in) in [(60, 0.25), (62, 0.1)] {
+        recorder.arm_at(clock.time_at(clock.samples()));
+        let path = audio_capture_path(&take_path(&test_file, recorder.take_number()));
+        let capture = AudioCapture::create(&path, SAMPLE_RATE, 1).unwrap();
+        feed(&mut clock, &capture, &silence(lead_in));
+        recorder.set_time(Some(clock.time_at(clock.samples())));
+        recorder.record_note_on(key, 80);
+        feed(&mut clock, &capture, &[1.0]);
+        feed(&mut clock, &capture, &silence(0.25));
+        recorder.set_time(Some(clock.time_at(clock.samples())));
+        recorder.record_note_off(key);
+        recorder.disarm();
+        capture.finish().unwrap();
+        clock.advance(silence(0.3).len());
+    }
+    recorder.stop();
+    recorder.save(&test_file).unwrap();
+
+    for (take, key, lead_in) in [(1, 60, 0.25), (2, 62, 0.1)] {
+        let midi_path = take_path(&test_file, take);
+        let wav_path = audio_capture_path(&midi_path);
+        let note = Take::load(&midi_path).unwrap().notes[0];
+        assert_eq!(note.note, key);
+
+        let mut reader = hound::WavReader::open(&wav_path).unwrap();
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        let click = samples.iter().position(|&sample| sample == 1.0).unwrap();
+        assert_eq!(click, (SAMPLE_RATE as f32 * lead_in) as usize);
+        // The note sits on the click, within the file's tick resolution (about 1ms)
+        let note_frame = note.start as f64 * SAMPLE_RATE as f64 / 1_000_000.0;
+        assert!(
+            (note_frame - click as f64).abs() < SAMPLE_RATE as f64 / 1000.0,
+            "take {}: note at frame {:.0}, click at frame {}",
+            take,
+            note_frame,
+            click
+        );
+
+        fs::remove_file(&midi_path).unwrap();
+        fs::remove_file(&wav_path).unwrap();
+    }
+}